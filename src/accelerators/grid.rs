@@ -1,8 +1,10 @@
 use crate::accelerators::{HasPosition, Accelerator};
 use crate::util::*;
+use crate::domain::Domain;
 use cgmath::{InnerSpace};
 
 pub struct Grid<'a, T> {
+    domain: Domain,
     width: f32,
     height: f32,
     bin_size: f32,
@@ -27,9 +29,17 @@ impl<'a, T> Accelerator for Grid<'a, T> where T: HasPosition {
 
 impl<'a, T> Grid<'a, T> where T: HasPosition {
     pub fn new(width: f32, height: f32, bin_size: f32, items: &'a [T]) -> Self {
-        let cells = Grid::<T>::construct_grid(width, height, bin_size, items);
+        Grid::with_domain(Domain::new(width, height), bin_size, items)
+    }
+
+    // Along the periodic axes of `domain`, neighbours are also found across
+    // the seam, and distances use the closest image
+    pub fn with_domain(domain: Domain, bin_size: f32, items: &'a [T]) -> Self {
+        let (width, height) = (domain.width, domain.height);
+        let cells = Grid::<T>::construct_grid(&domain, bin_size, items);
 
         Grid{
+            domain,
             width: width,
             height: height,
             bin_size: bin_size,
@@ -42,24 +52,18 @@ impl<'a, T> Grid<'a, T> where T: HasPosition {
     // `r`. If `filter_idx` is provided, that item is excluded in the returned indices
     fn nearest(&self, pos: Vector2f, r: f32, filter_idx: Option<usize>) -> Vec<usize> {
         let cols = (self.width / self.bin_size).ceil() as usize;
-        let _rows = (self.height / self.bin_size).ceil() as usize;
+        let rows = (self.height / self.bin_size).ceil() as usize;
 
         // We save some time on allocation by preallocating space.
         // Maybe also compute this heurestic on the fly after seeing a few samples.
         let mut neighbours = Vec::with_capacity(24);
 
-        let x0 = clamp_f32(pos.x - r, 0.0, self.width  as f32 - 1e-2);
-        let x1 = clamp_f32(pos.x + r, 0.0, self.width  as f32 - 1e-2);
-        let y0 = clamp_f32(pos.y - r, 0.0, self.height as f32 - 1e-2);
-        let y1 = clamp_f32(pos.y + r, 0.0, self.height as f32 - 1e-2);
-
-        let x0 = (x0 / self.bin_size) as usize;
-        let x1 = (x1 / self.bin_size) as usize;
-        let y0 = (y0 / self.bin_size) as usize;
-        let y1 = (y1 / self.bin_size) as usize;
+        let pos = self.domain.wrap(pos);
+        let xs = self.cell_range(pos.x, r, self.width, cols, self.domain.periodic_x);
+        let ys = self.cell_range(pos.y, r, self.height, rows, self.domain.periodic_y);
 
-        for x in x0..x1+1 {
-            for y in y0..y1+1 {
+        for &x in xs.iter() {
+            for &y in ys.iter() {
                 let idx = y * cols + x;
                 for j in self.cells[idx].items.iter() {
                     let j = *j;
@@ -71,7 +75,7 @@ impl<'a, T> Grid<'a, T> where T: HasPosition {
 
                     let pos_j = self.items[j].position();
 
-                    if (pos - pos_j).magnitude2() < r*r {
+                    if self.domain.offset(pos, pos_j).magnitude2() < r*r {
                         neighbours.push(j);
                     }
                 }
@@ -81,7 +85,48 @@ impl<'a, T> Grid<'a, T> where T: HasPosition {
         neighbours
     }
 
-    fn construct_grid(width: f32, height: f32, bin_size: f32, items: &'a [T]) -> Vec<Cell> {
+    // Cells along one axis that overlap [p - r, p + r]. On a periodic axis the
+    // range continues on the other side of the seam
+    fn cell_range(&self, p: f32, r: f32, size: f32, n: usize, periodic: bool) -> Vec<usize> {
+        let cell = |v: f32| Grid::<T>::bin(v, size, self.bin_size, n);
+
+        if !periodic {
+            return (cell(p - r)..cell(p + r)+1).collect();
+        }
+
+        // Small domains can be covered more than once
+        if 2.0 * r >= size {
+            return (0..n).collect();
+        }
+
+        // Split the range at the seam. The last cell can be narrower than the
+        // others, so this is done in positions rather than cell indices. The
+        // pieces can share cells on either side of the seam, and those must
+        // only be searched once
+        let mut cells = Vec::with_capacity(4);
+        for &shift in [-size, 0.0, size].iter() {
+            let lo = (p - r + shift).max(0.0);
+            let hi = (p + r + shift).min(size);
+            if lo < hi {
+                cells.extend(cell(lo)..cell(hi)+1);
+            }
+        }
+        cells.sort_unstable();
+        cells.dedup();
+
+        cells
+    }
+
+    // Bin along an axis of length `size` split into `n` bins. Always a valid
+    // bin, even for positions on the far edge of domains that are so wide
+    // that the 1e-2 margin is lost to f32 rounding
+    fn bin(v: f32, size: f32, bin_size: f32, n: usize) -> usize {
+        let i = (clamp_f32(v, 0.0, size - 1e-2) / bin_size) as usize;
+        i.min(n - 1)
+    }
+
+    fn construct_grid(domain: &Domain, bin_size: f32, items: &'a [T]) -> Vec<Cell> {
+        let (width, height) = (domain.width, domain.height);
         let cols = (width / bin_size).ceil() as usize;
         let rows = (height / bin_size).ceil() as usize;
         let n_cells = cols * rows;
@@ -91,12 +136,10 @@ impl<'a, T> Grid<'a, T> where T: HasPosition {
         }).collect();
 
         for (i, pi) in items.iter().enumerate() {
-            let pos = pi.position();
+            let pos = domain.wrap(pi.position());
 
-            let x = clamp_f32(pos.x, 0.0, width-1e-2);
-            let y = clamp_f32(pos.y, 0.0, height-1e-2);
-            let x = (x / bin_size) as usize;
-            let y = (y / bin_size) as usize;
+            let x = Grid::<T>::bin(pos.x, width, bin_size, cols);
+            let y = Grid::<T>::bin(pos.y, height, bin_size, rows);
 
             let idx = y * cols + x;
             cells[idx].items.push(i);
@@ -127,4 +170,45 @@ impl<'a, T> Grid<'a, T> where T: HasPosition {
         splits
     }
 
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::particle::Particle;
+
+    #[test]
+    fn far_edge_of_a_wide_domain_is_binned() {
+        // 35 * 2^15 has a resolution of 0.125 in f32
+        let width = 35.0 * 32768.0;
+        let particles: Vec<Particle> = [width, width - 20.0, width + 5.0].iter().enumerate().map(|(i, &x)| {
+            Particle::new(i as u32, Vector2f::new(x, 50.0), 1.0)
+        }).collect();
+
+        let grid = Grid::new(width, 100.0, 35.0, &particles);
+        let mut neighbours = grid.nearest_by_pos(Vector2f::new(width, 50.0), 30.0);
+        neighbours.sort();
+        assert_eq!(neighbours, vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn cells_across_a_short_seam_are_searched_once() {
+        // 145.8 is split into bins of 35 with a narrow last one, and a range
+        // of 70 on either side of the seam reaches into the same bins
+        let height = 145.8;
+        let particles: Vec<Particle> = (0..30).map(|i| {
+            Particle::new(i, Vector2f::new(10.0, 0.3 + 4.85 * i as f32), 1.0)
+        }).collect();
+
+        let mut domain = Domain::new(20.0, height);
+        domain.periodic_y = true;
+        let grid = Grid::with_domain(domain, 35.0, &particles);
+        for (i, pi) in particles.iter().enumerate() {
+            let mut neighbours = grid.nearest_by_idx(i, 70.0);
+            let found = neighbours.len();
+            neighbours.sort();
+            neighbours.dedup();
+            assert_eq!(neighbours.len(), found, "duplicates around {:?}", pi.pos);
+        }
+    }
+}
@@ -24,7 +24,7 @@ fn main() {
     let mut first_person = kiss3d::camera::FirstPerson::new(eye, look_at);
 
     let config = Config::new(0.4, 0.8, 50, 10);
-    let mut universe = Universe::new(700.0, 700.0, &config);
+    let mut universe = Universe::new(700.0, 700.0, &config).unwrap();
 
     let mut force_x = 150.0;
     let mut force_y = 100.0;
@@ -62,6 +62,7 @@ fn main() {
     let font = Font::default();
 
     let mut last_time = std::time::Instant::now();
+    let mut frame: u64 = 0;
     while !window.should_close() {
         window.render_with_camera(&mut first_person);
 
@@ -86,7 +87,7 @@ fn main() {
                 WindowEvent::Key(Key::Space, Action::Press, _) => {
                 },
                 WindowEvent::Key(Key::R, Action::Press, _) => {
-                    universe = Universe::new(700.0, 700.0, &config);
+                    universe = Universe::new(700.0, 700.0, &config).unwrap();
                     let force = Force::new(350.0, 100.0, 2e8, 100.0);
                     universe.add_force(force);
                 },
@@ -122,9 +123,18 @@ fn main() {
 
         for _ in 0..2 {
             let old_particles = universe.get_particles().clone();
-            universe.update(0.005);
-            universe.debug_check_nans(&old_particles);
+            let result = universe.update(0.005);
+            if let Some((pi, before)) = universe.debug_check_nans(&old_particles) {
+                eprintln!("Found bad particle: {:?}\nPrevious frame: {:?}", pi, before);
+            }
+            result.unwrap();
+        }
+
+        // Structured solver stats, one JSON object per line
+        if frame % 100 == 0 {
+            println!("{}", universe.get_stats().to_json());
         }
+        frame += 1;
 
         // Debug accelerator
         if false {
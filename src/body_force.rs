@@ -0,0 +1,71 @@
+use wasm_bindgen::prelude::*;
+use crate::util::*;
+
+// Analytic body force fields that can be selected over wasm, where
+// callbacks aren't an option
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BodyForcePreset {
+    Swirl, // rotates the fluid counter-clockwise about the center of the tank
+    Shear, // pushes the top half right and the bottom half left
+    Shake, // shakes the tank left and right once a second
+    Central, // pulls everything towards the center of the tank
+}
+
+// An acceleration field applied to every particle on top of gravity
+pub enum BodyForce {
+    None,
+    Preset(BodyForcePreset, f32),
+
+    // Accelerations sampled on a cols x rows grid spanning the tank, with the
+    // first sample at (0, 0) and the last at (width, height)
+    Grid(usize, usize, Vec<Vector2f>),
+
+    // Called with the position and the simulation time. Universes can be
+    // moved to other threads, so it has to be as well
+    Callback(Box<dyn Fn(Vector2f, f32) -> Vector2f + Send + Sync>),
+}
+
+impl BodyForce {
+    pub fn sample(&self, pos: Vector2f, time: f32, width: f32, height: f32) -> Vector2f {
+        match self {
+            BodyForce::None => vec2f_zero(),
+            BodyForce::Preset(preset, strength) => {
+                // Normalized so that `strength` is the acceleration at the walls
+                let center = Vector2f::new(width / 2.0, height / 2.0);
+                let d = Vector2f::new((pos.x - center.x) / center.x, (pos.y - center.y) / center.y);
+                let a = match preset {
+                    BodyForcePreset::Swirl => Vector2f::new(-d.y, d.x),
+                    BodyForcePreset::Shear => Vector2f::new(d.y, 0.0),
+                    BodyForcePreset::Shake => {
+                        Vector2f::new((2.0 * std::f32::consts::PI * time).sin(), 0.0)
+                    },
+                    BodyForcePreset::Central => -d,
+                };
+                a * *strength
+            },
+            BodyForce::Grid(cols, rows, data) => {
+                sample_grid(*cols, *rows, data, pos.x / width, pos.y / height)
+            },
+            BodyForce::Callback(f) => f(pos, time),
+        }
+    }
+}
+
+// Bilinearly interpolates the grid at (u, v) in [0, 1]x[0, 1]. Points outside
+// take the value at the closest edge
+fn sample_grid(cols: usize, rows: usize, data: &[Vector2f], u: f32, v: f32) -> Vector2f {
+    let x = clamp_f32(u, 0.0, 1.0) * (cols - 1) as f32;
+    let y = clamp_f32(v, 0.0, 1.0) * (rows - 1) as f32;
+
+    let x0 = (x.floor() as usize).min(cols - 1);
+    let y0 = (y.floor() as usize).min(rows - 1);
+    let x1 = (x0 + 1).min(cols - 1);
+    let y1 = (y0 + 1).min(rows - 1);
+    let (fx, fy) = (x - x0 as f32, y - y0 as f32);
+
+    let at = |i: usize, j: usize| data[j * cols + i];
+    let bottom = at(x0, y0) * (1.0 - fx) + at(x1, y0) * fx;
+    let top    = at(x0, y1) * (1.0 - fx) + at(x1, y1) * fx;
+    bottom * (1.0 - fy) + top * fy
+}
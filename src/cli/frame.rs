@@ -0,0 +1,63 @@
+use std::fs;
+use std::path::Path;
+use spherro::{Universe, RASTER_STRIDE};
+
+// Renders the density over the whole tank as a greyscale PGM image `width`
+// pixels wide. Brighter is denser, relative to the densest pixel, and empty
+// space is black
+pub fn write_frame(universe: &Universe, width: usize, path: &Path) -> Result<(), String> {
+    let (w, h) = (universe.get_width(), universe.get_height());
+    let height = ((width as f32 * h / w).round() as usize).max(1);
+    let raster = universe.rasterize(0.0, 0.0, w, h, width, height).map_err(|e| e.to_string())?;
+
+    let densities: Vec<f32> = raster.chunks(RASTER_STRIDE).map(|s| s[2]).collect();
+    let max = densities.iter().cloned().filter(|d| d.is_finite()).fold(0.0, f32::max);
+
+    let mut image = format!("P5\n{} {}\n255\n", width, height).into_bytes();
+    // The raster starts at the bottom of the tank, images at the top
+    for row in densities.chunks(width).rev() {
+        image.extend(row.iter().map(|&d| {
+            if d.is_finite() && max > 0.0 { (255.0 * d / max).round() as u8 } else { 0 }
+        }));
+    }
+
+    fs::write(path, image).map_err(|e| format!("cannot write {}: {}", path.display(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use spherro::Config;
+
+    #[test]
+    fn frames_are_pgm_images_of_the_density() {
+        // Fluid in the lower left of a tank twice as wide as it is high
+        let mut universe = Universe::new(600.0, 300.0, &Config::new(0.3, 0.5, 10, 10)).unwrap();
+        universe.update(0.005).unwrap();
+
+        let path = env::temp_dir().join(format!("spherro-cli-frame-{}.pgm", std::process::id()));
+        write_frame(&universe, 40, &path).unwrap();
+        let image = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let header = b"P5\n40 20\n255\n";
+        assert_eq!(&image[..header.len()], &header[..]);
+        let pixels = &image[header.len()..];
+        assert_eq!(pixels.len(), 40 * 20);
+
+        // Rows are written from the top of the tank down
+        let pixel = |x: usize, y: usize| pixels[y * 40 + x];
+        assert!(pixel(2, 18) > 0);
+        assert_eq!(pixel(2, 1), 0);
+        assert_eq!(pixel(38, 18), 0);
+        assert_eq!(pixels.iter().cloned().max(), Some(255));
+    }
+
+    #[test]
+    fn unwritable_frames_are_reported() {
+        let universe = Universe::new(100.0, 100.0, &Config::new(0.5, 0.5, 4, 4)).unwrap();
+        let path = env::temp_dir().join("spherro-cli-no-such-dir").join("frame.pgm");
+        assert!(write_frame(&universe, 8, &path).unwrap_err().starts_with("cannot write"));
+    }
+}
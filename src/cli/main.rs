@@ -0,0 +1,390 @@
+extern crate spherro;
+#[macro_use]
+extern crate clap;
+
+mod scene;
+mod frame;
+mod sweep;
+
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+use std::str::FromStr;
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+use spherro::{Universe, Config, SmoothingKernel, PressureSolver, StepStats, util::Stopwatch};
+use scene::Scene;
+use sweep::SweepOptions;
+
+// Exit codes, so that scripts can tell a blown up run from a bad command line
+const EXIT_UNSTABLE: i32 = 1;
+const EXIT_USAGE: i32 = 2;
+
+// Headless simulation from the command line
+//
+//   spherro-cli run --scene dam_break --steps 2000 --snapshot-every 100 --out runs/dam
+//   spherro-cli sweep --param h=30,35,40 --param k=5:20:5 --csv sweep.csv
+fn main() {
+    let matches = App::new("spherro-cli")
+        .version(crate_version!())
+        .about("Runs spherro simulations without a window")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(with_run_args(with_sim_args(SubCommand::with_name("run")
+            .about("Runs a single simulation"))))
+        .subcommand(sweep::with_args(with_sim_args(SubCommand::with_name("sweep")
+            .about("Runs the simulation for every combination of some parameters, in parallel, \
+                    and summarizes how each run went"))))
+        .get_matches_safe()
+        .unwrap_or_else(|e| {
+            // --help and --version come through here too
+            if !e.use_stderr() {
+                e.exit();
+            }
+            eprintln!("{}", e.message);
+            process::exit(EXIT_USAGE);
+        });
+
+    let result = match matches.subcommand() {
+        ("run", Some(m)) => RunOptions::from_matches(m).and_then(|options| run(&options)),
+        ("sweep", Some(m)) => SweepOptions::from_matches(m).and_then(|options| sweep::run(&options)),
+        _ => unreachable!(),
+    };
+
+    if let Err(e) = result {
+        eprintln!("{}", e);
+        process::exit(e.code());
+    }
+}
+
+enum Error {
+    Usage(String), // bad arguments, scene or output directory
+    Unstable(String),
+}
+
+impl Error {
+    fn code(&self) -> i32 {
+        match self {
+            Error::Usage(_) => EXIT_USAGE,
+            Error::Unstable(_) => EXIT_UNSTABLE,
+        }
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Error::Usage(e) => write!(f, "error: {}", e),
+            Error::Unstable(e) => write!(f, "unstable: {}", e),
+        }
+    }
+}
+
+fn arg<'a, 'b>(name: &'a str) -> Arg<'a, 'b> {
+    Arg::with_name(name).long(name).takes_value(true)
+}
+
+// The scene, the solver and when to give up on it
+fn with_sim_args<'a, 'b>(app: App<'a, 'b>) -> App<'a, 'b> {
+    app.arg(arg("scene").value_name("NAME|FILE")
+            .help("A validation scenario (dam_break, hydrostatic_column, lid_driven_cavity, \
+                   taylor_green, poiseuille, couette) or a snapshot written by --snapshot-every. \
+                   Snapshots only hold the particles, and are loaded into a --size tank with the \
+                   default settings. Without it the tank starts with a block of fluid given by --config"))
+       .arg(arg("config").value_name("WFRAC,HFRAC,ROWS,COLS").default_value("0.4,0.8,50,10")
+            .help("Block of fluid in the lower left of the tank"))
+       .arg(arg("size").value_name("WIDTHxHEIGHT").default_value("600x600")
+            .help("Size of the tank. Scenarios come with their own"))
+       .arg(arg("dt").default_value("0.005"))
+       .arg(arg("steps").default_value("1000"))
+       .arg(arg("solver").possible_values(&["fixed", "adaptive"]).default_value("fixed"))
+       .arg(arg("pressure-iterations").value_name("N")
+            .help("Pressure iterations per step, or the most the adaptive solver takes [default: 4]"))
+       .arg(arg("pressure-tolerance").value_name("TOL")
+            .help("Average compression the adaptive solver stops at [default: 0.25]"))
+       .arg(arg("kernel").possible_values(&["cubic", "wendland"]).default_value("cubic"))
+       .arg(arg("seed").default_value("0").help("Seeds the positions of spawned particles"))
+       .arg(Arg::with_name("no-monitor").long("no-monitor")
+            .help("Fails on the first unstable step, instead of rolling it back and substepping"))
+       .arg(arg("max-density-error").value_name("ERROR")
+            .help("Fails once any particle is compressed this much past the rest density"))
+}
+
+fn with_run_args<'a, 'b>(app: App<'a, 'b>) -> App<'a, 'b> {
+    app.arg(arg("threads").default_value("0").help("Worker threads, 0 for one per core"))
+       .arg(arg("out").value_name("DIR").default_value(".").help("Where snapshots and frames go"))
+       .arg(arg("snapshot-every").value_name("N").default_value("0")
+            .help("Writes the particles to snapshot_<step>.csv every N steps"))
+       .arg(arg("frame-every").value_name("N").default_value("0")
+            .help("Renders the density to frame_<step>.pgm every N steps"))
+       .arg(arg("frame-width").value_name("PIXELS").default_value("256"))
+       .arg(arg("stats-every").value_name("N").default_value("100")
+            .help("Prints the step stats as a line of JSON every N steps"))
+       .arg(Arg::with_name("quiet").long("quiet").short("q").help("No progress on stderr"))
+}
+
+struct SimOptions {
+    scene: Scene,
+    dt: f32,
+    steps: u64,
+    solver: PressureSolver,
+    pressure_iterations: Option<u32>,
+    pressure_tolerance: Option<f32>,
+    kernel: SmoothingKernel,
+    seed: u32,
+    monitor: bool,
+    max_density_error: Option<f32>,
+}
+
+struct RunOptions {
+    sim: SimOptions,
+    threads: usize,
+    out: PathBuf,
+    snapshot_every: u64,
+    frame_every: u64,
+    frame_width: usize,
+    stats_every: u64,
+    quiet: bool,
+}
+
+impl SimOptions {
+    fn from_matches(m: &ArgMatches) -> Result<SimOptions, Error> {
+        let (width, height) = parse_size(m.value_of("size").unwrap())?;
+        let scene = match m.value_of("scene") {
+            Some(name) => Scene::named(name, width, height),
+            None => Scene::Block { config: parse_config(m.value_of("config").unwrap())?, width, height },
+        };
+
+        // Caught here, since the universe would only report it as a failed step
+        let dt: f32 = value(m, "dt")?;
+        if !(dt.is_finite() && dt > 0.0) {
+            return Err(Error::Usage(format!("bad --dt {}, expected a positive time step", dt)));
+        }
+
+        Ok(SimOptions {
+            scene,
+            dt,
+            steps: value(m, "steps")?,
+            solver: match m.value_of("solver") {
+                Some("adaptive") => PressureSolver::Adaptive,
+                _ => PressureSolver::Fixed,
+            },
+            pressure_iterations: optional(m, "pressure-iterations")?,
+            pressure_tolerance: optional(m, "pressure-tolerance")?,
+            kernel: match m.value_of("kernel") {
+                Some("wendland") => SmoothingKernel::WendlandC2,
+                _ => SmoothingKernel::CubicSpline,
+            },
+            seed: value(m, "seed")?,
+            monitor: !m.is_present("no-monitor"),
+            max_density_error: optional(m, "max-density-error")?,
+        })
+    }
+
+    // Builds the scene with every solver setting applied
+    fn universe(&self) -> Result<Universe, Error> {
+        let mut universe = self.scene.build().map_err(Error::Usage)?;
+        universe.set_seed(self.seed);
+        universe.set_smoothing_kernel(self.kernel);
+        universe.set_pressure_solver(self.solver);
+        universe.set_stability_monitor(self.monitor);
+        if let Some(n) = self.pressure_iterations {
+            universe.set_pressure_iterations(n).map_err(|e| Error::Usage(e.to_string()))?;
+        }
+        if let Some(tolerance) = self.pressure_tolerance {
+            universe.set_pressure_tolerance(tolerance).map_err(|e| Error::Usage(e.to_string()))?;
+        }
+
+        Ok(universe)
+    }
+
+    // Takes step number `step`, and fails if it went unstable
+    fn step(&self, universe: &mut Universe, dt: f32, step: u64) -> Result<StepStats, Error> {
+        universe.update(dt).map_err(|e| {
+            Error::Unstable(format!("step {} at t={}: {}", step, universe.get_time(), e))
+        })?;
+        let stats = universe.get_stats();
+
+        if let Some(max) = self.max_density_error {
+            if stats.density_error_max > max {
+                return Err(Error::Unstable(format!(
+                    "step {} at t={}: density error {} is over {}",
+                    step, universe.get_time(), stats.density_error_max, max,
+                )));
+            }
+        }
+
+        Ok(stats)
+    }
+}
+
+impl RunOptions {
+    fn from_matches(m: &ArgMatches) -> Result<RunOptions, Error> {
+        let options = RunOptions {
+            sim: SimOptions::from_matches(m)?,
+            threads: value(m, "threads")?,
+            out: PathBuf::from(m.value_of("out").unwrap()),
+            snapshot_every: value(m, "snapshot-every")?,
+            frame_every: value(m, "frame-every")?,
+            frame_width: value(m, "frame-width")?,
+            stats_every: value(m, "stats-every")?,
+            quiet: m.is_present("quiet"),
+        };
+
+        if options.frame_width == 0 {
+            return Err(Error::Usage("--frame-width must be positive".to_string()));
+        }
+
+        Ok(options)
+    }
+}
+
+fn run(options: &RunOptions) -> Result<(), Error> {
+    rayon::ThreadPoolBuilder::new().num_threads(options.threads).build_global()
+                             .map_err(|e| Error::Usage(e.to_string()))?;
+
+    let sim = &options.sim;
+    let mut universe = sim.universe()?;
+    if options.snapshot_every > 0 || options.frame_every > 0 {
+        fs::create_dir_all(&options.out).map_err(|e| {
+            Error::Usage(format!("cannot create {}: {}", options.out.display(), e))
+        })?;
+    }
+
+    let due = |every: u64, step: u64| step.checked_rem(every) == Some(0);
+    let mut clock = Stopwatch::start();
+    let mut elapsed_ms = 0.0;
+    for step in 1..=sim.steps {
+        let stats = sim.step(&mut universe, sim.dt, step)?;
+
+        if due(options.snapshot_every, step) {
+            let path = options.out.join(format!("snapshot_{:06}.csv", step));
+            scene::write_snapshot(&universe, &path).map_err(Error::Usage)?;
+        }
+        if due(options.frame_every, step) {
+            let path = options.out.join(format!("frame_{:06}.pgm", step));
+            frame::write_frame(&universe, options.frame_width, &path).map_err(Error::Usage)?;
+        }
+        if due(options.stats_every, step) {
+            println!("{}", stats.to_json());
+        }
+
+        // Progress about once a second
+        elapsed_ms += clock.lap();
+        if !options.quiet && (elapsed_ms > 1000.0 || step == sim.steps) {
+            eprintln!("step {}/{} t={:.3} particles={} density error {:.3} {:.1} ms/step",
+                      step, sim.steps, universe.get_time(), stats.particles,
+                      stats.density_error_max, stats.total_ms);
+            elapsed_ms = 0.0;
+        }
+    }
+
+    Ok(())
+}
+
+fn value<T: FromStr>(m: &ArgMatches, name: &str) -> Result<T, Error> {
+    optional(m, name)?.ok_or_else(|| Error::Usage(format!("--{} is required", name)))
+}
+
+fn optional<T: FromStr>(m: &ArgMatches, name: &str) -> Result<Option<T>, Error> {
+    match m.value_of(name) {
+        Some(v) => v.parse().map(Some).map_err(|_| Error::Usage(format!("bad --{} {}", name, v))),
+        None => Ok(None),
+    }
+}
+
+fn parse_size(size: &str) -> Result<(f32, f32), Error> {
+    let bad = || Error::Usage(format!("bad --size {}, expected eg. 600x600", size));
+    let mut parts = size.split('x').map(|p| p.parse::<f32>());
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some(Ok(w)), Some(Ok(h)), None) => Ok((w, h)),
+        _ => Err(bad()),
+    }
+}
+
+fn parse_config(config: &str) -> Result<Config, Error> {
+    let bad = || Error::Usage(format!("bad --config {}, expected eg. 0.4,0.8,50,10", config));
+    let parts: Vec<&str> = config.split(',').collect();
+    if parts.len() != 4 {
+        return Err(bad());
+    }
+
+    let frac = |s: &str| s.parse::<f32>().map_err(|_| bad());
+    let count = |s: &str| s.parse::<usize>().map_err(|_| bad());
+    Ok(Config::new(frac(parts[0])?, frac(parts[1])?, count(parts[2])?, count(parts[3])?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use spherro::util::Vector2f;
+
+    fn sim_options(args: &[&str]) -> Result<SimOptions, Error> {
+        let app = with_sim_args(App::new("test"));
+        let m = app.get_matches_from_safe(Some("test").iter().chain(args.iter())).unwrap();
+        SimOptions::from_matches(&m)
+    }
+
+    #[test]
+    fn sizes_are_parsed() {
+        assert_eq!(parse_size("600x400").ok(), Some((600.0, 400.0)));
+        assert_eq!(parse_size("1e3x2.5").ok(), Some((1000.0, 2.5)));
+        for bad in ["600", "600x", "x600", "600x400x2", "600X400", "wide x tall"].iter() {
+            assert_eq!(parse_size(bad).err().map(|e| e.code()), Some(EXIT_USAGE), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn configs_are_parsed() {
+        assert_eq!(parse_config("0.5,0.25,12,3").ok(), Some(Config::new(0.5, 0.25, 12, 3)));
+        for bad in ["0.5,0.25,12", "0.5,0.25,12,3,1", "0.5,0.25,-1,3", "0.5,0.25,1.5,3", ""].iter() {
+            assert_eq!(parse_config(bad).err().map(|e| e.code()), Some(EXIT_USAGE), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn sim_options_have_defaults() {
+        let options = sim_options(&[]).ok().unwrap();
+        assert!(matches!(options.scene, Scene::Block { width, height, .. } if width == 600.0 && height == 600.0));
+        assert_eq!((options.dt, options.steps, options.seed), (0.005, 1000, 0));
+        assert_eq!(options.solver, PressureSolver::Fixed);
+        assert_eq!(options.kernel, SmoothingKernel::CubicSpline);
+        assert!(options.monitor);
+        assert!(options.pressure_iterations.is_none() && options.max_density_error.is_none());
+
+        let options = sim_options(&["--scene", "couette", "--solver", "adaptive", "--kernel", "wendland",
+                                    "--pressure-iterations", "6", "--no-monitor"]).ok().unwrap();
+        assert!(matches!(options.scene, Scene::Scenario(ref name) if name == "couette"));
+        assert_eq!(options.solver, PressureSolver::Adaptive);
+        assert_eq!(options.kernel, SmoothingKernel::WendlandC2);
+        assert_eq!(options.pressure_iterations, Some(6));
+        assert!(!options.monitor);
+    }
+
+    #[test]
+    fn bad_options_are_usage_errors() {
+        for args in [&["--dt", "fast"][..], &["--dt", "0"], &["--dt", "nan"], &["--dt=-0.005"], &["--steps=-1"],
+                     &["--size", "600"], &["--pressure-iterations", "0"]].iter() {
+            let error = sim_options(args).and_then(|options| options.universe().map(|_| ()));
+            assert_eq!(error.err().map(|e| e.code()), Some(EXIT_USAGE), "{:?}", args);
+        }
+    }
+
+    #[test]
+    fn unstable_runs_exit_with_their_own_code() {
+        assert_eq!(Error::Usage(String::new()).code(), 2);
+        assert_eq!(Error::Unstable(String::new()).code(), 1);
+
+        // Every step is over a negative limit
+        let options = sim_options(&["--config", "0.4,0.8,20,10", "--max-density-error=-1"]).ok().unwrap();
+        let mut universe = options.universe().ok().unwrap();
+        match options.step(&mut universe, 0.005, 1) {
+            Err(e @ Error::Unstable(_)) => assert_eq!(e.code(), EXIT_UNSTABLE),
+            Err(e) => panic!("{}", e),
+            Ok(_) => panic!("expected the step to fail"),
+        }
+
+        let options = sim_options(&["--config", "0.4,0.8,20,10", "--no-monitor"]).ok().unwrap();
+        let mut universe = options.universe().ok().unwrap();
+        universe.set_body_force(|_, _| Vector2f::new(f32::NAN, 0.0)).unwrap();
+        assert!(matches!(options.step(&mut universe, 0.005, 1), Err(Error::Unstable(_))));
+    }
+}
@@ -0,0 +1,149 @@
+use std::fs;
+use std::path::Path;
+use spherro::{Universe, Particle, Config};
+use spherro::scenarios;
+use spherro::util::Vector2f;
+
+// First line of every snapshot
+const HEADER: &str = "id,x,y,vx,vy,rho,pressure,temperature,phase";
+
+// What the universe starts out as
+pub enum Scene {
+    Block { config: Config, width: f32, height: f32 },
+    Scenario(String), // one of `scenarios::all`, by name
+    Snapshot { path: String, width: f32, height: f32 },
+}
+
+impl Scene {
+    // A scenario name, or else the path of a snapshot
+    pub fn named(name: &str, width: f32, height: f32) -> Scene {
+        if scenarios::all().iter().any(|s| s.name() == name) {
+            Scene::Scenario(name.to_string())
+        } else {
+            Scene::Snapshot { path: name.to_string(), width, height }
+        }
+    }
+
+    pub fn build(&self) -> Result<Universe, String> {
+        match self {
+            Scene::Block { config, width, height } => {
+                Universe::new(*width, *height, config).map_err(|e| e.to_string())
+            },
+            Scene::Scenario(name) => {
+                let scenario = scenarios::all().into_iter().find(|s| s.name() == name)
+                                                           .ok_or_else(|| format!("no scenario {}", name))?;
+                scenario.build().map_err(|e| format!("{}: {}", name, e))
+            },
+            Scene::Snapshot { path, width, height } => {
+                let text = fs::read_to_string(path).map_err(|e| format!("cannot read {}: {}", path, e))?;
+                let particles = read_snapshot(&text, *width, *height).map_err(|e| format!("{}: {}", path, e))?;
+                Universe::from_particles(*width, *height, particles).map_err(|e| format!("{}: {}", path, e))
+            },
+        }
+    }
+}
+
+// Writes every particle as a line of CSV. These can be loaded back as a
+// scene, into a --size tank with the default settings. Only the particles
+// are kept, so this picks up runs of a block of fluid. A scenario's own tank,
+// obstacles, periodicity, gravity, viscosity and particle mass are lost
+pub fn write_snapshot(universe: &Universe, path: &Path) -> Result<(), String> {
+    let mut text = String::with_capacity(64 * universe.get_size());
+    text.push_str(HEADER);
+    text.push('\n');
+    for pi in universe.get_particles().iter() {
+        text.push_str(&format!("{},{},{},{},{},{},{},{},{}\n",
+                               pi.id, pi.pos.x, pi.pos.y, pi.vel.x, pi.vel.y,
+                               pi.rho, pi.pressure, pi.temperature, pi.phase));
+    }
+
+    fs::write(path, text).map_err(|e| format!("cannot write {}: {}", path.display(), e))
+}
+
+// The density and pressure are left for the first step to compute. Every
+// particle has to be in the width x height tank. Particles are pushed back
+// from the walls rather than stopped at them, so they can be a little past
+fn read_snapshot(text: &str, width: f32, height: f32) -> Result<Vec<Particle>, String> {
+    let slack = Universe::default_smoothing_length();
+    let mut lines = text.lines().enumerate();
+    match lines.next() {
+        Some((_, HEADER)) => {},
+        _ => return Err(format!("line 1: expected {}", HEADER)),
+    }
+
+    let mut particles = Vec::new();
+    for (i, line) in lines {
+        let bad = |what: &str| format!("line {}: bad {}", i + 1, what);
+        let fields: Vec<&str> = line.split(',').collect();
+        if fields.len() != 9 {
+            return Err(format!("line {}: expected 9 fields, got {}", i + 1, fields.len()));
+        }
+
+        let f = |k: usize| fields[k].parse::<f32>().map_err(|_| bad(HEADER.split(',').nth(k).unwrap()));
+        let id = fields[0].parse().map_err(|_| bad("id"))?;
+        let pos = Vector2f::new(f(1)?, f(2)?);
+        if !(pos.x >= -slack && pos.x <= width + slack && pos.y >= -slack && pos.y <= height + slack) {
+            return Err(format!("line {}: ({}, {}) is outside the {}x{} tank, set its --size",
+                               i + 1, pos.x, pos.y, width, height));
+        }
+
+        let mut pi = Particle::new(id, pos, Universe::default_particle_mass());
+        pi.vel = Vector2f::new(f(3)?, f(4)?);
+        pi.temperature = f(7)?;
+        pi.phase = fields[8].parse().map_err(|_| bad("phase"))?;
+        particles.push(pi);
+    }
+
+    Ok(particles)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn names_pick_scenarios_before_files() {
+        assert!(matches!(Scene::named("dam_break", 1.0, 1.0), Scene::Scenario(ref name) if name == "dam_break"));
+        assert!(matches!(Scene::named("dam_break.csv", 1.0, 1.0), Scene::Snapshot { .. }));
+        assert!(Scene::Scenario("explosion".to_string()).build().is_err());
+        assert!(Scene::named("no/such/snapshot.csv", 1.0, 1.0).build().is_err());
+    }
+
+    #[test]
+    fn snapshots_round_trip() {
+        let mut universe = Universe::new(300.0, 300.0, &Config::new(0.4, 0.8, 10, 10)).unwrap();
+        universe.set_phase_in_rect(0.0, 0.0, 50.0, 300.0, 2);
+        for _ in 0..20 {
+            universe.update(0.005).unwrap();
+        }
+
+        let path = env::temp_dir().join(format!("spherro-cli-snapshot-{}.csv", std::process::id()));
+        write_snapshot(&universe, &path).unwrap();
+        let loaded = Scene::Snapshot { path: path.to_str().unwrap().to_string(), width: 300.0, height: 300.0 }.build();
+        fs::remove_file(&path).unwrap();
+
+        let loaded = loaded.unwrap();
+        assert_eq!(loaded.get_size(), universe.get_size());
+        assert!(universe.get_particles().iter().any(|pi| pi.phase == 2));
+        for (a, b) in universe.get_particles().iter().zip(loaded.get_particles().iter()) {
+            assert_eq!((a.id, a.pos, a.vel, a.temperature, a.phase), (b.id, b.pos, b.vel, b.temperature, b.phase));
+        }
+    }
+
+    #[test]
+    fn bad_snapshots_name_the_line() {
+        let read = |text: &str| read_snapshot(text, 10.0, 10.0);
+        let row = "0,1,2,3,4,0,0,0,0";
+        assert_eq!(read("id,x,y\n").err().unwrap(), format!("line 1: expected {}", HEADER));
+        assert_eq!(read(&format!("{}\n{}\n1,2\n", HEADER, row)).err().unwrap(),
+                   "line 3: expected 9 fields, got 2");
+        assert_eq!(read(&format!("{}\n0,1,2,3,fast,0,0,0,0\n", HEADER)).err().unwrap(), "line 2: bad vy");
+        assert_eq!(read(&format!("{}\n-1,1,2,3,4,0,0,0,0\n", HEADER)).err().unwrap(), "line 2: bad id");
+        assert_eq!(read(&format!("{}\n{}\n", HEADER, row)).unwrap().len(), 1);
+
+        // Snapshots of a bigger tank, eg. of a scenario, need its size
+        assert_eq!(read(&format!("{}\n{}\n1,60,2,3,4,0,0,0,0\n", HEADER, row)).err().unwrap(),
+                   "line 3: (60, 2) is outside the 10x10 tank, set its --size");
+    }
+}
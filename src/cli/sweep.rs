@@ -0,0 +1,489 @@
+use std::fmt::Write;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+use clap::{App, Arg, ArgMatches};
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use rayon::prelude::*;
+use spherro::{Universe, StepStats};
+use crate::{Error, SimOptions, arg, value, optional};
+
+// Parameters that can be swept. dt is the time step, and the rest are set on
+// the universe: h the smoothing length, mass the particle mass, k the
+// stiffness, visc the viscosity coefficient, xsph the XSPH epsilon, and
+// iterations and tolerance the pressure solver's
+const PARAMS: [&str; 8] = ["dt", "h", "mass", "k", "visc", "xsph", "iterations", "tolerance"];
+
+pub fn with_args<'a, 'b>(app: App<'a, 'b>) -> App<'a, 'b> {
+    app.arg(arg("param").value_name("NAME=VALUES").multiple(true).number_of_values(1).required(true)
+            .help("A parameter to sweep, one of dt, h, mass, k, visc, xsph, iterations and \
+                   tolerance. VALUES is a list like 30,35,40, an inclusive range with a step \
+                   like 5:20:5, or a range to sample like 5..20, which needs --samples"))
+       .arg(arg("samples").value_name("N")
+            .help("Runs N random combinations, drawn with --seed, instead of every combination"))
+       .arg(arg("jobs").default_value("0").help("Runs at once, 0 for one per core"))
+       .arg(arg("metrics").value_name("NAMES").use_delimiter(true)
+            .help("Stats of the last step to add to the summary, by their names in the JSON \
+                   printed by run, eg. max_speed,kinetic_energy"))
+       .arg(arg("csv").value_name("FILE").help("Writes the summary as CSV"))
+       .arg(arg("json").value_name("FILE").help("Writes the summary as JSON"))
+       .arg(Arg::with_name("quiet").long("quiet").short("q").help("No progress on stderr"))
+}
+
+enum Values {
+    List(Vec<f32>),
+    Range(f32, f32), // to sample uniformly
+}
+
+struct Param {
+    name: String,
+    values: Values,
+}
+
+impl Param {
+    fn parse(spec: &str) -> Result<Param, Error> {
+        let bad = |why: &str| Error::Usage(format!("bad --param {}: {}", spec, why));
+        let mut parts = spec.splitn(2, '=');
+        let (name, values) = match (parts.next(), parts.next()) {
+            (Some(name), Some(values)) => (name.trim(), values.trim()),
+            _ => return Err(bad("expected NAME=VALUES")),
+        };
+        if !PARAMS.contains(&name) {
+            return Err(bad(&format!("no parameter {}, expected one of {}", name, PARAMS.join(", "))));
+        }
+
+        let number = |s: &str| s.trim().parse::<f32>().ok().filter(|v| v.is_finite())
+                                .ok_or_else(|| bad(&format!("{} is not a number", s)));
+        let values = if let Some(i) = values.find("..") {
+            let (lo, hi) = (number(&values[..i])?, number(&values[i + 2..])?);
+            if lo >= hi {
+                return Err(bad("the range is empty"));
+            }
+            Values::Range(lo, hi)
+        } else if values.contains(':') {
+            let bounds: Vec<&str> = values.split(':').collect();
+            if bounds.len() != 3 {
+                return Err(bad("expected START:END:STEP"));
+            }
+            let (start, end, step) = (number(bounds[0])?, number(bounds[1])?, number(bounds[2])?);
+            if step <= 0.0 || start > end {
+                return Err(bad("the range is empty"));
+            }
+            // Rounded so that the end is kept despite rounding errors
+            let n = ((end - start) / step + 1e-3).floor() as usize + 1;
+            Values::List((0..n).map(|i| start + i as f32 * step).collect())
+        } else {
+            Values::List(values.split(',').map(number).collect::<Result<_, _>>()?)
+        };
+
+        Ok(Param { name: name.to_string(), values })
+    }
+}
+
+pub struct SweepOptions {
+    sim: SimOptions,
+    params: Vec<Param>,
+    samples: Option<usize>,
+    jobs: usize,
+    metrics: Vec<String>,
+    csv: Option<PathBuf>,
+    json: Option<PathBuf>,
+    quiet: bool,
+}
+
+impl SweepOptions {
+    pub fn from_matches(m: &ArgMatches) -> Result<SweepOptions, Error> {
+        let params: Vec<Param> = m.values_of("param").unwrap().map(Param::parse).collect::<Result<_, _>>()?;
+        for (i, p) in params.iter().enumerate() {
+            if params[..i].iter().any(|q| q.name == p.name) {
+                return Err(Error::Usage(format!("{} is swept more than once", p.name)));
+            }
+        }
+
+        let metrics: Vec<String> = m.values_of("metrics").map_or(Vec::new(), |v| v.map(String::from).collect());
+        if let Some(name) = metrics.iter().find(|name| StepStats::default().get(name).is_none()) {
+            return Err(Error::Usage(format!("no stat {}", name)));
+        }
+
+        let options = SweepOptions {
+            sim: SimOptions::from_matches(m)?,
+            params,
+            samples: optional(m, "samples")?,
+            jobs: value(m, "jobs")?,
+            metrics,
+            csv: m.value_of("csv").map(PathBuf::from),
+            json: m.value_of("json").map(PathBuf::from),
+            quiet: m.is_present("quiet"),
+        };
+
+        let sampled = options.params.iter().any(|p| matches!(p.values, Values::Range(..)));
+        if sampled && options.samples.is_none() {
+            return Err(Error::Usage("ranges like 5..20 need --samples".to_string()));
+        }
+
+        Ok(options)
+    }
+
+    // Values of every parameter for each run, in the order of `params`
+    fn combinations(&self) -> Vec<Vec<f32>> {
+        if let Some(samples) = self.samples {
+            let mut rng = StdRng::seed_from_u64(self.sim.seed as u64);
+            return (0..samples).map(|_| {
+                self.params.iter().map(|p| match &p.values {
+                    Values::List(values) => values[rng.gen_range(0, values.len())],
+                    Values::Range(lo, hi) => rng.gen_range(*lo, *hi),
+                }).collect()
+            }).collect();
+        }
+
+        // Every combination, with the last parameter changing fastest
+        let mut combinations = vec![Vec::new()];
+        for p in self.params.iter() {
+            let values = match &p.values {
+                Values::List(values) => values,
+                Values::Range(..) => unreachable!(),
+            };
+            combinations = combinations.iter().flat_map(|c| values.iter().map(move |&v| {
+                let mut c = c.clone();
+                c.push(v);
+                c
+            })).collect();
+        }
+
+        combinations
+    }
+
+    fn label(&self, values: &[f32]) -> String {
+        let pairs: Vec<String> = self.params.iter().zip(values).map(|(p, v)| format!("{}={}", p.name, v)).collect();
+        pairs.join(" ")
+    }
+}
+
+enum Status {
+    Stable, // made it through every step
+    Unstable,
+    Invalid, // a parameter was out of range
+}
+
+impl Status {
+    fn name(&self) -> &'static str {
+        match self {
+            Status::Stable => "stable",
+            Status::Unstable => "unstable",
+            Status::Invalid => "invalid",
+        }
+    }
+}
+
+// How a run went
+struct Outcome {
+    values: Vec<f32>,
+    status: Status,
+    steps: u64, // completed
+    time: f32,
+    wall_ms: f32,
+    peak_density_error: f32, // over every completed step
+    stats: StepStats, // of the last completed step
+    message: String, // why it failed
+}
+
+fn run_one(options: &SweepOptions, values: &[f32]) -> Outcome {
+    let clock = Instant::now();
+    let mut outcome = Outcome {
+        values: values.to_vec(),
+        status: Status::Invalid,
+        steps: 0,
+        time: 0.0,
+        wall_ms: 0.0,
+        peak_density_error: f32::NAN,
+        stats: StepStats::default(),
+        message: String::new(),
+    };
+
+    let sim = &options.sim;
+    let setup = sim.universe().and_then(|mut universe| {
+        let mut dt = sim.dt;
+        for (p, &v) in options.params.iter().zip(values) {
+            set(&mut universe, &mut dt, &p.name, v).map_err(|e| Error::Usage(format!("{}={}: {}", p.name, v, e)))?;
+        }
+        Ok((universe, dt))
+    });
+    let (mut universe, dt) = match setup {
+        Ok(setup) => setup,
+        Err(Error::Usage(e)) | Err(Error::Unstable(e)) => {
+            outcome.message = e;
+            return outcome;
+        },
+    };
+
+    outcome.status = Status::Stable;
+    for step in 1..=sim.steps {
+        match sim.step(&mut universe, dt, step) {
+            Ok(stats) => {
+                outcome.steps = step;
+                outcome.peak_density_error = outcome.peak_density_error.max(stats.density_error_max);
+                outcome.stats = stats;
+            },
+            Err(Error::Usage(e)) | Err(Error::Unstable(e)) => {
+                outcome.status = Status::Unstable;
+                outcome.message = e;
+                break;
+            },
+        }
+    }
+
+    outcome.time = universe.get_time();
+    outcome.wall_ms = clock.elapsed().as_secs_f32() * 1000.0;
+    outcome
+}
+
+fn set(universe: &mut Universe, dt: &mut f32, name: &str, v: f32) -> Result<(), String> {
+    let result = match name {
+        "dt" if v > 0.0 => {
+            *dt = v;
+            Ok(())
+        },
+        "dt" => return Err("time step must be positive".to_string()),
+        "h" => universe.set_smoothing_length(v),
+        "mass" => universe.set_particle_mass(v),
+        "k" => universe.set_stiffness(v),
+        "visc" => {
+            universe.set_viscosity(v);
+            Ok(())
+        },
+        "xsph" => {
+            universe.set_xsph(v);
+            Ok(())
+        },
+        "iterations" => universe.set_pressure_iterations(v.round().max(0.0) as u32),
+        "tolerance" => universe.set_pressure_tolerance(v),
+        _ => unreachable!(),
+    };
+
+    result.map_err(|e| e.to_string())
+}
+
+pub fn run(options: &SweepOptions) -> Result<(), Error> {
+    rayon::ThreadPoolBuilder::new().num_threads(options.jobs).build_global()
+                             .map_err(|e| Error::Usage(e.to_string()))?;
+
+    // A bad scene would fail every run the same way
+    options.sim.universe()?;
+
+    let combinations = options.combinations();
+    let done = AtomicUsize::new(0);
+    let outcomes: Vec<Outcome> = combinations.par_iter().map(|values| {
+        let outcome = run_one(options, values);
+        let done = done.fetch_add(1, Ordering::SeqCst) + 1;
+        if !options.quiet {
+            eprintln!("run {}/{} {}: {} after {} steps in {:.0} ms",
+                      done, combinations.len(), options.label(values),
+                      outcome.status.name(), outcome.steps, outcome.wall_ms);
+        }
+        outcome
+    }).collect();
+
+    let write = |path: &PathBuf, text: String| {
+        fs::write(path, text).map_err(|e| Error::Usage(format!("cannot write {}: {}", path.display(), e)))
+    };
+    if let Some(path) = &options.csv {
+        write(path, to_csv(options, &outcomes))?;
+    }
+    if let Some(path) = &options.json {
+        write(path, to_json(options, &outcomes))?;
+    }
+    if options.csv.is_none() && options.json.is_none() {
+        print!("{}", to_csv(options, &outcomes));
+    }
+
+    Ok(())
+}
+
+// Every column of the summary but the run number, parameters and message
+fn columns(options: &SweepOptions, outcome: &Outcome) -> Vec<(String, f32)> {
+    let mut columns = vec![
+        ("steps".to_string(), outcome.steps as f32),
+        ("time".to_string(), outcome.time),
+        ("wall_ms".to_string(), outcome.wall_ms),
+        ("density_error_avg".to_string(), outcome.stats.density_error_avg),
+        ("density_error_max".to_string(), outcome.stats.density_error_max),
+        ("density_error_peak".to_string(), outcome.peak_density_error),
+    ];
+    for name in options.metrics.iter() {
+        columns.push((name.clone(), outcome.stats.get(name).unwrap()));
+    }
+
+    if outcome.steps == 0 {
+        for c in columns.iter_mut().skip(3) {
+            c.1 = f32::NAN;
+        }
+    }
+    columns
+}
+
+fn to_csv(options: &SweepOptions, outcomes: &[Outcome]) -> String {
+    let mut csv = String::from("run");
+    for p in options.params.iter() {
+        write!(csv, ",{}", p.name).unwrap();
+    }
+    csv.push_str(",status");
+    if let Some(outcome) = outcomes.first() {
+        for (name, _) in columns(options, outcome) {
+            write!(csv, ",{}", name).unwrap();
+        }
+    }
+    csv.push_str(",message\n");
+
+    for (i, outcome) in outcomes.iter().enumerate() {
+        write!(csv, "{}", i).unwrap();
+        for v in outcome.values.iter() {
+            write!(csv, ",{}", v).unwrap();
+        }
+        write!(csv, ",{}", outcome.status.name()).unwrap();
+        for (_, v) in columns(options, outcome) {
+            if v.is_finite() {
+                write!(csv, ",{}", v).unwrap();
+            } else {
+                csv.push(',');
+            }
+        }
+        writeln!(csv, ",\"{}\"", outcome.message.replace('"', "\"\"")).unwrap();
+    }
+
+    csv
+}
+
+fn to_json(options: &SweepOptions, outcomes: &[Outcome]) -> String {
+    let number = |v: f32| if v.is_finite() { v.to_string() } else { "null".to_string() };
+    let runs: Vec<String> = outcomes.iter().enumerate().map(|(i, outcome)| {
+        let mut run = format!("{{\"run\":{}", i);
+        for (p, &v) in options.params.iter().zip(outcome.values.iter()) {
+            write!(run, ",\"{}\":{}", p.name, number(v)).unwrap();
+        }
+        write!(run, ",\"status\":\"{}\"", outcome.status.name()).unwrap();
+        for (name, v) in columns(options, outcome) {
+            write!(run, ",\"{}\":{}", name, number(v)).unwrap();
+        }
+        write!(run, ",\"message\":{}}}", json_string(&outcome.message)).unwrap();
+        run
+    }).collect();
+
+    format!("[\n{}\n]\n", runs.join(",\n"))
+}
+
+fn json_string(s: &str) -> String {
+    let mut json = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            c if (c as u32) < 0x20 => write!(json, "\\u{:04x}", c as u32).unwrap(),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{with_sim_args, EXIT_USAGE};
+
+    fn options(args: &[&str]) -> Result<SweepOptions, Error> {
+        let app = with_args(with_sim_args(App::new("test")));
+        let m = app.get_matches_from_safe(Some("test").iter().chain(args.iter())).unwrap();
+        SweepOptions::from_matches(&m)
+    }
+
+    fn list(spec: &str) -> Vec<f32> {
+        match Param::parse(spec).ok().unwrap().values {
+            Values::List(values) => values,
+            Values::Range(..) => panic!("{} is not a list", spec),
+        }
+    }
+
+    #[test]
+    fn stepped_ranges_keep_their_end() {
+        assert_eq!(list("h=30:40:2.5"), vec![30.0, 32.5, 35.0, 37.5, 40.0]);
+        assert_eq!(list("k=5:19:5"), vec![5.0, 10.0, 15.0]);
+        assert_eq!(list("visc=1:1:1"), vec![1.0]);
+
+        // 0.003 - 0.001 is a little short of 2 steps in f32
+        let dt = list("dt=0.001:0.003:0.001");
+        assert_eq!(dt.len(), 3);
+        assert!((dt[2] - 0.003).abs() < 1e-6, "{:?}", dt);
+    }
+
+    #[test]
+    fn empty_ranges_are_rejected() {
+        for bad in ["k=20:5:5", "k=5:20:0", "k=5:20:-1", "k=5:20", "k=5..5", "k=6..5", "k=", "k=5,,6",
+                    "k=inf", "k", "speed=5"].iter() {
+            assert_eq!(Param::parse(bad).err().map(|e| e.code()), Some(EXIT_USAGE), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn params_are_swept_once() {
+        let error = options(&["--param", "h=30", "--param", "k=5", "--param", "h=35"]);
+        assert_eq!(error.err().map(|e| e.code()), Some(EXIT_USAGE));
+        let error = options(&["--param", "k=5..20"]);
+        assert_eq!(error.err().map(|e| e.code()), Some(EXIT_USAGE));
+        let error = options(&["--param", "k=5", "--metrics", "max_speed,top_speed"]);
+        assert_eq!(error.err().map(|e| e.code()), Some(EXIT_USAGE));
+    }
+
+    #[test]
+    fn every_combination_is_run() {
+        let options = options(&["--param", "h=30,35", "--param", "k=5:10:5"]).ok().unwrap();
+        assert_eq!(options.combinations(), vec![
+            vec![30.0, 5.0], vec![30.0, 10.0], vec![35.0, 5.0], vec![35.0, 10.0],
+        ]);
+        assert_eq!(options.label(&[30.0, 5.0]), "h=30 k=5");
+    }
+
+    #[test]
+    fn sampled_ranges_stay_inside() {
+        let args = ["--param", "k=5..20", "--param", "h=30,40", "--samples", "50", "--seed", "3"];
+        let combinations = options(&args).ok().unwrap().combinations();
+        assert_eq!(combinations.len(), 50);
+        assert!(combinations.iter().all(|c| c[0] >= 5.0 && c[0] < 20.0 && (c[1] == 30.0 || c[1] == 40.0)));
+        assert_eq!(options(&args).ok().unwrap().combinations(), combinations);
+    }
+
+    // A run that went wrong, with a message that needs escaping
+    fn summary(format: fn(&SweepOptions, &[Outcome]) -> String) -> String {
+        let options = options(&["--param", "h=30", "--metrics", "max_speed"]).ok().unwrap();
+        let outcome = Outcome {
+            values: vec![30.0],
+            status: Status::Unstable,
+            steps: 2,
+            time: 0.01,
+            wall_ms: 1.5,
+            peak_density_error: f32::NAN,
+            stats: StepStats { density_error_avg: 0.25, max_speed: f32::INFINITY, ..StepStats::default() },
+            message: "particle \"7\" at C:\\tmp\nisn't finite".to_string(),
+        };
+        format(&options, &[outcome])
+    }
+
+    #[test]
+    fn csv_leaves_non_finite_values_empty() {
+        let csv = summary(to_csv);
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines[0], "run,h,status,steps,time,wall_ms,density_error_avg,density_error_max,\
+                              density_error_peak,max_speed,message");
+        assert!(lines[1].starts_with("0,30,unstable,2,0.01,1.5,0.25,0,,,\"particle \"\"7\"\" at C:\\tmp"), "{}", csv);
+        assert_eq!(lines[2], "isn't finite\"");
+    }
+
+    #[test]
+    fn json_writes_non_finite_values_as_null() {
+        let json = summary(to_json);
+        assert!(json.contains(",\"density_error_peak\":null,\"max_speed\":null,"), "{}", json);
+        assert!(json.contains(",\"message\":\"particle \\\"7\\\" at C:\\\\tmp\\u000aisn't finite\"}"), "{}", json);
+        assert!(json.starts_with("[\n{\"run\":0,\"h\":30,\"status\":\"unstable\",\"steps\":2,"), "{}", json);
+    }
+}
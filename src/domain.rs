@@ -0,0 +1,49 @@
+use crate::util::*;
+
+// The rectangle the simulation runs in. Periodic axes wrap around, so that
+// anything leaving one side comes back in on the opposite side
+#[derive(Clone, Copy, Debug)]
+pub struct Domain {
+    pub width: f32,
+    pub height: f32,
+    pub periodic_x: bool,
+    pub periodic_y: bool,
+}
+
+impl Domain {
+    pub fn new(width: f32, height: f32) -> Domain {
+        Domain {
+            width,
+            height,
+            periodic_x: false,
+            periodic_y: false,
+        }
+    }
+
+    // Returns a - b. On periodic axes, b is replaced by whichever of its
+    // images is closest to a(the minimum image convention)
+    pub fn offset(&self, a: Vector2f, b: Vector2f) -> Vector2f {
+        let mut d = a - b;
+        if self.periodic_x {
+            d.x -= self.width * (d.x / self.width).round();
+        }
+        if self.periodic_y {
+            d.y -= self.height * (d.y / self.height).round();
+        }
+
+        d
+    }
+
+    // Brings a position back into the domain along the periodic axes
+    pub fn wrap(&self, p: Vector2f) -> Vector2f {
+        let x = if self.periodic_x { wrap_f32(p.x, self.width) } else { p.x };
+        let y = if self.periodic_y { wrap_f32(p.y, self.height) } else { p.y };
+        Vector2f::new(x, y)
+    }
+}
+
+// Wraps v into [0, size). rem_euclid can round up to exactly `size`
+fn wrap_f32(v: f32, size: f32) -> f32 {
+    let v = v.rem_euclid(size);
+    if v >= size { 0.0 } else { v }
+}
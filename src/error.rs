@@ -0,0 +1,83 @@
+use std::fmt;
+use wasm_bindgen::prelude::*;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum SpherroError {
+    // The universe or its config can't produce a valid simulation
+    InvalidConfig(String),
+    // Time steps must be positive and finite
+    InvalidTimeStep(f32),
+    // A particle's position or velocity stopped being finite during a step
+    Unstable { id: u32 },
+    // A despawn asked for more particles than there are in the universe
+    NotEnoughParticles { requested: usize, available: usize },
+    // There is no body with this index
+    InvalidBody(usize),
+    // The body force grid doesn't hold cols * rows (x, y) pairs
+    InvalidBodyForceGrid { cols: usize, rows: usize, len: usize },
+    // Only universes made with `Universe::new` can be recorded, before their
+    // first update
+    CannotRecord(String),
+    // A recording couldn't be read. Lines count from 1
+    InvalidRecording { line: usize, reason: String },
+}
+
+impl fmt::Display for SpherroError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SpherroError::InvalidConfig(reason) => {
+                write!(f, "invalid config: {}", reason)
+            },
+            SpherroError::InvalidTimeStep(dt) => {
+                write!(f, "invalid time step {}", dt)
+            },
+            SpherroError::Unstable { id } => {
+                write!(f, "simulation became unstable at particle {}", id)
+            },
+            SpherroError::NotEnoughParticles { requested, available } => {
+                write!(f, "cannot remove {} particles, only {} exist", requested, available)
+            },
+            SpherroError::InvalidBody(i) => {
+                write!(f, "no body with index {}", i)
+            },
+            SpherroError::InvalidBodyForceGrid { cols, rows, len } => {
+                write!(f, "a {}x{} body force grid needs {} floats, got {}", cols, rows, cols * rows * 2, len)
+            },
+            SpherroError::CannotRecord(reason) => {
+                write!(f, "cannot record: {}", reason)
+            },
+            SpherroError::InvalidRecording { line, reason } => {
+                write!(f, "invalid recording at line {}: {}", line, reason)
+            },
+        }
+    }
+}
+
+impl std::error::Error for SpherroError {}
+
+impl SpherroError {
+    // Name of the variant, so that callers in JS can tell errors apart
+    pub fn kind(&self) -> &'static str {
+        match self {
+            SpherroError::InvalidConfig(_) => "InvalidConfig",
+            SpherroError::InvalidTimeStep(_) => "InvalidTimeStep",
+            SpherroError::Unstable { .. } => "Unstable",
+            SpherroError::NotEnoughParticles { .. } => "NotEnoughParticles",
+            SpherroError::InvalidBody(_) => "InvalidBody",
+            SpherroError::InvalidBodyForceGrid { .. } => "InvalidBodyForceGrid",
+            SpherroError::CannotRecord(_) => "CannotRecord",
+            SpherroError::InvalidRecording { .. } => "InvalidRecording",
+        }
+    }
+}
+
+// Lets wasm_bindgen functions return Result<_, SpherroError>. The error is
+// thrown as a JS Error carrying the message above, with its `name` set to
+// the kind of error
+impl From<SpherroError> for JsValue {
+    fn from(error: SpherroError) -> JsValue {
+        let e = js_sys::Error::new(&error.to_string());
+        e.set_name(error.kind());
+        e.into()
+    }
+}
@@ -1,10 +1,24 @@
 use wasm_bindgen::prelude::*;
 use crate::universe::Universe;
+use crate::util::SEGMENT_STRIDE;
 
 // 2 floats for position
 // 2 floats for velocity
 const STRIDE: usize = 4;
 
+// 2 floats for the surface normal
+// 1 float that is 1.0 for free surface particles and 0.0 otherwise
+const SURFACE_STRIDE: usize = 3;
+
+// 2 floats for position, 1 float for angle
+// 2 floats for velocity, 1 float for angular velocity
+const BODY_STRIDE: usize = 6;
+
+// 2 floats for position
+// 1 float for age
+// 1 float for the emitter that released it, or -1.0
+const TRACER_STRIDE: usize = 4;
+
 // Fetches data from the universe into a buffer
 // for wasm to read. The point of this is to separate
 // the Universe's concern from the data format needed
@@ -12,6 +26,15 @@ const STRIDE: usize = 4;
 #[wasm_bindgen]
 pub struct Fetcher {
     buffer: Vec<f32>,
+    surface_buffer: Vec<f32>,
+    body_buffer: Vec<f32>,
+    id_buffer: Vec<u32>,
+    temperature_buffer: Vec<f32>,
+    vorticity_buffer: Vec<f32>,
+    strain_rate_buffer: Vec<f32>,
+    tracer_buffer: Vec<f32>,
+    pathline_buffer: Vec<f32>,
+    streakline_buffer: Vec<f32>,
 }
 
 #[wasm_bindgen]
@@ -19,6 +42,15 @@ impl Fetcher {
     pub fn new() -> Fetcher {
         Fetcher {
             buffer: Vec::new(),
+            surface_buffer: Vec::new(),
+            body_buffer: Vec::new(),
+            id_buffer: Vec::new(),
+            temperature_buffer: Vec::new(),
+            vorticity_buffer: Vec::new(),
+            strain_rate_buffer: Vec::new(),
+            tracer_buffer: Vec::new(),
+            pathline_buffer: Vec::new(),
+            streakline_buffer: Vec::new(),
         }
     }
 
@@ -38,4 +70,117 @@ impl Fetcher {
     pub fn stride(&self) -> usize {
         STRIDE
     }
-}
\ No newline at end of file
+
+    pub fn fetch_surface(&mut self, universe: &Universe) -> *const f32 {
+        self.surface_buffer.resize(universe.get_size() * SURFACE_STRIDE, 0.0);
+
+        for (i, pi) in universe.get_particles().iter().enumerate() {
+            self.surface_buffer[i*SURFACE_STRIDE] = pi.normal.x;
+            self.surface_buffer[i*SURFACE_STRIDE + 1] = pi.normal.y;
+            self.surface_buffer[i*SURFACE_STRIDE + 2] = if pi.is_surface { 1.0 } else { 0.0 };
+        }
+
+        self.surface_buffer.as_ptr()
+    }
+
+    pub fn surface_stride(&self) -> usize {
+        SURFACE_STRIDE
+    }
+
+    pub fn fetch_bodies(&mut self, universe: &Universe) -> *const f32 {
+        self.body_buffer.resize(universe.get_body_count() * BODY_STRIDE, 0.0);
+
+        for (i, body) in universe.get_bodies().iter().enumerate() {
+            self.body_buffer[i*BODY_STRIDE] = body.pos.x;
+            self.body_buffer[i*BODY_STRIDE + 1] = body.pos.y;
+            self.body_buffer[i*BODY_STRIDE + 2] = body.angle;
+            self.body_buffer[i*BODY_STRIDE + 3] = body.vel.x;
+            self.body_buffer[i*BODY_STRIDE + 4] = body.vel.y;
+            self.body_buffer[i*BODY_STRIDE + 5] = body.omega;
+        }
+
+        self.body_buffer.as_ptr()
+    }
+
+    pub fn body_stride(&self) -> usize {
+        BODY_STRIDE
+    }
+
+    // One id per particle, in the same order as `fetch`
+    pub fn fetch_ids(&mut self, universe: &Universe) -> *const u32 {
+        self.id_buffer.clear();
+        self.id_buffer.extend(universe.get_particles().iter().map(|pi| pi.id));
+
+        self.id_buffer.as_ptr()
+    }
+
+    // One temperature per particle, in the same order as `fetch`
+    pub fn fetch_temperature(&mut self, universe: &Universe) -> *const f32 {
+        self.temperature_buffer.clear();
+        self.temperature_buffer.extend(universe.get_particles().iter().map(|pi| pi.temperature));
+
+        self.temperature_buffer.as_ptr()
+    }
+
+    // One vorticity per particle, in the same order as `fetch`. Counter-
+    // clockwise positive
+    pub fn fetch_vorticity(&mut self, universe: &Universe) -> *const f32 {
+        self.vorticity_buffer.clear();
+        self.vorticity_buffer.extend(universe.get_particles().iter().map(|pi| pi.vorticity));
+
+        self.vorticity_buffer.as_ptr()
+    }
+
+    // One strain rate per particle, in the same order as `fetch`
+    pub fn fetch_strain_rate(&mut self, universe: &Universe) -> *const f32 {
+        self.strain_rate_buffer.clear();
+        self.strain_rate_buffer.extend(universe.get_particles().iter().map(|pi| pi.strain_rate));
+
+        self.strain_rate_buffer.as_ptr()
+    }
+
+    pub fn fetch_tracers(&mut self, universe: &Universe) -> *const f32 {
+        self.tracer_buffer.clear();
+        for tracer in universe.get_tracers().iter() {
+            let emitter = tracer.source.map_or(-1.0, |(e, _)| e as f32);
+            self.tracer_buffer.extend_from_slice(&[tracer.pos.x, tracer.pos.y, tracer.age, emitter]);
+        }
+
+        self.tracer_buffer.as_ptr()
+    }
+
+    pub fn tracer_stride(&self) -> usize {
+        TRACER_STRIDE
+    }
+
+    // Line segments along every tracer's recent path. The number of segments
+    // changes from frame to frame, see `pathline_count`
+    pub fn fetch_pathlines(&mut self, universe: &Universe) -> *const f32 {
+        self.pathline_buffer.clear();
+        universe.get_tracer_set().pathlines(&universe.domain(), &mut self.pathline_buffer);
+
+        self.pathline_buffer.as_ptr()
+    }
+
+    // Number of segments in the last `fetch_pathlines`
+    pub fn pathline_count(&self) -> usize {
+        self.pathline_buffer.len() / SEGMENT_STRIDE
+    }
+
+    // Line segments joining the tracers released from the same emitter point
+    pub fn fetch_streaklines(&mut self, universe: &Universe) -> *const f32 {
+        self.streakline_buffer.clear();
+        universe.get_tracer_set().streaklines(&universe.domain(), &mut self.streakline_buffer);
+
+        self.streakline_buffer.as_ptr()
+    }
+
+    // Number of segments in the last `fetch_streaklines`
+    pub fn streakline_count(&self) -> usize {
+        self.streakline_buffer.len() / SEGMENT_STRIDE
+    }
+
+    pub fn segment_stride(&self) -> usize {
+        SEGMENT_STRIDE
+    }
+}
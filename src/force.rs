@@ -1,7 +1,35 @@
 use wasm_bindgen::prelude::*;
+use cgmath::{InnerSpace};
 use crate::util::*;
+use crate::kernel::cubicspline_f;
 
+// What a force does to the particles within its radius
 #[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ForceKind {
+    Radial, // pushes away from the center, or pulls in with negative power
+    Jet, // pushes along (dir_x, dir_y), within a cone of half-angle `spread`
+    Vortex, // swirls counter-clockwise around the center, clockwise with negative power
+    Drag, // pulls the particle velocity towards (dir_x, dir_y)
+    Uniform, // pushes along (dir_x, dir_y) everywhere in the radius
+}
+
+// How the strength of a force drops with distance from its center
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Falloff {
+    Constant,
+    Linear,
+    InverseSquare,
+    Smooth,
+}
+
+// Below this distance from the center, the direction of a radial or
+// vortex force is undefined and the inverse square blows up
+const MIN_DIST: f32 = 1e-3;
+
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug)]
 pub struct Force {
     //TODO: using x,y instead of Vector2f because of lack of
     //wasm_bindgen in Vector2f
@@ -9,16 +37,71 @@ pub struct Force {
     pub y: f32,
     pub power: f32,
     pub r: f32,
+    pub kind: ForceKind,
+    pub falloff: Falloff,
+    // Direction for jets and uniform fields, target velocity for drag
+    pub dir_x: f32,
+    pub dir_y: f32,
+    pub spread: f32, // radians
 }
 
 #[wasm_bindgen]
 impl Force {
+    // The original push force: a radial repulsor with inverse square falloff
     pub fn new(x: f32, y: f32, power: f32, r: f32) -> Force {
+        Force::radial(x, y, power, r, Falloff::InverseSquare)
+    }
+
+    pub fn radial(x: f32, y: f32, power: f32, r: f32, falloff: Falloff) -> Force {
         Force{
-            x: x,
-            y: y,
-            power: power,
-            r: r,
+            x,
+            y,
+            power,
+            r,
+            kind: ForceKind::Radial,
+            falloff,
+            dir_x: 0.0,
+            dir_y: 0.0,
+            spread: 0.0,
+        }
+    }
+
+    // `angle` is the direction of the jet, in radians from the x axis
+    pub fn jet(x: f32, y: f32, angle: f32, spread: f32, power: f32, r: f32, falloff: Falloff) -> Force {
+        Force{
+            kind: ForceKind::Jet,
+            dir_x: angle.cos(),
+            dir_y: angle.sin(),
+            spread,
+            ..Force::radial(x, y, power, r, falloff)
+        }
+    }
+
+    pub fn vortex(x: f32, y: f32, power: f32, r: f32, falloff: Falloff) -> Force {
+        Force{
+            kind: ForceKind::Vortex,
+            ..Force::radial(x, y, power, r, falloff)
+        }
+    }
+
+    // `power` is the rate(per second) at which the velocity
+    // approaches (target_vx, target_vy)
+    pub fn drag(x: f32, y: f32, target_vx: f32, target_vy: f32,
+                power: f32, r: f32, falloff: Falloff) -> Force {
+        Force{
+            kind: ForceKind::Drag,
+            dir_x: target_vx,
+            dir_y: target_vy,
+            ..Force::radial(x, y, power, r, falloff)
+        }
+    }
+
+    pub fn uniform(x: f32, y: f32, dir_x: f32, dir_y: f32, power: f32, r: f32) -> Force {
+        Force{
+            kind: ForceKind::Uniform,
+            dir_x,
+            dir_y,
+            ..Force::radial(x, y, power, r, Falloff::Constant)
         }
     }
 }
@@ -27,4 +110,43 @@ impl Force {
     pub fn pos(&self) -> Vector2f {
         Vector2f::new(self.x, self.y)
     }
-}
\ No newline at end of file
+
+    // Acceleration on a particle at `pos` moving at `vel`. This is always
+    // finite, including exactly at the center of the force
+    pub fn acceleration(&self, pos: Vector2f, vel: Vector2f) -> Vector2f {
+        let offset = pos - self.pos();
+        let dist = offset.magnitude();
+        if dist > self.r {
+            return vec2f_zero();
+        }
+
+        let radial = if dist > MIN_DIST { offset / dist } else { vec2f_zero() };
+        let dir = Vector2f::new(self.dir_x, self.dir_y);
+        let dir = if dir.magnitude2() > 0.0 { dir.normalize() } else { vec2f_zero() };
+
+        let strength = self.power * self.falloff_weight(dist);
+        match self.kind {
+            ForceKind::Radial => radial * strength,
+            ForceKind::Jet => {
+                // Particles right at the nozzle are always inside the cone
+                let inside = dist <= MIN_DIST || radial.dot(dir) >= self.spread.cos();
+                if inside { dir * strength } else { vec2f_zero() }
+            },
+            ForceKind::Vortex => Vector2f::new(-radial.y, radial.x) * strength,
+            ForceKind::Drag => {
+                let target = Vector2f::new(self.dir_x, self.dir_y);
+                (target - vel) * strength
+            },
+            ForceKind::Uniform => dir * strength,
+        }
+    }
+
+    fn falloff_weight(&self, dist: f32) -> f32 {
+        match self.falloff {
+            Falloff::Constant => 1.0,
+            Falloff::Linear => (1.0 - dist / self.r).max(0.0),
+            Falloff::InverseSquare => 1.0 / dist.max(MIN_DIST).powi(2),
+            Falloff::Smooth => cubicspline_f(2.0 * dist / self.r) / cubicspline_f(0.0),
+        }
+    }
+}
@@ -0,0 +1,63 @@
+use wasm_bindgen::prelude::*;
+use cgmath::{InnerSpace};
+use crate::util::*;
+
+// The walls of the tank
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Wall {
+    Left,
+    Right,
+    Bottom,
+    Top,
+}
+
+// Where a heat source acts
+#[derive(Clone, Debug)]
+pub enum HeatZone {
+    // The rectangle spanning the two corners
+    Rect(Vector2f, Vector2f),
+    Circle(Vector2f, f32),
+    // Particles closer to the wall than the distance
+    Wall(Wall, f32),
+    // Particles touching the rigid body, ie. with any of its boundary
+    // particles within their kernel
+    Body(usize),
+}
+
+// Pulls the temperature of the particles in its zone towards `temperature`,
+// closing the gap at `rate` per second. A sink is just a source that is
+// colder than the fluid
+#[derive(Clone, Debug)]
+pub struct HeatSource {
+    pub zone: HeatZone,
+    pub temperature: f32,
+    pub rate: f32,
+}
+
+impl HeatSource {
+    // `touches(b)` tells whether the particle touches body `b`
+    pub fn contains<F>(&self, pos: Vector2f, width: f32, height: f32, touches: F) -> bool
+            where F: Fn(usize) -> bool {
+        match self.zone {
+            HeatZone::Rect(a, b) => {
+                pos.x >= a.x.min(b.x) && pos.x <= a.x.max(b.x)
+                && pos.y >= a.y.min(b.y) && pos.y <= a.y.max(b.y)
+            },
+            HeatZone::Circle(center, r) => (pos - center).magnitude2() <= r * r,
+            HeatZone::Wall(wall, d) => match wall {
+                Wall::Left => pos.x < d,
+                Wall::Right => width - pos.x < d,
+                Wall::Bottom => pos.y < d,
+                Wall::Top => height - pos.y < d,
+            },
+            HeatZone::Body(b) => touches(b),
+        }
+    }
+
+    // Change in temperature over `dt` for a particle at `t`. This is exact
+    // for a constant source temperature, so large rates can't overshoot it
+    pub fn heat(&self, t: f32, dt: f32) -> f32 {
+        (self.temperature - t) * (1.0 - (-self.rate * dt).exp())
+    }
+}
@@ -1,13 +1,15 @@
 use wasm_bindgen::prelude::*;
 use crate::Particle;
 use crate::util::*;
+use crate::error::SpherroError;
 
 #[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Config {
-    width_frac: f32,
-    height_frac: f32,
-    rows: usize,
-    cols: usize,
+    pub(crate) width_frac: f32,
+    pub(crate) height_frac: f32,
+    pub(crate) rows: usize,
+    pub(crate) cols: usize,
 }
 
 #[wasm_bindgen]
@@ -22,8 +24,29 @@ impl Config {
     }
 }
 
+impl Config {
+    pub fn validate(&self) -> Result<(), SpherroError> {
+        let in_range = |f: f32| f > 0.0 && f <= 1.0;
+        if !in_range(self.width_frac) || !in_range(self.height_frac) {
+            return Err(SpherroError::InvalidConfig(format!(
+                "width and height fractions must be in (0, 1], got {} and {}",
+                self.width_frac, self.height_frac,
+            )));
+        }
+
+        if self.rows == 0 || self.cols == 0 {
+            return Err(SpherroError::InvalidConfig(format!(
+                "need at least one row and column, got {}x{}", self.rows, self.cols,
+            )));
+        }
+
+        Ok(())
+    }
+}
+
 // Creates particles arranged in rows and columns delimited by the fractions
-// of width and height provided by the config. Cleaner ways of implementing
+// of width and height provided by the config. Particles are given ids
+// counting up from 0. Cleaner ways of implementing
 // this are difficult because wasm_bindgen doesn't support traits and non-C style
 // enums yet
 pub fn initialize(config: &Config, width: f32, height: f32, particle_mass: f32) -> Vec<Particle> {
@@ -41,15 +64,8 @@ pub fn initialize(config: &Config, width: f32, height: f32, particle_mass: f32)
             let y = (y_spacing * j) as f32;
 
             let position = Vector2f::new(x, y);
-            let color = Color::new(0.0, 0.0, 1.0);
-            particles.push(Particle{
-                pos: position,
-                col: color,
-                vel: vec2f_zero(),
-                mass: particle_mass,
-                rho: 0.0,
-                pressure: 0.0
-            });
+            let id = particles.len() as u32;
+            particles.push(Particle::new(id, position, particle_mass));
         }
     }
 
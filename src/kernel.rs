@@ -1,4 +1,5 @@
 use std::f32::consts::PI;
+use wasm_bindgen::prelude::*;
 
 // https://pysph.readthedocs.io/en/latest/reference/kernels.html
 const _SIGMA_1D: f32 = 2.0 / 3.0;
@@ -7,6 +8,35 @@ const _SIGMA_3D: f32 = 1.0 / PI;
 
 const SIGMA: f32 = SIGMA_2D;
 
+const WENDLAND_SIGMA_2D: f32 = 7.0 / (4.0 * PI);
+
+// Kernel the solver computes densities and forces with. Both have a support
+// radius of 2H
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SmoothingKernel {
+    CubicSpline,
+    WendlandC2, // doesn't let particles clump together under tension
+}
+
+impl SmoothingKernel {
+    #[inline]
+    pub(crate) fn f(self, q: f32) -> f32 {
+        match self {
+            SmoothingKernel::CubicSpline => cubicspline_f(q),
+            SmoothingKernel::WendlandC2 => wendland_f(q),
+        }
+    }
+
+    #[inline]
+    pub(crate) fn df(self, q: f32) -> f32 {
+        match self {
+            SmoothingKernel::CubicSpline => cubicspline_df(q),
+            SmoothingKernel::WendlandC2 => wendland_df(q),
+        }
+    }
+}
+
 #[inline]
 pub fn cubicspline_f(q: f32) -> f32 {
     if 0.0 <= q && q < 1.0 {
@@ -27,4 +57,95 @@ pub fn cubicspline_df(q: f32) -> f32 {
     } else {
         0.0
     }
-}
\ No newline at end of file
+}
+
+// Wendland C2 kernel(Wendland 1995), the 2D one called WendlandQuintic
+// in pysph
+#[inline]
+pub fn wendland_f(q: f32) -> f32 {
+    if (0.0..2.0).contains(&q) {
+        WENDLAND_SIGMA_2D * (1.0 - q / 2.0).powi(4) * (2.0 * q + 1.0)
+    } else {
+        0.0
+    }
+}
+
+#[inline]
+pub fn wendland_df(q: f32) -> f32 {
+    if (0.0..2.0).contains(&q) {
+        -5.0 * WENDLAND_SIGMA_2D * q * (1.0 - q / 2.0).powi(3)
+    } else {
+        0.0
+    }
+}
+
+// Poly6 kernel(Müller et al. 2003) normalized in 2D, with a support radius
+// of 1. Cheaper and smoother than the cubic spline, but with no use for
+// gradients since it flattens out at the center
+#[inline]
+pub fn poly6_f(q: f32) -> f32 {
+    if q >= 1.0 {
+        return 0.0;
+    }
+
+    (4.0 / PI) * (1.0 - q * q).powi(3)
+}
+
+// Cohesion spline from "Versatile Surface Tension and Adhesion for SPH
+// Fluids"(Akinci et al. 2013). `c` is the support radius
+#[inline]
+pub fn cohesion_f(r: f32, c: f32) -> f32 {
+    let sigma = 32.0 / (PI * c.powi(9));
+    if 2.0 * r > c && r <= c {
+        sigma * (c - r).powi(3) * r.powi(3)
+    } else if r > 0.0 && 2.0 * r <= c {
+        sigma * (2.0 * (c - r).powi(3) * r.powi(3) - c.powi(6) / 64.0)
+    } else {
+        0.0
+    }
+}
+
+// Adhesion spline from the same paper. Only particles in the outer half of
+// the support radius are attracted
+#[inline]
+pub fn adhesion_f(r: f32, c: f32) -> f32 {
+    if 2.0 * r > c && r <= c {
+        (0.007 / c.powf(3.25)) * (-4.0 * r * r / c + 6.0 * r - 2.0 * c).powf(0.25)
+    } else {
+        0.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const C: f32 = 70.0;
+
+    #[test]
+    fn cohesion_repels_close_and_attracts_far() {
+        assert!(cohesion_f(0.05 * C, C) < 0.0);
+        assert!(cohesion_f(0.75 * C, C) > 0.0);
+        assert_eq!(cohesion_f(0.0, C), 0.0);
+        assert_eq!(cohesion_f(1.01 * C, C), 0.0);
+        assert!(cohesion_f(0.999 * C, C).abs() < 1e-6 * cohesion_f(0.5 * C, C));
+
+        // Both pieces meet at c/2, where the attraction peaks
+        let (below, at, above) = (cohesion_f(0.499 * C, C), cohesion_f(0.5 * C, C), cohesion_f(0.501 * C, C));
+        assert!((below - at).abs() < 1e-3 * at && (above - at).abs() < 1e-3 * at);
+        assert!(at > cohesion_f(0.4 * C, C) && at > cohesion_f(0.6 * C, C));
+    }
+
+    #[test]
+    fn adhesion_only_attracts_the_outer_half() {
+        assert_eq!(adhesion_f(0.25 * C, C), 0.0);
+        assert_eq!(adhesion_f(0.5 * C, C), 0.0);
+        assert_eq!(adhesion_f(1.01 * C, C), 0.0);
+        assert!(adhesion_f(C, C) < 1e-3 * adhesion_f(0.75 * C, C));
+
+        // Peaks at 3c/4
+        let peak = adhesion_f(0.75 * C, C);
+        assert!(peak > 0.0);
+        assert!(peak > adhesion_f(0.7 * C, C) && peak > adhesion_f(0.8 * C, C));
+    }
+}
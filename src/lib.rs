@@ -6,18 +6,51 @@ pub mod util; //TODO: make this private
 extern crate rand;
 
 mod accelerators;
+mod domain;
 mod particle;
 mod universe;
 mod kernel;
 mod fetcher;
 mod force;
+mod surface;
+mod rigid;
+mod body_force;
+mod removal;
+mod error;
+mod stability;
+mod stats;
+mod heat;
+mod reconstruction;
+mod sampling;
+mod tracers;
+mod viscosity;
+mod rheology;
+mod recorder;
+mod solver;
 pub mod initializer;
+pub mod scenarios;
 
 // Re-export some names for flatter syntax
 pub use particle::Particle;
 pub use universe::Universe;
 pub use fetcher::Fetcher;
-pub use force::Force;
+pub use force::{Force, ForceKind, Falloff};
+pub use surface::SurfaceClassifier;
+pub use rigid::{RigidBody, Shape, Keyframe};
+pub use body_force::BodyForcePreset;
+pub use removal::Removal;
+pub use error::SpherroError;
+pub use stability::{StabilityCheck, Recovery, Trip};
+pub use stats::StepStats;
+pub use heat::{HeatSource, HeatZone, Wall};
+pub use reconstruction::{SurfaceReconstructor, FieldKernel};
+pub use sampling::{FieldSample, RASTER_STRIDE};
+pub use tracers::{Tracer, TracerIntegrator};
+pub use viscosity::{Viscosity, ViscosityModel};
+pub use rheology::{Rheology, RheologyPreset};
+pub use recorder::{Input, Recording, Replayer};
+pub use kernel::SmoothingKernel;
+pub use solver::PressureSolver;
 pub use initializer::Config;
 
 // When the `wee_alloc` feature is enabled, use `wee_alloc` as the global
@@ -1,22 +1,69 @@
-use crate::util::{Vector2f, Color};
+use crate::util::{Vector2f, Color, vec2f_zero};
 use crate::accelerators::{HasPosition};
 
 #[repr(C)]
 #[derive(Clone, Debug)]
 pub struct Particle {
+    // Unique for the lifetime of a Universe, unlike the particle's index
+    // which changes whenever particles are removed
+    pub id: u32,
     pub pos: Vector2f,
     pub vel: Vector2f,
     pub mass: f32,
     pub rho: f32,
     pub pressure: f32,
 
+    // Only drives the fluid through buoyancy, so the units and the zero
+    // point are up to the user
+    pub temperature: f32,
+
+    // Curl of the velocity, counter-clockwise positive
+    pub vorticity: f32,
+
+    // Magnitude of the strain rate tensor, sqrt(2 D:D)
+    pub strain_rate: f32,
+
+    // Deviatoric stress(xx, xy, yy) carried by granular particles, in the
+    // solver's pressure units. Zero for everything else
+    pub stress: [f32; 3],
+
+    // Selects the particle's rheology. Particles are phase 0 unless told
+    // otherwise
+    pub phase: u32,
+
+    // Scaled gradient of the smoothed colour field(Akinci et al. 2013). This
+    // points into the fluid and is close to zero inside the bulk
+    pub normal: Vector2f,
+    pub is_surface: bool,
+
     // The color is more of a way to debug things than an actual property
     // of the particle.
     pub col: Color,
 }
 
+impl Particle {
+    pub fn new(id: u32, pos: Vector2f, mass: f32) -> Particle {
+        Particle {
+            id,
+            pos,
+            vel: vec2f_zero(),
+            mass,
+            rho: 0.0,
+            pressure: 0.0,
+            temperature: 0.0,
+            vorticity: 0.0,
+            strain_rate: 0.0,
+            stress: [0.0; 3],
+            phase: 0,
+            normal: vec2f_zero(),
+            is_surface: false,
+            col: Color::new(0.0, 0.0, 1.0),
+        }
+    }
+}
+
 impl HasPosition for Particle {
     fn position(&self) -> Vector2f {
         self.pos
     }
-}
\ No newline at end of file
+}
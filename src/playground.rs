@@ -7,9 +7,13 @@ fn main() {
     let config = spherro::Config::new(0.4, 0.8, 50, 10);
     let mut universe = spherro::Universe::new(
         600.0, 600.0, &config,
-    );
+    ).unwrap();
 
-    for _ in 0..10000 {
-        universe.update(0.001)
+    for i in 0..10000 {
+        universe.update(0.001).unwrap();
+
+        if i % 1000 == 0 {
+            println!("{}", universe.get_stats().to_json());
+        }
     }
 }
\ No newline at end of file
@@ -0,0 +1,405 @@
+use wasm_bindgen::prelude::*;
+use cgmath::{InnerSpace};
+use crate::util::*;
+use crate::accelerators::{Accelerator, Grid};
+use crate::kernel::{cubicspline_f, poly6_f};
+use crate::universe::Universe;
+use crate::error::SpherroError;
+
+// 2 floats for each corner of a triangle
+const TRIANGLE_STRIDE: usize = 6;
+
+// Anisotropic kernels are stretched at most this much more along one axis
+// than along the other
+const MAX_ANISOTROPY: f32 = 4.0;
+
+// Particles with fewer neighbours keep a round kernel, since their
+// covariance is too noisy to go by
+const MIN_ANISOTROPIC_NEIGHBOURS: usize = 6;
+
+// Kernel the colour field is built with
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FieldKernel {
+    CubicSpline,
+    Poly6,
+}
+
+// Both kernels reach out to 2h
+fn kernel_w(kernel: FieldKernel, r: f32, h: f32) -> f32 {
+    let radius = 2.0 * h;
+    match kernel {
+        FieldKernel::CubicSpline => cubicspline_f(r / h) / (h * h),
+        FieldKernel::Poly6 => poly6_f(r / radius) / (radius * radius),
+    }
+}
+
+// Linear map applied to offsets from a particle before evaluating its kernel.
+// It is symmetric with a determinant of 1, so it reshapes the kernel into an
+// ellipse without changing its volume. `reach` is the longest semi-axis
+// relative to the support radius
+#[derive(Clone, Copy)]
+struct Stretch {
+    xx: f32,
+    xy: f32,
+    yy: f32,
+    reach: f32,
+}
+
+impl Stretch {
+    fn round() -> Stretch {
+        Stretch { xx: 1.0, xy: 0.0, yy: 1.0, reach: 1.0 }
+    }
+
+    fn apply(&self, d: Vector2f) -> Vector2f {
+        Vector2f::new(self.xx * d.x + self.xy * d.y, self.xy * d.x + self.yy * d.y)
+    }
+}
+
+// Builds a colour field from the particles on a grid spanning the universe,
+// and extracts the contours where it crosses `iso` with marching squares.
+// The contours come out as line segments and as a triangulated fill of the
+// inside, as flat buffers for wasm to read
+#[wasm_bindgen]
+pub struct SurfaceReconstructor {
+    resolution: f32,
+    iso: f32,
+    kernel: FieldKernel,
+    anisotropic: bool,
+    cols: usize, // number of cells
+    rows: usize,
+    cell: Vector2f, // size of the cells after rounding
+    field: Vec<f32>,
+    segments: Vec<f32>,
+    triangles: Vec<f32>,
+}
+
+#[wasm_bindgen]
+impl SurfaceReconstructor {
+    // `resolution` is the size of the grid cells, which is rounded so that
+    // the cells fit the universe exactly. The colour field is close to 1
+    // inside settled fluid and falls to 0 across the surface, so an `iso`
+    // of 0.5 follows it
+    pub fn new(resolution: f32, iso: f32) -> Result<SurfaceReconstructor, SpherroError> {
+        let mut reconstructor = SurfaceReconstructor {
+            resolution: 1.0,
+            iso,
+            kernel: FieldKernel::CubicSpline,
+            anisotropic: false,
+            cols: 0,
+            rows: 0,
+            cell: vec2f_zero(),
+            field: Vec::new(),
+            segments: Vec::new(),
+            triangles: Vec::new(),
+        };
+        reconstructor.set_resolution(resolution)?;
+
+        Ok(reconstructor)
+    }
+
+    pub fn set_resolution(&mut self, resolution: f32) -> Result<(), SpherroError> {
+        if !(resolution.is_finite() && resolution > 0.0) {
+            return Err(SpherroError::InvalidConfig(format!(
+                "resolution must be positive, got {}", resolution,
+            )));
+        }
+
+        self.resolution = resolution;
+        Ok(())
+    }
+
+    pub fn set_iso(&mut self, iso: f32) {
+        self.iso = iso;
+    }
+
+    pub fn set_kernel(&mut self, kernel: FieldKernel) {
+        self.kernel = kernel;
+    }
+
+    // Stretches each particle's kernel along the spread of its neighbours
+    // (Yu & Turk 2013), which flattens the bumps that round kernels leave
+    // along the surface
+    pub fn set_anisotropic(&mut self, anisotropic: bool) {
+        self.anisotropic = anisotropic;
+    }
+
+    // Rebuilds the field and the contours from the universe's particles
+    pub fn update(&mut self, universe: &Universe) {
+        self.build_field(universe);
+        self.extract_contours();
+    }
+
+    pub fn segments(&self) -> *const f32 {
+        self.segments.as_ptr()
+    }
+
+    pub fn segment_count(&self) -> usize {
+        self.segments.len() / SEGMENT_STRIDE
+    }
+
+    pub fn segment_stride(&self) -> usize {
+        SEGMENT_STRIDE
+    }
+
+    pub fn triangles(&self) -> *const f32 {
+        self.triangles.as_ptr()
+    }
+
+    pub fn triangle_count(&self) -> usize {
+        self.triangles.len() / TRIANGLE_STRIDE
+    }
+
+    pub fn triangle_stride(&self) -> usize {
+        TRIANGLE_STRIDE
+    }
+
+    // Field values at the grid nodes, row by row from the bottom left
+    // corner of the universe
+    pub fn field(&self) -> *const f32 {
+        self.field.as_ptr()
+    }
+
+    // Number of grid nodes along x
+    pub fn field_cols(&self) -> usize {
+        self.cols + 1
+    }
+
+    // Number of grid nodes along y
+    pub fn field_rows(&self) -> usize {
+        self.rows + 1
+    }
+}
+
+impl SurfaceReconstructor {
+    // Each segment is (x0, y0, x1, y1), oriented so that the inside is on
+    // its left. Segments of a contour share their end points exactly
+    pub fn get_segments(&self) -> &[f32] {
+        &self.segments
+    }
+
+    // Each triangle is three (x, y) corners, counter-clockwise
+    pub fn get_triangles(&self) -> &[f32] {
+        &self.triangles
+    }
+
+    pub fn get_field(&self) -> &[f32] {
+        &self.field
+    }
+
+    fn build_field(&mut self, universe: &Universe) {
+        let particles = universe.get_particles();
+        let domain = universe.domain();
+
+        self.cols = ((domain.width / self.resolution).ceil() as usize).max(1);
+        self.rows = ((domain.height / self.resolution).ceil() as usize).max(1);
+        self.cell = Vector2f::new(domain.width / self.cols as f32, domain.height / self.rows as f32);
+        let (dx, dy) = (self.cell.x, self.cell.y);
+        let nx = self.cols + 1;
+
+        self.field.clear();
+        self.field.resize(nx * (self.rows + 1), 0.0);
+
+        // The smoothing length and the settled density can be changed on the
+        // universe, so they are read from it on every update
+        let h = universe.get_smoothing_length();
+        let radius = 2.0 * h;
+        let area_density = universe.fluid_area_density();
+
+        let grid = Grid::with_domain(domain, h, particles);
+        let kernel = self.kernel;
+
+        for (i, pi) in particles.iter().enumerate() {
+            let stretch = if self.anisotropic {
+                let offsets: Vec<Vector2f> = grid.nearest_by_idx(i, radius)
+                                                 .iter()
+                                                 .map(|&j| domain.offset(particles[j].pos, pi.pos))
+                                                 .collect();
+                anisotropic_stretch(&offsets, radius)
+            } else {
+                Stretch::round()
+            };
+
+            // The area the particle takes up in settled fluid. Using the
+            // actual density instead would inflate the sparse particles
+            // along the surface and push the contour outwards
+            let volume = pi.mass / area_density;
+            let reach = radius * stretch.reach;
+
+            // Node ranges covering the kernel. Along periodic axes these can
+            // run past the edges, and wrap around
+            let range = |p: f32, d: f32, n: usize, periodic: bool| {
+                let lo = ((p - reach) / d).floor() as isize;
+                let hi = ((p + reach) / d).ceil() as isize;
+                if periodic { (lo, hi) } else { (lo.max(0), hi.min(n as isize)) }
+            };
+            let (x0, x1) = range(pi.pos.x, dx, self.cols, domain.periodic_x);
+            let (y0, y1) = range(pi.pos.y, dy, self.rows, domain.periodic_y);
+
+            for y in y0..y1+1 {
+                for x in x0..x1+1 {
+                    let d = Vector2f::new(x as f32 * dx, y as f32 * dy) - pi.pos;
+                    let w = kernel_w(kernel, stretch.apply(d).magnitude(), h);
+                    if w == 0.0 {
+                        continue;
+                    }
+
+                    let x = if domain.periodic_x { x.rem_euclid(self.cols as isize) } else { x } as usize;
+                    let y = if domain.periodic_y { y.rem_euclid(self.rows as isize) } else { y } as usize;
+                    self.field[y * nx + x] += volume * w;
+                }
+            }
+        }
+
+        // The last node along a periodic axis is the first one again
+        if domain.periodic_x {
+            for y in 0..self.rows+1 {
+                self.field[y * nx + self.cols] = self.field[y * nx];
+            }
+        }
+        if domain.periodic_y {
+            for x in 0..nx {
+                self.field[self.rows * nx + x] = self.field[x];
+            }
+        }
+    }
+
+    fn extract_contours(&mut self) {
+        self.segments.clear();
+        self.triangles.clear();
+
+        let nx = self.cols + 1;
+        let (dx, dy) = (self.cell.x, self.cell.y);
+        let node = |x: usize, y: usize| Vector2f::new(x as f32 * dx, y as f32 * dy);
+
+        for y in 0..self.rows {
+            for x in 0..self.cols {
+                // Corners counter-clockwise from the bottom left
+                let corners = [(x, y), (x + 1, y), (x + 1, y + 1), (x, y + 1)];
+                let v: Vec<f32> = corners.iter().map(|&(i, j)| self.field[j * nx + i] - self.iso).collect();
+                let inside: Vec<bool> = v.iter().map(|&v| v > 0.0).collect();
+
+                let count = inside.iter().filter(|&&b| b).count();
+                if count == 0 {
+                    continue;
+                }
+
+                // Walk around the cell, collecting the outline of the inside.
+                // Crossings are interpolated from the left or bottom node of
+                // their edge, so that neighbouring cells agree on them exactly
+                let mut ring = Vec::with_capacity(6);
+                let mut exits = Vec::with_capacity(2);
+                let mut entries = Vec::with_capacity(2);
+                for k in 0..4 {
+                    let next = (k + 1) % 4;
+                    let a = corners[k];
+                    if inside[k] {
+                        ring.push(node(a.0, a.1));
+                    }
+                    if inside[k] != inside[next] {
+                        let (first, second) = if k < 2 { (k, next) } else { (next, k) };
+                        let (pa, pb) = (corners[first], corners[second]);
+                        let t = v[first] / (v[first] - v[second]);
+                        let p = node(pa.0, pa.1) + (node(pb.0, pb.1) - node(pa.0, pa.1)) * t;
+
+                        if inside[k] { exits.push(ring.len()) } else { entries.push(ring.len()) }
+                        ring.push(p);
+                    }
+                }
+
+                // Diagonally opposite corners inside are either joined through
+                // the middle of the cell or cut off from each other
+                let saddle = count == 2 && inside[0] == inside[2];
+                let joined = !saddle || v.iter().sum::<f32>() > 0.0;
+
+                // Joined, the contour leaving at an exit comes back in at the
+                // next entry around the cell. Split, at the previous one
+                for &exit in exits.iter() {
+                    let entry = if joined {
+                        entries.iter().find(|&&e| e > exit).unwrap_or(&entries[0])
+                    } else {
+                        entries.iter().rev().find(|&&e| e < exit).unwrap_or(&entries[entries.len() - 1])
+                    };
+                    self.push_segment(ring[exit], ring[*entry]);
+                }
+
+                if joined {
+                    // The inside of a cell is convex unless it's split
+                    for k in 1..ring.len()-1 {
+                        self.push_triangle(ring[0], ring[k], ring[k + 1]);
+                    }
+                } else {
+                    // Each inside corner is cut off with its two crossings
+                    for k in 0..ring.len() {
+                        if !exits.contains(&k) && !entries.contains(&k) {
+                            let n = ring.len();
+                            self.push_triangle(ring[(k + n - 1) % n], ring[k], ring[(k + 1) % n]);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn push_segment(&mut self, a: Vector2f, b: Vector2f) {
+        self.segments.extend_from_slice(&[a.x, a.y, b.x, b.y]);
+    }
+
+    fn push_triangle(&mut self, a: Vector2f, b: Vector2f, c: Vector2f) {
+        self.triangles.extend_from_slice(&[a.x, a.y, b.x, b.y, c.x, c.y]);
+    }
+}
+
+// Stretch that lines a kernel up with the spread of the neighbours, given
+// their offsets from the particle. The spread is the weighted covariance of
+// the neighbourhood, and the kernel's semi-axes follow its standard deviations.
+// The neighbours are weighted down to 0 at `radius`
+fn anisotropic_stretch(offsets: &[Vector2f], radius: f32) -> Stretch {
+    if offsets.len() < MIN_ANISOTROPIC_NEIGHBOURS {
+        return Stretch::round();
+    }
+
+    // The particle itself is at the origin, with a weight of 1
+    let weight = |d: Vector2f| 1.0 - (d.magnitude() / radius).powi(3);
+    let total = 1.0 + offsets.iter().map(|&d| weight(d)).sum::<f32>();
+    let mean = offsets.iter().map(|&d| weight(d) * d).sum::<Vector2f>() / total;
+
+    let (mut cxx, mut cxy, mut cyy) = (0.0, 0.0, 0.0);
+    for d in offsets.iter().map(|&d| (d, weight(d))).chain(std::iter::once((vec2f_zero(), 1.0))) {
+        let (d, w) = (d.0 - mean, d.1);
+        cxx += w * d.x * d.x;
+        cxy += w * d.x * d.y;
+        cyy += w * d.y * d.y;
+    }
+
+    // Eigenvalues of the symmetric 2x2 covariance matrix
+    let tr = cxx + cyy;
+    let det = cxx * cyy - cxy * cxy;
+    let disc = (tr * tr / 4.0 - det).max(0.0).sqrt();
+    let (l1, l2) = (tr / 2.0 + disc, tr / 2.0 - disc);
+    if l1 <= 0.0 {
+        return Stretch::round();
+    }
+
+    let s1 = l1.sqrt();
+    let s2 = l2.max(0.0).sqrt().max(s1 / MAX_ANISOTROPY);
+    let g = (s1 * s2).sqrt();
+    let (s1, s2) = (s1 / g, s2 / g);
+
+    // Direction of the larger eigenvalue
+    let e = if cxy.abs() > 1e-6 * tr {
+        Vector2f::new(l1 - cyy, cxy).normalize()
+    } else if cxx >= cyy {
+        Vector2f::new(1.0, 0.0)
+    } else {
+        Vector2f::new(0.0, 1.0)
+    };
+    let (c, s) = (e.x, e.y);
+
+    Stretch {
+        xx: c * c / s1 + s * s / s2,
+        xy: c * s * (1.0 / s1 - 1.0 / s2),
+        yy: s * s / s1 + c * c / s2,
+        reach: s1,
+    }
+}
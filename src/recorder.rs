@@ -0,0 +1,702 @@
+use std::fmt::Write;
+use crate::util::Vector2f;
+use crate::universe::Universe;
+use crate::initializer::Config;
+use crate::error::SpherroError;
+use crate::force::{Force, ForceKind, Falloff};
+use crate::surface::SurfaceClassifier;
+use crate::body_force::BodyForcePreset;
+use crate::heat::Wall;
+use crate::rheology::{Rheology, RheologyPreset};
+use crate::tracers::TracerIntegrator;
+use crate::kernel::SmoothingKernel;
+use crate::solver::PressureSolver;
+use crate::removal::Removal;
+use crate::viscosity::Viscosity;
+
+// First word of every recording, followed by the format version
+const MAGIC: &str = "spherro-recording";
+const VERSION: u32 = 1;
+
+// The wasm enums are written as their index in these
+const FORCE_KINDS: [ForceKind; 5] = [ForceKind::Radial, ForceKind::Jet, ForceKind::Vortex,
+                                     ForceKind::Drag, ForceKind::Uniform];
+const FALLOFFS: [Falloff; 4] = [Falloff::Constant, Falloff::Linear, Falloff::InverseSquare, Falloff::Smooth];
+const CLASSIFIERS: [SurfaceClassifier; 3] = [SurfaceClassifier::ColorField, SurfaceClassifier::NeighbourCount,
+                                             SurfaceClassifier::Covariance];
+const BODY_FORCE_PRESETS: [BodyForcePreset; 4] = [BodyForcePreset::Swirl, BodyForcePreset::Shear,
+                                                  BodyForcePreset::Shake, BodyForcePreset::Central];
+const WALLS: [Wall; 4] = [Wall::Left, Wall::Right, Wall::Bottom, Wall::Top];
+const RHEOLOGY_PRESETS: [RheologyPreset; 5] = [RheologyPreset::Honey, RheologyPreset::Ketchup,
+                                               RheologyPreset::Cornstarch, RheologyPreset::Sand,
+                                               RheologyPreset::Snow];
+const INTEGRATORS: [TracerIntegrator; 2] = [TracerIntegrator::Rk2, TracerIntegrator::Rk4];
+const KERNELS: [SmoothingKernel; 2] = [SmoothingKernel::CubicSpline, SmoothingKernel::WendlandC2];
+const PRESSURE_SOLVERS: [PressureSolver; 2] = [PressureSolver::Fixed, PressureSolver::Adaptive];
+
+// A call on the universe's wasm API that changes it, with its arguments.
+// Calls that take a callback, like `set_body_force`, can't be recorded. `Update` stands for `count` updates with the same dt
+#[derive(Clone, Debug)]
+pub enum Input {
+    Update { dt: f32, count: u32 },
+    SetSeed(u32),
+    AddForce(Force),
+    ClearForces,
+    SetSurfaceTension(f32),
+    SetAdhesion(f32),
+    SetPeriodic(bool, bool),
+    SetSurfaceClassifier(SurfaceClassifier),
+    AddBoxBody(f32, f32, f32, f32, f32),
+    AddDiscBody(f32, f32, f32, f32),
+    AddBoxObstacle(f32, f32, f32, f32),
+    AddDiscObstacle(f32, f32, f32),
+    SetObstacleTarget(usize, f32, f32, f32),
+    AddObstacleKeyframe(usize, f32, f32, f32, f32),
+    SetObstacleLooping(usize, bool),
+    ClearBodies,
+    SetViscosity(f32),
+    SetViscosityModel(Viscosity),
+    SetKinematicViscosity(f32),
+    SetLaminarViscosity(f32),
+    SetArtificialViscosity(f32, f32),
+    SetXsph(f32),
+    SetSmoothingKernel(SmoothingKernel),
+    SetPressureSolver(PressureSolver),
+    SetPressureIterations(u32),
+    SetPressureTolerance(f32),
+    SetSmoothingLength(f32),
+    SetParticleMass(f32),
+    SetStiffness(f32),
+    SetPowerLaw(u32, f32, f32),
+    SetCross(u32, f32, f32, f32, f32),
+    SetCarreau(u32, f32, f32, f32, f32),
+    SetBingham(u32, f32, f32),
+    SetDruckerPrager(u32, f32, f32),
+    SetRheologyPreset(u32, RheologyPreset),
+    SetRheology(u32, Rheology),
+    SetNewtonian(u32),
+    SetPhaseInRect(f32, f32, f32, f32, u32),
+    SetPhaseInCircle(f32, f32, f32, u32),
+    SetVorticityConfinement(f32),
+    SetConductivity(f32),
+    SetThermalExpansion(f32),
+    SetReferenceTemperature(f32),
+    AddHeatRect(f32, f32, f32, f32, f32, f32),
+    AddHeatCircle(f32, f32, f32, f32, f32),
+    AddHeatWall(Wall, f32, f32),
+    AddHeatBody(usize, f32, f32),
+    ClearHeatSources,
+    SetGravity(f32, f32),
+    SetGravityAngle(f32),
+    SetBodyForcePreset(BodyForcePreset, f32),
+    SetBodyForceGrid(usize, usize, Vec<f32>),
+    ClearBodyForce,
+    QueueSpawnParticles(usize, f32, f32),
+    QueueDespawnParticles(usize),
+    QueueDespawnOldest(usize),
+    QueueDespawnRandom(usize, u32),
+    QueueDespawnDensest(usize),
+    QueueDespawnInRect(f32, f32, f32, f32),
+    QueueDespawnInCircle(f32, f32, f32),
+    DespawnById(u32),
+    Despawn(Removal),
+    SetStabilityMonitor(bool),
+    SetStabilityLimits(f32, f32),
+    SetStabilityRecovery(u32, f32),
+    AddTracer(f32, f32),
+    AddTracerLine(f32, f32, f32, f32, usize),
+    AddTracerEmitter(f32, f32, f32),
+    AddTracerLineEmitter(f32, f32, f32, f32, usize, f32),
+    SetTracerIntegrator(TracerIntegrator),
+    SetTracerHistory(usize),
+    SetTracerLifetime(f32),
+    ClearTracers,
+    ClearColors,
+    DebugSingleParticle,
+    DebugFirstForce,
+}
+
+impl Input {
+    // Makes the same call on `universe`
+    pub fn apply(&self, universe: &mut Universe) -> Result<(), SpherroError> {
+        match *self {
+            Input::Update { dt, count } => {
+                for _ in 0..count {
+                    universe.update(dt)?;
+                }
+            },
+            Input::SetSeed(seed) => universe.set_seed(seed),
+            Input::AddForce(force) => universe.add_force(force),
+            Input::ClearForces => universe.clear_forces(),
+            Input::SetSurfaceTension(gamma) => universe.set_surface_tension(gamma),
+            Input::SetAdhesion(beta) => universe.set_adhesion(beta),
+            Input::SetPeriodic(x, y) => universe.set_periodic(x, y),
+            Input::SetSurfaceClassifier(classifier) => universe.set_surface_classifier(classifier),
+            Input::AddBoxBody(x, y, w, h, density) => { universe.add_box_body(x, y, w, h, density)?; },
+            Input::AddDiscBody(x, y, r, density) => { universe.add_disc_body(x, y, r, density)?; },
+            Input::AddBoxObstacle(x, y, w, h) => { universe.add_box_obstacle(x, y, w, h); },
+            Input::AddDiscObstacle(x, y, r) => { universe.add_disc_obstacle(x, y, r); },
+            Input::SetObstacleTarget(i, x, y, angle) => universe.set_obstacle_target(i, x, y, angle)?,
+            Input::AddObstacleKeyframe(i, t, x, y, angle) => universe.add_obstacle_keyframe(i, t, x, y, angle)?,
+            Input::SetObstacleLooping(i, looping) => universe.set_obstacle_looping(i, looping)?,
+            Input::ClearBodies => universe.clear_bodies(),
+            Input::SetViscosity(v) => universe.set_viscosity(v),
+            Input::SetViscosityModel(viscosity) => universe.set_viscosity_model(viscosity),
+            Input::SetKinematicViscosity(nu) => universe.set_kinematic_viscosity(nu),
+            Input::SetLaminarViscosity(nu) => universe.set_laminar_viscosity(nu),
+            Input::SetArtificialViscosity(alpha, beta) => universe.set_artificial_viscosity(alpha, beta),
+            Input::SetXsph(epsilon) => universe.set_xsph(epsilon),
+            Input::SetSmoothingKernel(kernel) => universe.set_smoothing_kernel(kernel),
+            Input::SetPressureSolver(solver) => universe.set_pressure_solver(solver),
+            Input::SetPressureIterations(iterations) => universe.set_pressure_iterations(iterations)?,
+            Input::SetPressureTolerance(tolerance) => universe.set_pressure_tolerance(tolerance)?,
+            Input::SetSmoothingLength(h) => universe.set_smoothing_length(h)?,
+            Input::SetParticleMass(mass) => universe.set_particle_mass(mass)?,
+            Input::SetStiffness(k) => universe.set_stiffness(k)?,
+            Input::SetPowerLaw(phase, k, n) => universe.set_power_law(phase, k, n),
+            Input::SetCross(phase, nu_0, nu_inf, lambda, n) => universe.set_cross(phase, nu_0, nu_inf, lambda, n),
+            Input::SetCarreau(phase, nu_0, nu_inf, lambda, n) => universe.set_carreau(phase, nu_0, nu_inf, lambda, n),
+            Input::SetBingham(phase, nu, yield_stress) => universe.set_bingham(phase, nu, yield_stress),
+            Input::SetDruckerPrager(phase, angle, cohesion) => universe.set_drucker_prager(phase, angle, cohesion),
+            Input::SetRheologyPreset(phase, preset) => universe.set_rheology_preset(phase, preset),
+            Input::SetRheology(phase, rheology) => universe.set_rheology(phase, rheology),
+            Input::SetNewtonian(phase) => universe.set_newtonian(phase),
+            Input::SetPhaseInRect(x0, y0, x1, y1, phase) => { universe.set_phase_in_rect(x0, y0, x1, y1, phase); },
+            Input::SetPhaseInCircle(x, y, r, phase) => { universe.set_phase_in_circle(x, y, r, phase); },
+            Input::SetVorticityConfinement(strength) => universe.set_vorticity_confinement(strength),
+            Input::SetConductivity(k) => universe.set_conductivity(k),
+            Input::SetThermalExpansion(beta) => universe.set_thermal_expansion(beta),
+            Input::SetReferenceTemperature(t) => universe.set_reference_temperature(t),
+            Input::AddHeatRect(x0, y0, x1, y1, t, rate) => { universe.add_heat_rect(x0, y0, x1, y1, t, rate); },
+            Input::AddHeatCircle(x, y, r, t, rate) => { universe.add_heat_circle(x, y, r, t, rate); },
+            Input::AddHeatWall(wall, t, rate) => { universe.add_heat_wall(wall, t, rate); },
+            Input::AddHeatBody(i, t, rate) => { universe.add_heat_body(i, t, rate)?; },
+            Input::ClearHeatSources => universe.clear_heat_sources(),
+            Input::SetGravity(x, y) => universe.set_gravity(x, y),
+            Input::SetGravityAngle(angle) => universe.set_gravity_angle(angle),
+            Input::SetBodyForcePreset(preset, strength) => universe.set_body_force_preset(preset, strength),
+            Input::SetBodyForceGrid(cols, rows, ref data) => universe.set_body_force_grid(cols, rows, data.clone())?,
+            Input::ClearBodyForce => universe.clear_body_force(),
+            Input::QueueSpawnParticles(count, x, y) => universe.queue_spawn_particles(count, x, y),
+            Input::QueueDespawnParticles(count) => universe.queue_despawn_particles(count),
+            Input::QueueDespawnOldest(count) => universe.queue_despawn_oldest(count),
+            Input::QueueDespawnRandom(count, seed) => universe.queue_despawn_random(count, seed),
+            Input::QueueDespawnDensest(count) => universe.queue_despawn_densest(count),
+            Input::QueueDespawnInRect(x0, y0, x1, y1) => universe.queue_despawn_in_rect(x0, y0, x1, y1),
+            Input::QueueDespawnInCircle(x, y, r) => universe.queue_despawn_in_circle(x, y, r),
+            Input::DespawnById(id) => { universe.despawn_by_id(id); },
+            Input::Despawn(ref removal) => { universe.despawn(removal)?; },
+            Input::SetStabilityMonitor(enabled) => universe.set_stability_monitor(enabled),
+            Input::SetStabilityLimits(cfl, error) => universe.set_stability_limits(cfl, error),
+            Input::SetStabilityRecovery(retries, damping) => universe.set_stability_recovery(retries, damping),
+            Input::AddTracer(x, y) => { universe.add_tracer(x, y); },
+            Input::AddTracerLine(x0, y0, x1, y1, count) => universe.add_tracer_line(x0, y0, x1, y1, count),
+            Input::AddTracerEmitter(x, y, period) => { universe.add_tracer_emitter(x, y, period)?; },
+            Input::AddTracerLineEmitter(x0, y0, x1, y1, count, period) => {
+                universe.add_tracer_line_emitter(x0, y0, x1, y1, count, period)?;
+            },
+            Input::SetTracerIntegrator(integrator) => universe.set_tracer_integrator(integrator),
+            Input::SetTracerHistory(length) => universe.set_tracer_history(length),
+            Input::SetTracerLifetime(lifetime) => universe.set_tracer_lifetime(lifetime),
+            Input::ClearTracers => universe.clear_tracers(),
+            Input::ClearColors => universe.clear_colors(),
+            Input::DebugSingleParticle => universe.debug_single_particle(),
+            Input::DebugFirstForce => universe.debug_first_force(),
+        }
+
+        Ok(())
+    }
+
+    // The call's name followed by its arguments, separated by spaces
+    fn write(&self, out: &mut String) {
+        let mut args = Args::default();
+        let name = match *self {
+            Input::Update { dt, count } => { args.f(dt).u(count); "update" },
+            Input::SetSeed(seed) => { args.u(seed); "set_seed" },
+            Input::AddForce(f) => {
+                args.f(f.x).f(f.y).f(f.power).f(f.r).e(&FORCE_KINDS, f.kind).e(&FALLOFFS, f.falloff)
+                    .f(f.dir_x).f(f.dir_y).f(f.spread);
+                "add_force"
+            },
+            Input::ClearForces => "clear_forces",
+            Input::SetSurfaceTension(gamma) => { args.f(gamma); "set_surface_tension" },
+            Input::SetAdhesion(beta) => { args.f(beta); "set_adhesion" },
+            Input::SetPeriodic(x, y) => { args.b(x).b(y); "set_periodic" },
+            Input::SetSurfaceClassifier(c) => { args.e(&CLASSIFIERS, c); "set_surface_classifier" },
+            Input::AddBoxBody(x, y, w, h, d) => { args.f(x).f(y).f(w).f(h).f(d); "add_box_body" },
+            Input::AddDiscBody(x, y, r, d) => { args.f(x).f(y).f(r).f(d); "add_disc_body" },
+            Input::AddBoxObstacle(x, y, w, h) => { args.f(x).f(y).f(w).f(h); "add_box_obstacle" },
+            Input::AddDiscObstacle(x, y, r) => { args.f(x).f(y).f(r); "add_disc_obstacle" },
+            Input::SetObstacleTarget(i, x, y, a) => { args.u(i).f(x).f(y).f(a); "set_obstacle_target" },
+            Input::AddObstacleKeyframe(i, t, x, y, a) => { args.u(i).f(t).f(x).f(y).f(a); "add_obstacle_keyframe" },
+            Input::SetObstacleLooping(i, looping) => { args.u(i).b(looping); "set_obstacle_looping" },
+            Input::ClearBodies => "clear_bodies",
+            Input::SetViscosity(v) => { args.f(v); "set_viscosity" },
+            Input::SetViscosityModel(v) => { args.viscosity(v); "set_viscosity_model" },
+            Input::SetKinematicViscosity(nu) => { args.f(nu); "set_kinematic_viscosity" },
+            Input::SetLaminarViscosity(nu) => { args.f(nu); "set_laminar_viscosity" },
+            Input::SetArtificialViscosity(a, b) => { args.f(a).f(b); "set_artificial_viscosity" },
+            Input::SetXsph(e) => { args.f(e); "set_xsph" },
+            Input::SetSmoothingKernel(k) => { args.e(&KERNELS, k); "set_smoothing_kernel" },
+            Input::SetPressureSolver(s) => { args.e(&PRESSURE_SOLVERS, s); "set_pressure_solver" },
+            Input::SetPressureIterations(n) => { args.u(n); "set_pressure_iterations" },
+            Input::SetPressureTolerance(t) => { args.f(t); "set_pressure_tolerance" },
+            Input::SetSmoothingLength(h) => { args.f(h); "set_smoothing_length" },
+            Input::SetParticleMass(m) => { args.f(m); "set_particle_mass" },
+            Input::SetStiffness(k) => { args.f(k); "set_stiffness" },
+            Input::SetPowerLaw(p, k, n) => { args.u(p).f(k).f(n); "set_power_law" },
+            Input::SetCross(p, a, b, l, n) => { args.u(p).f(a).f(b).f(l).f(n); "set_cross" },
+            Input::SetCarreau(p, a, b, l, n) => { args.u(p).f(a).f(b).f(l).f(n); "set_carreau" },
+            Input::SetBingham(p, nu, y) => { args.u(p).f(nu).f(y); "set_bingham" },
+            Input::SetDruckerPrager(p, a, c) => { args.u(p).f(a).f(c); "set_drucker_prager" },
+            Input::SetRheologyPreset(p, r) => { args.u(p).e(&RHEOLOGY_PRESETS, r); "set_rheology_preset" },
+            Input::SetRheology(p, r) => { args.u(p).rheology(r); "set_rheology" },
+            Input::SetNewtonian(p) => { args.u(p); "set_newtonian" },
+            Input::SetPhaseInRect(x0, y0, x1, y1, p) => { args.f(x0).f(y0).f(x1).f(y1).u(p); "set_phase_in_rect" },
+            Input::SetPhaseInCircle(x, y, r, p) => { args.f(x).f(y).f(r).u(p); "set_phase_in_circle" },
+            Input::SetVorticityConfinement(s) => { args.f(s); "set_vorticity_confinement" },
+            Input::SetConductivity(k) => { args.f(k); "set_conductivity" },
+            Input::SetThermalExpansion(b) => { args.f(b); "set_thermal_expansion" },
+            Input::SetReferenceTemperature(t) => { args.f(t); "set_reference_temperature" },
+            Input::AddHeatRect(x0, y0, x1, y1, t, r) => { args.f(x0).f(y0).f(x1).f(y1).f(t).f(r); "add_heat_rect" },
+            Input::AddHeatCircle(x, y, rad, t, r) => { args.f(x).f(y).f(rad).f(t).f(r); "add_heat_circle" },
+            Input::AddHeatWall(w, t, r) => { args.e(&WALLS, w).f(t).f(r); "add_heat_wall" },
+            Input::AddHeatBody(i, t, r) => { args.u(i).f(t).f(r); "add_heat_body" },
+            Input::ClearHeatSources => "clear_heat_sources",
+            Input::SetGravity(x, y) => { args.f(x).f(y); "set_gravity" },
+            Input::SetGravityAngle(a) => { args.f(a); "set_gravity_angle" },
+            Input::SetBodyForcePreset(p, s) => { args.e(&BODY_FORCE_PRESETS, p).f(s); "set_body_force_preset" },
+            Input::SetBodyForceGrid(cols, rows, ref data) => {
+                args.u(cols).u(rows);
+                for &v in data.iter() {
+                    args.f(v);
+                }
+                "set_body_force_grid"
+            },
+            Input::ClearBodyForce => "clear_body_force",
+            Input::QueueSpawnParticles(n, x, y) => { args.u(n).f(x).f(y); "queue_spawn_particles" },
+            Input::QueueDespawnParticles(n) => { args.u(n); "queue_despawn_particles" },
+            Input::QueueDespawnOldest(n) => { args.u(n); "queue_despawn_oldest" },
+            Input::QueueDespawnRandom(n, seed) => { args.u(n).u(seed); "queue_despawn_random" },
+            Input::QueueDespawnDensest(n) => { args.u(n); "queue_despawn_densest" },
+            Input::QueueDespawnInRect(x0, y0, x1, y1) => { args.f(x0).f(y0).f(x1).f(y1); "queue_despawn_in_rect" },
+            Input::QueueDespawnInCircle(x, y, r) => { args.f(x).f(y).f(r); "queue_despawn_in_circle" },
+            Input::DespawnById(id) => { args.u(id); "despawn_by_id" },
+            Input::Despawn(ref r) => { args.removal(r); "despawn" },
+            Input::SetStabilityMonitor(enabled) => { args.b(enabled); "set_stability_monitor" },
+            Input::SetStabilityLimits(c, e) => { args.f(c).f(e); "set_stability_limits" },
+            Input::SetStabilityRecovery(n, d) => { args.u(n).f(d); "set_stability_recovery" },
+            Input::AddTracer(x, y) => { args.f(x).f(y); "add_tracer" },
+            Input::AddTracerLine(x0, y0, x1, y1, n) => { args.f(x0).f(y0).f(x1).f(y1).u(n); "add_tracer_line" },
+            Input::AddTracerEmitter(x, y, p) => { args.f(x).f(y).f(p); "add_tracer_emitter" },
+            Input::AddTracerLineEmitter(x0, y0, x1, y1, n, p) => {
+                args.f(x0).f(y0).f(x1).f(y1).u(n).f(p);
+                "add_tracer_line_emitter"
+            },
+            Input::SetTracerIntegrator(i) => { args.e(&INTEGRATORS, i); "set_tracer_integrator" },
+            Input::SetTracerHistory(n) => { args.u(n); "set_tracer_history" },
+            Input::SetTracerLifetime(t) => { args.f(t); "set_tracer_lifetime" },
+            Input::ClearTracers => "clear_tracers",
+            Input::ClearColors => "clear_colors",
+            Input::DebugSingleParticle => "debug_single_particle",
+            Input::DebugFirstForce => "debug_first_force",
+        };
+
+        out.push_str(name);
+        out.push_str(&args.0);
+    }
+
+    // Reads back a call written by `write`, given its name and the words after it
+    fn parse<'a, I>(name: &str, words: I) -> Result<Input, String> where I: Iterator<Item=&'a str> {
+        let mut a = Parser(words);
+        let input = match name {
+            "update" => Input::Update { dt: a.f()?, count: a.u()? },
+            "set_seed" => Input::SetSeed(a.u()?),
+            "add_force" => Input::AddForce(Force {
+                x: a.f()?, y: a.f()?, power: a.f()?, r: a.f()?,
+                kind: a.e(&FORCE_KINDS)?, falloff: a.e(&FALLOFFS)?,
+                dir_x: a.f()?, dir_y: a.f()?, spread: a.f()?,
+            }),
+            "clear_forces" => Input::ClearForces,
+            "set_surface_tension" => Input::SetSurfaceTension(a.f()?),
+            "set_adhesion" => Input::SetAdhesion(a.f()?),
+            "set_periodic" => Input::SetPeriodic(a.b()?, a.b()?),
+            "set_surface_classifier" => Input::SetSurfaceClassifier(a.e(&CLASSIFIERS)?),
+            "add_box_body" => Input::AddBoxBody(a.f()?, a.f()?, a.f()?, a.f()?, a.f()?),
+            "add_disc_body" => Input::AddDiscBody(a.f()?, a.f()?, a.f()?, a.f()?),
+            "add_box_obstacle" => Input::AddBoxObstacle(a.f()?, a.f()?, a.f()?, a.f()?),
+            "add_disc_obstacle" => Input::AddDiscObstacle(a.f()?, a.f()?, a.f()?),
+            "set_obstacle_target" => Input::SetObstacleTarget(a.u()?, a.f()?, a.f()?, a.f()?),
+            "add_obstacle_keyframe" => Input::AddObstacleKeyframe(a.u()?, a.f()?, a.f()?, a.f()?, a.f()?),
+            "set_obstacle_looping" => Input::SetObstacleLooping(a.u()?, a.b()?),
+            "clear_bodies" => Input::ClearBodies,
+            "set_viscosity" => Input::SetViscosity(a.f()?),
+            "set_viscosity_model" => Input::SetViscosityModel(a.viscosity()?),
+            "set_kinematic_viscosity" => Input::SetKinematicViscosity(a.f()?),
+            "set_laminar_viscosity" => Input::SetLaminarViscosity(a.f()?),
+            "set_artificial_viscosity" => Input::SetArtificialViscosity(a.f()?, a.f()?),
+            "set_xsph" => Input::SetXsph(a.f()?),
+            "set_smoothing_kernel" => Input::SetSmoothingKernel(a.e(&KERNELS)?),
+            "set_pressure_solver" => Input::SetPressureSolver(a.e(&PRESSURE_SOLVERS)?),
+            "set_pressure_iterations" => Input::SetPressureIterations(a.u()?),
+            "set_pressure_tolerance" => Input::SetPressureTolerance(a.f()?),
+            "set_smoothing_length" => Input::SetSmoothingLength(a.f()?),
+            "set_particle_mass" => Input::SetParticleMass(a.f()?),
+            "set_stiffness" => Input::SetStiffness(a.f()?),
+            "set_power_law" => Input::SetPowerLaw(a.u()?, a.f()?, a.f()?),
+            "set_cross" => Input::SetCross(a.u()?, a.f()?, a.f()?, a.f()?, a.f()?),
+            "set_carreau" => Input::SetCarreau(a.u()?, a.f()?, a.f()?, a.f()?, a.f()?),
+            "set_bingham" => Input::SetBingham(a.u()?, a.f()?, a.f()?),
+            "set_drucker_prager" => Input::SetDruckerPrager(a.u()?, a.f()?, a.f()?),
+            "set_rheology_preset" => Input::SetRheologyPreset(a.u()?, a.e(&RHEOLOGY_PRESETS)?),
+            "set_rheology" => Input::SetRheology(a.u()?, a.rheology()?),
+            "set_newtonian" => Input::SetNewtonian(a.u()?),
+            "set_phase_in_rect" => Input::SetPhaseInRect(a.f()?, a.f()?, a.f()?, a.f()?, a.u()?),
+            "set_phase_in_circle" => Input::SetPhaseInCircle(a.f()?, a.f()?, a.f()?, a.u()?),
+            "set_vorticity_confinement" => Input::SetVorticityConfinement(a.f()?),
+            "set_conductivity" => Input::SetConductivity(a.f()?),
+            "set_thermal_expansion" => Input::SetThermalExpansion(a.f()?),
+            "set_reference_temperature" => Input::SetReferenceTemperature(a.f()?),
+            "add_heat_rect" => Input::AddHeatRect(a.f()?, a.f()?, a.f()?, a.f()?, a.f()?, a.f()?),
+            "add_heat_circle" => Input::AddHeatCircle(a.f()?, a.f()?, a.f()?, a.f()?, a.f()?),
+            "add_heat_wall" => Input::AddHeatWall(a.e(&WALLS)?, a.f()?, a.f()?),
+            "add_heat_body" => Input::AddHeatBody(a.u()?, a.f()?, a.f()?),
+            "clear_heat_sources" => Input::ClearHeatSources,
+            "set_gravity" => Input::SetGravity(a.f()?, a.f()?),
+            "set_gravity_angle" => Input::SetGravityAngle(a.f()?),
+            "set_body_force_preset" => Input::SetBodyForcePreset(a.e(&BODY_FORCE_PRESETS)?, a.f()?),
+            "set_body_force_grid" => {
+                let (cols, rows) = (a.u()?, a.u()?);
+                let data = a.0.by_ref().map(|w| w.parse::<f32>().map_err(|_| format!("bad number {}", w)))
+                                       .collect::<Result<Vec<f32>, String>>()?;
+                Input::SetBodyForceGrid(cols, rows, data)
+            },
+            "clear_body_force" => Input::ClearBodyForce,
+            "queue_spawn_particles" => Input::QueueSpawnParticles(a.u()?, a.f()?, a.f()?),
+            "queue_despawn_particles" => Input::QueueDespawnParticles(a.u()?),
+            "queue_despawn_oldest" => Input::QueueDespawnOldest(a.u()?),
+            "queue_despawn_random" => Input::QueueDespawnRandom(a.u()?, a.u()?),
+            "queue_despawn_densest" => Input::QueueDespawnDensest(a.u()?),
+            "queue_despawn_in_rect" => Input::QueueDespawnInRect(a.f()?, a.f()?, a.f()?, a.f()?),
+            "queue_despawn_in_circle" => Input::QueueDespawnInCircle(a.f()?, a.f()?, a.f()?),
+            "despawn_by_id" => Input::DespawnById(a.u()?),
+            "despawn" => Input::Despawn(a.removal()?),
+            "set_stability_monitor" => Input::SetStabilityMonitor(a.b()?),
+            "set_stability_limits" => Input::SetStabilityLimits(a.f()?, a.f()?),
+            "set_stability_recovery" => Input::SetStabilityRecovery(a.u()?, a.f()?),
+            "add_tracer" => Input::AddTracer(a.f()?, a.f()?),
+            "add_tracer_line" => Input::AddTracerLine(a.f()?, a.f()?, a.f()?, a.f()?, a.u()?),
+            "add_tracer_emitter" => Input::AddTracerEmitter(a.f()?, a.f()?, a.f()?),
+            "add_tracer_line_emitter" => Input::AddTracerLineEmitter(a.f()?, a.f()?, a.f()?, a.f()?, a.u()?, a.f()?),
+            "set_tracer_integrator" => Input::SetTracerIntegrator(a.e(&INTEGRATORS)?),
+            "set_tracer_history" => Input::SetTracerHistory(a.u()?),
+            "set_tracer_lifetime" => Input::SetTracerLifetime(a.f()?),
+            "clear_tracers" => Input::ClearTracers,
+            "clear_colors" => Input::ClearColors,
+            "debug_single_particle" => Input::DebugSingleParticle,
+            "debug_first_force" => Input::DebugFirstForce,
+            _ => return Err(format!("unknown call {}", name)),
+        };
+
+        match a.0.next() {
+            Some(w) => Err(format!("unexpected argument {}", w)),
+            None => Ok(input),
+        }
+    }
+}
+
+// Builds up the arguments of a call
+#[derive(Default)]
+struct Args(String);
+
+impl Args {
+    // f32's Display is the shortest string that parses back to the same value
+    fn f(&mut self, v: f32) -> &mut Args {
+        write!(self.0, " {}", v).unwrap();
+        self
+    }
+
+    fn u<T: std::fmt::Display>(&mut self, v: T) -> &mut Args {
+        write!(self.0, " {}", v).unwrap();
+        self
+    }
+
+    fn b(&mut self, v: bool) -> &mut Args {
+        self.u(v as u32)
+    }
+
+    fn e<T: PartialEq>(&mut self, all: &[T], v: T) -> &mut Args {
+        self.u(all.iter().position(|x| *x == v).unwrap())
+    }
+
+    // The native enums are written as the variant's index followed by its fields
+    fn removal(&mut self, r: &Removal) -> &mut Args {
+        match *r {
+            Removal::Fastest(n) => self.u(0).u(n),
+            Removal::Oldest(n) => self.u(1).u(n),
+            Removal::Random(n, seed) => self.u(2).u(n).u(seed),
+            Removal::Densest(n) => self.u(3).u(n),
+            Removal::InRect(a, b) => self.u(4).f(a.x).f(a.y).f(b.x).f(b.y),
+            Removal::InCircle(c, r) => self.u(5).f(c.x).f(c.y).f(r),
+        }
+    }
+
+    fn rheology(&mut self, r: Rheology) -> &mut Args {
+        match r {
+            Rheology::Newtonian => self.u(0),
+            Rheology::PowerLaw { k, n } => self.u(1).f(k).f(n),
+            Rheology::Cross { nu_0, nu_inf, lambda, n } => self.u(2).f(nu_0).f(nu_inf).f(lambda).f(n),
+            Rheology::Carreau { nu_0, nu_inf, lambda, n } => self.u(3).f(nu_0).f(nu_inf).f(lambda).f(n),
+            Rheology::Bingham { nu_plastic, yield_stress } => self.u(4).f(nu_plastic).f(yield_stress),
+            Rheology::DruckerPrager { friction_angle, cohesion } => self.u(5).f(friction_angle).f(cohesion),
+        }
+    }
+
+    fn viscosity(&mut self, v: Viscosity) -> &mut Args {
+        match v {
+            Viscosity::Laplacian(c) => self.u(0).f(c),
+            Viscosity::Morris(nu) => self.u(1).f(nu),
+            Viscosity::Monaghan { alpha, beta } => self.u(2).f(alpha).f(beta),
+        }
+    }
+}
+
+// Reads back the arguments of a call in order
+struct Parser<I>(I);
+
+impl<'a, I> Parser<I> where I: Iterator<Item=&'a str> {
+    fn word(&mut self) -> Result<&'a str, String> {
+        self.0.next().ok_or_else(|| "missing argument".to_string())
+    }
+
+    fn f(&mut self) -> Result<f32, String> {
+        let w = self.word()?;
+        w.parse().map_err(|_| format!("bad number {}", w))
+    }
+
+    fn u<T: std::str::FromStr>(&mut self) -> Result<T, String> {
+        let w = self.word()?;
+        w.parse().map_err(|_| format!("bad integer {}", w))
+    }
+
+    fn b(&mut self) -> Result<bool, String> {
+        Ok(self.u::<u32>()? != 0)
+    }
+
+    fn e<T: Copy>(&mut self, all: &[T]) -> Result<T, String> {
+        let i: usize = self.u()?;
+        all.get(i).cloned().ok_or_else(|| format!("no variant {}", i))
+    }
+
+    fn removal(&mut self) -> Result<Removal, String> {
+        Ok(match self.u()? {
+            0 => Removal::Fastest(self.u()?),
+            1 => Removal::Oldest(self.u()?),
+            2 => Removal::Random(self.u()?, self.u()?),
+            3 => Removal::Densest(self.u()?),
+            4 => Removal::InRect(Vector2f::new(self.f()?, self.f()?), Vector2f::new(self.f()?, self.f()?)),
+            5 => Removal::InCircle(Vector2f::new(self.f()?, self.f()?), self.f()?),
+            i => return Err(format!("no variant {}", i)),
+        })
+    }
+
+    fn rheology(&mut self) -> Result<Rheology, String> {
+        Ok(match self.u()? {
+            0 => Rheology::Newtonian,
+            1 => Rheology::PowerLaw { k: self.f()?, n: self.f()? },
+            2 => Rheology::Cross { nu_0: self.f()?, nu_inf: self.f()?, lambda: self.f()?, n: self.f()? },
+            3 => Rheology::Carreau { nu_0: self.f()?, nu_inf: self.f()?, lambda: self.f()?, n: self.f()? },
+            4 => Rheology::Bingham { nu_plastic: self.f()?, yield_stress: self.f()? },
+            5 => Rheology::DruckerPrager { friction_angle: self.f()?, cohesion: self.f()? },
+            i => return Err(format!("no variant {}", i)),
+        })
+    }
+
+    fn viscosity(&mut self) -> Result<Viscosity, String> {
+        Ok(match self.u()? {
+            0 => Viscosity::Laplacian(self.f()?),
+            1 => Viscosity::Morris(self.f()?),
+            2 => Viscosity::Monaghan { alpha: self.f()?, beta: self.f()? },
+            i => return Err(format!("no variant {}", i)),
+        })
+    }
+}
+
+// The inputs a universe made with `Universe::new` received, each with the
+// number of updates before it. Written as text, one call per line:
+//
+//   spherro-recording 1 <width> <height> <width_frac> <height_frac> <rows> <cols>
+//   <step> <call> <args>...
+//
+// where consecutive updates with the same dt share a line
+#[derive(Clone, Debug)]
+pub struct Recording {
+    width: f32,
+    height: f32,
+    config: Config,
+    inputs: Vec<(u64, Input)>,
+    steps: u64,
+}
+
+impl Recording {
+    pub fn new(width: f32, height: f32, config: Config) -> Recording {
+        Recording {
+            width,
+            height,
+            config,
+            inputs: Vec::new(),
+            steps: 0,
+        }
+    }
+
+    pub fn push(&mut self, input: Input) {
+        if let Input::Update { dt, count } = input {
+            self.steps += count as u64;
+            if let Some((_, Input::Update { dt: last, count: n })) = self.inputs.last_mut() {
+                if last.to_bits() == dt.to_bits() {
+                    *n += count;
+                    return;
+                }
+            }
+            self.inputs.push((self.steps - count as u64, input));
+        } else {
+            self.inputs.push((self.steps, input));
+        }
+    }
+
+    pub fn inputs(&self) -> &[(u64, Input)] {
+        &self.inputs
+    }
+
+    // Number of updates recorded
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    // A universe in the state the recording started from
+    pub fn universe(&self) -> Result<Universe, SpherroError> {
+        Universe::new(self.width, self.height, &self.config)
+    }
+
+    pub fn to_text(&self) -> String {
+        let c = &self.config;
+        let mut out = format!("{} {} {} {} {} {} {} {}\n", MAGIC, VERSION, self.width, self.height,
+                              c.width_frac, c.height_frac, c.rows, c.cols);
+        for (step, input) in self.inputs.iter() {
+            write!(out, "{} ", step).unwrap();
+            input.write(&mut out);
+            out.push('\n');
+        }
+        out
+    }
+
+    pub fn parse(text: &str) -> Result<Recording, SpherroError> {
+        let error = |line: usize, reason: String| SpherroError::InvalidRecording { line, reason };
+        let mut lines = text.lines().enumerate().filter(|(_, l)| !l.trim().is_empty());
+
+        let header = lines.next().map(|(_, l)| l).unwrap_or("");
+        let (width, height, config) = parse_header(header).map_err(|e| error(1, e))?;
+
+        let mut recording = Recording::new(width, height, config);
+        for (i, line) in lines {
+            let mut words = line.split_whitespace();
+            let step: u64 = Parser(&mut words).u().map_err(|e| error(i + 1, e))?;
+            if step != recording.steps {
+                return Err(error(i + 1, format!("expected step {}, got {}", recording.steps, step)));
+            }
+
+            let name = words.next().ok_or_else(|| error(i + 1, "missing call".to_string()))?;
+            let input = Input::parse(name, words).map_err(|e| error(i + 1, e))?;
+            recording.push(input);
+        }
+
+        Ok(recording)
+    }
+}
+
+// Reads the size and the config from the first line of a recording
+fn parse_header(header: &str) -> Result<(f32, f32, Config), String> {
+    let mut words = header.split_whitespace();
+    if words.next() != Some(MAGIC) {
+        return Err("not a recording".to_string());
+    }
+
+    let mut a = Parser(words);
+    let version: u32 = a.u()?;
+    if version != VERSION {
+        return Err(format!("unsupported version {}", version));
+    }
+
+    let (width, height) = (a.f()?, a.f()?);
+    let config = Config::new(a.f()?, a.f()?, a.u()?, a.u()?);
+    Ok((width, height, config))
+}
+
+// Feeds a recording back into a fresh universe one update at a time, so that
+// the caller can look at the universe in between
+pub struct Replayer {
+    recording: Recording,
+    next: usize, // index of the next input
+    done: u32, // updates already made from the next input
+    step: u64,
+    dropped: Vec<(u64, SpherroError)>, // despawns that couldn't be made, by step
+}
+
+impl Replayer {
+    pub fn new(recording: Recording) -> Replayer {
+        Replayer {
+            recording,
+            next: 0,
+            done: 0,
+            step: 0,
+            dropped: Vec::new(),
+        }
+    }
+
+    pub fn recording(&self) -> &Recording {
+        &self.recording
+    }
+
+    // Number of updates made so far
+    pub fn step(&self) -> u64 {
+        self.step
+    }
+
+    // Despawns that asked for more particles than there were, along with the
+    // step they were dropped in. The session that made the recording got the
+    // same errors after those steps, and went on like the replay does
+    pub fn dropped(&self) -> &[(u64, SpherroError)] {
+        &self.dropped
+    }
+
+    // Applies the inputs up to and including the next update. Returns false
+    // once the recording is over
+    pub fn advance(&mut self, universe: &mut Universe) -> Result<bool, SpherroError> {
+        while let Some((_, input)) = self.recording.inputs.get(self.next) {
+            if let Input::Update { dt, count } = *input {
+                if self.done < count {
+                    self.done += 1;
+                    self.step += 1;
+                    match universe.update(dt) {
+                        // Only the despawn was dropped, the step went through
+                        Err(e @ SpherroError::NotEnoughParticles { .. }) => self.dropped.push((self.step, e)),
+                        result => result?,
+                    }
+                    return Ok(true);
+                }
+                self.done = 0;
+            } else {
+                input.apply(universe)?;
+            }
+            self.next += 1;
+        }
+
+        Ok(false)
+    }
+
+    // Replays the rest of the recording
+    pub fn finish(&mut self, universe: &mut Universe) -> Result<(), SpherroError> {
+        while self.advance(universe)? {}
+        Ok(())
+    }
+}
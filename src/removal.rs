@@ -0,0 +1,128 @@
+use std::cmp::Ordering;
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use cgmath::{InnerSpace};
+use crate::util::*;
+use crate::particle::Particle;
+use crate::error::SpherroError;
+
+// Which particles a despawn removes
+#[derive(Clone, Debug)]
+pub enum Removal {
+    // The fastest particles. This is a heuristic to prevent destabilization
+    Fastest(usize),
+    // The particles with the smallest ids, ie. the ones spawned first
+    Oldest(usize),
+    // Particles picked uniformly at random. The same seed always picks the
+    // same particles from the same universe
+    Random(usize, u64),
+    // The particles in the most compressed regions
+    Densest(usize),
+    // Every particle in the rectangle spanning the two corners
+    InRect(Vector2f, Vector2f),
+    // Every particle within the radius of the center
+    InCircle(Vector2f, f32),
+}
+
+impl Removal {
+    // Returns the indices of the particles to remove, in no particular order
+    pub fn select(&self, particles: &[Particle]) -> Result<Vec<usize>, SpherroError> {
+        match *self {
+            Removal::Fastest(count) => {
+                top_k(particles, count, |pi| pi.vel.magnitude2() as f64)
+            },
+            Removal::Oldest(count) => {
+                top_k(particles, count, |pi| -(pi.id as f64))
+            },
+            Removal::Densest(count) => {
+                top_k(particles, count, |pi| pi.rho as f64)
+            },
+            Removal::Random(count, seed) => {
+                check_count(particles, count)?;
+
+                // Partial Fisher-Yates: the first `count` entries end up as a
+                // uniformly random subset
+                let mut rng = StdRng::seed_from_u64(seed);
+                let mut indices: Vec<usize> = (0..particles.len()).collect();
+                for i in 0..count {
+                    let j = rng.gen_range(i, indices.len());
+                    indices.swap(i, j);
+                }
+                indices.truncate(count);
+
+                Ok(indices)
+            },
+            Removal::InRect(a, b) => {
+                let (x0, x1) = (a.x.min(b.x), a.x.max(b.x));
+                let (y0, y1) = (a.y.min(b.y), a.y.max(b.y));
+                Ok(filter(particles, |pi| {
+                    pi.pos.x >= x0 && pi.pos.x <= x1 && pi.pos.y >= y0 && pi.pos.y <= y1
+                }))
+            },
+            Removal::InCircle(center, r) => {
+                Ok(filter(particles, |pi| (pi.pos - center).magnitude2() <= r * r))
+            },
+        }
+    }
+
+    // Number of particles this removes, as far as can be known before it
+    // runs. Region removals report how many particles are in the region now
+    pub fn estimate(&self, particles: &[Particle]) -> usize {
+        match *self {
+            Removal::Fastest(count) | Removal::Oldest(count) |
+            Removal::Random(count, _) | Removal::Densest(count) => count,
+            Removal::InRect(..) | Removal::InCircle(..) => {
+                self.select(particles).map(|s| s.len()).unwrap_or(0)
+            },
+        }
+    }
+}
+
+fn check_count(particles: &[Particle], count: usize) -> Result<(), SpherroError> {
+    if count > particles.len() {
+        return Err(SpherroError::NotEnoughParticles {
+            requested: count,
+            available: particles.len(),
+        });
+    }
+
+    Ok(())
+}
+
+// Indices of the `count` particles with the largest keys. Uses selection
+// rather than a full sort, and NaN keys(from an exploded particle) are
+// considered the largest of all
+fn top_k<F>(particles: &[Particle], count: usize, key: F) -> Result<Vec<usize>, SpherroError>
+        where F: Fn(&Particle) -> f64 {
+    check_count(particles, count)?;
+    if count == 0 {
+        return Ok(Vec::new());
+    }
+
+    let mut keyed: Vec<(f64, usize)> = particles.iter()
+                                                .enumerate()
+                                                .map(|(idx, pi)| (key(pi), idx))
+                                                .collect();
+    let descending = |a: &(f64, usize), b: &(f64, usize)| -> Ordering {
+        match (a.0.is_nan(), b.0.is_nan()) {
+            (true, true) => Ordering::Equal,
+            (true, false) => Ordering::Less,
+            (false, true) => Ordering::Greater,
+            (false, false) => b.0.partial_cmp(&a.0).unwrap(),
+        }
+    };
+
+    if count < keyed.len() {
+        keyed.select_nth_unstable_by(count - 1, descending);
+    }
+
+    Ok(keyed[..count].iter().map(|t| t.1).collect())
+}
+
+fn filter<F>(particles: &[Particle], pred: F) -> Vec<usize> where F: Fn(&Particle) -> bool {
+    particles.iter()
+             .enumerate()
+             .filter(|(_, pi)| pred(pi))
+             .map(|(idx, _)| idx)
+             .collect()
+}
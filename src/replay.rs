@@ -0,0 +1,68 @@
+extern crate spherro;
+
+use std::process;
+use spherro::{Recording, Replayer};
+
+// Replays a recording made with `Universe::start_recording`, eg. one saved
+// from the browser, without any rendering. Prints the stats every `every`
+// steps and exits with an error if the universe fails. Despawns that asked
+// for more particles than there were are only reported, like the browser does
+//
+//   replay <recording> [every]
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    if args.len() < 2 || args.len() > 3 {
+        eprintln!("usage: {} <recording> [every]", args[0]);
+        process::exit(2);
+    }
+
+    let every: u64 = match args.get(2).map(|a| a.parse()) {
+        None => 100,
+        Some(Ok(every)) if every > 0 => every,
+        _ => {
+            eprintln!("every must be a positive integer");
+            process::exit(2);
+        },
+    };
+
+    let text = std::fs::read_to_string(&args[1]).unwrap_or_else(|e| {
+        eprintln!("cannot read {}: {}", args[1], e);
+        process::exit(1);
+    });
+    let recording = Recording::parse(&text).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(1);
+    });
+
+    let mut universe = recording.universe().unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(1);
+    });
+
+    let total = recording.steps();
+    let mut replayer = Replayer::new(recording);
+    let mut next_report = every;
+    let mut dropped = 0;
+    loop {
+        match replayer.advance(&mut universe) {
+            Ok(true) => {
+                for (step, e) in replayer.dropped()[dropped..].iter() {
+                    eprintln!("step {} of {}: {}", step, total, e);
+                }
+                dropped = replayer.dropped().len();
+
+                if replayer.step() == next_report {
+                    println!("{}", universe.get_stats().to_json());
+                    next_report += every;
+                }
+            },
+            Ok(false) => break,
+            Err(e) => {
+                eprintln!("step {} of {}: {}", replayer.step(), total, e);
+                process::exit(1);
+            },
+        }
+    }
+
+    eprintln!("replayed {} steps, {} particles at t={}", total, universe.get_size(), universe.get_time());
+}
@@ -0,0 +1,87 @@
+use wasm_bindgen::prelude::*;
+
+// Parameter sets that look roughly like everyday fluids at the default
+// particle size. Selectable over wasm, where `Rheology` isn't
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RheologyPreset {
+    Honey, // thick, and slightly thinner when stirred
+    Ketchup, // holds its shape until pushed hard enough
+    Cornstarch, // stiffens when stirred quickly
+    Sand, // dry grains that pile up
+    Snow, // like sand, but sticks together
+}
+
+// How a phase's kinematic viscosity depends on its strain rate. Viscosities
+// are kinematic and in real units, like `Universe::kinematic_viscosity`,
+// and so is the yield stress(ie. divided by the density)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Rheology {
+    // Follows the universe's viscosity model
+    Newtonian,
+
+    // nu = k rate^(n - 1). Shear thinning for n < 1, thickening for n > 1
+    PowerLaw { k: f32, n: f32 },
+
+    // nu = nu_inf + (nu_0 - nu_inf)/(1 + (lambda rate)^n)
+    Cross { nu_0: f32, nu_inf: f32, lambda: f32, n: f32 },
+
+    // nu = nu_inf + (nu_0 - nu_inf)(1 + (lambda rate)^2)^((n - 1)/2)
+    Carreau { nu_0: f32, nu_inf: f32, lambda: f32, n: f32 },
+
+    // nu = nu_plastic + yield_stress/rate. Barely moves until the stress
+    // goes over the yield stress, then flows with nu_plastic. The viscosity
+    // is capped for stability, so below the yield stress it creeps
+    Bingham { nu_plastic: f32, yield_stress: f32 },
+
+    // Granular material that piles up instead of flowing. Carries an elastic
+    // shear stress that is capped by the Drucker-Prager yield criterion,
+    // from the angle of internal friction(in radians) and the cohesion(in
+    // the solver's pressure units). Otherwise follows the universe's
+    // viscosity model. Piles come out shallower than the friction angle,
+    // since there is little pressure to hold the grains near the surface.
+    // The tank's walls don't bounce granular particles, they hold them back
+    // with Coulomb friction at the friction angle
+    DruckerPrager { friction_angle: f32, cohesion: f32 },
+}
+
+impl Rheology {
+    pub fn preset(preset: RheologyPreset) -> Rheology {
+        match preset {
+            RheologyPreset::Honey => Rheology::Carreau { nu_0: 20000.0, nu_inf: 2000.0, lambda: 0.05, n: 0.7 },
+            RheologyPreset::Ketchup => Rheology::Bingham { nu_plastic: 2000.0, yield_stress: 2000000.0 },
+            RheologyPreset::Cornstarch => Rheology::PowerLaw { k: 500.0, n: 1.6 },
+            RheologyPreset::Sand => Rheology::DruckerPrager { friction_angle: 30f32.to_radians(), cohesion: 0.0 },
+            RheologyPreset::Snow => Rheology::DruckerPrager { friction_angle: 35f32.to_radians(), cohesion: 2.0 },
+        }
+    }
+
+    // Kinematic viscosity at strain rate `rate`, at most `max`, or None for
+    // Newtonian phases. The power law and Bingham models blow up as the
+    // rate goes to zero, which the cap takes care of
+    pub fn viscosity(&self, rate: f32, max: f32) -> Option<f32> {
+        let nu = match *self {
+            Rheology::Newtonian | Rheology::DruckerPrager { .. } => return None,
+            Rheology::PowerLaw { k, n } => k * rate.powf(n - 1.0),
+            Rheology::Cross { nu_0, nu_inf, lambda, n } => {
+                nu_inf + (nu_0 - nu_inf) / (1.0 + (lambda * rate).powf(n))
+            },
+            Rheology::Carreau { nu_0, nu_inf, lambda, n } => {
+                nu_inf + (nu_0 - nu_inf) * (1.0 + (lambda * rate).powi(2)).powf(0.5 * (n - 1.0))
+            },
+            Rheology::Bingham { nu_plastic, yield_stress } => nu_plastic + yield_stress / rate,
+        };
+
+        // NaN from 0^negative powers or 0/0 are taken as infinite
+        Some(if nu.is_nan() { max } else { nu.max(0.0).min(max) })
+    }
+}
+
+// Drucker-Prager yield criterion matched to Mohr-Coulomb under plane strain:
+// sqrt(J2) <= alpha I1 + k, where I1 is 3 times the pressure. Returns
+// (alpha, k)
+pub fn drucker_prager(friction_angle: f32, cohesion: f32) -> (f32, f32) {
+    let t = friction_angle.tan();
+    let d = (9.0 + 12.0 * t * t).sqrt();
+    (t / d, 3.0 * cohesion / d)
+}
@@ -0,0 +1,452 @@
+use cgmath::{InnerSpace};
+use crate::util::*;
+use crate::accelerators::{HasPosition};
+
+#[derive(Clone, Debug)]
+pub enum Shape {
+    Box(f32, f32), // width, height
+    Disc(f32), // radius
+}
+
+impl Shape {
+    pub fn area(&self) -> f32 {
+        match *self {
+            Shape::Box(w, h) => w * h,
+            Shape::Disc(r) => std::f32::consts::PI * r * r,
+        }
+    }
+}
+
+// A pose the body passes through at time `t` on a keyframed path
+#[derive(Clone, Debug)]
+pub struct Keyframe {
+    pub t: f32,
+    pub pos: Vector2f,
+    pub angle: f32,
+}
+
+// A 2D rigid body that interacts with the fluid through boundary particles
+// sampled along its outline(Akinci et al. 2012)
+#[derive(Clone, Debug)]
+pub struct RigidBody {
+    pub shape: Shape,
+    pub mass: f32,
+    pub inertia: f32,
+    pub pos: Vector2f,
+    pub angle: f32,
+    pub vel: Vector2f,
+    pub omega: f32,
+
+    // Kinematic bodies ignore the forces acting on them and instead move to
+    // wherever they're told to, pushing the fluid out of the way
+    pub kinematic: bool,
+    target: Option<(Vector2f, f32)>,
+    path: Vec<Keyframe>,
+    looping: bool,
+
+    // Sample positions in the body's local frame, and the fluid mass
+    // each of them stands in for
+    samples: Vec<Vector2f>,
+    psi: Vec<f32>,
+
+    // Accumulated over a step, then cleared on integration
+    force: Vector2f,
+    torque: f32,
+}
+
+// A boundary particle in world space. Rebuilt from the bodies every step
+#[derive(Clone, Debug)]
+pub struct BoundarySample {
+    pub pos: Vector2f,
+    pub vel: Vector2f,
+    pub psi: f32,
+    pub body: usize,
+}
+
+impl HasPosition for BoundarySample {
+    fn position(&self) -> Vector2f {
+        self.pos
+    }
+}
+
+impl RigidBody {
+    // `spacing` is the distance between neighbouring boundary samples
+    pub fn new(shape: Shape, pos: Vector2f, mass: f32, spacing: f32) -> RigidBody {
+        let (inertia, samples) = match shape {
+            Shape::Box(w, h) => {
+                let inertia = mass * (w * w + h * h) / 12.0;
+                (inertia, sample_box(w, h, spacing))
+            },
+            Shape::Disc(r) => {
+                let inertia = 0.5 * mass * r * r;
+                (inertia, sample_disc(r, spacing))
+            },
+        };
+
+        let psi = vec![0.0; samples.len()];
+        RigidBody {
+            shape,
+            mass,
+            inertia,
+            pos,
+            angle: 0.0,
+            vel: vec2f_zero(),
+            omega: 0.0,
+            kinematic: false,
+            target: None,
+            path: Vec::new(),
+            looping: false,
+            samples,
+            psi,
+            force: vec2f_zero(),
+            torque: 0.0,
+        }
+    }
+
+    pub fn new_kinematic(shape: Shape, pos: Vector2f, spacing: f32) -> RigidBody {
+        let mut body = RigidBody::new(shape, pos, 1.0, spacing);
+        body.kinematic = true;
+        body
+    }
+
+    // Kinematic bodies move to this pose over the next step
+    pub fn set_target(&mut self, pos: Vector2f, angle: f32) {
+        self.target = Some((pos, angle));
+    }
+
+    // Keyframes must be added in increasing order of time. Once the path
+    // is non-empty, it overrides any target that is set
+    pub fn add_keyframe(&mut self, keyframe: Keyframe) {
+        self.path.push(keyframe);
+    }
+
+    // Looping paths should end on the pose they start with, otherwise the
+    // body jumps back to the start every lap
+    pub fn set_looping(&mut self, looping: bool) {
+        self.looping = looping;
+    }
+
+    // Pose along the keyframed path at time `t`, linearly interpolated
+    fn pose_at(&self, t: f32) -> Option<(Vector2f, f32)> {
+        let first = self.path.first()?;
+        let last = self.path.last()?;
+
+        let duration = last.t - first.t;
+        let t = if self.looping && duration > 0.0 {
+            first.t + (t - first.t).rem_euclid(duration)
+        } else {
+            t
+        };
+
+        if t <= first.t {
+            return Some((first.pos, first.angle));
+        }
+
+        for (k0, k1) in self.path.iter().zip(self.path.iter().skip(1)) {
+            if t <= k1.t {
+                let a = if k1.t > k0.t { (t - k0.t) / (k1.t - k0.t) } else { 1.0 };
+                return Some((k0.pos + a * (k1.pos - k0.pos), k0.angle + a * (k1.angle - k0.angle)));
+            }
+        }
+
+        Some((last.pos, last.angle))
+    }
+
+    // Sets the boundary volume of every sample from its neighbouring samples,
+    // using kernel `w` with support `r`
+    pub fn compute_psi<F>(&mut self, rest_rho: f32, r: f32, w: F) where F: Fn(Vector2f) -> f32 {
+        self.psi = self.samples.iter().map(|&si| {
+            let sum: f32 = self.samples.iter()
+                                       .filter(|&&sk| (si - sk).magnitude2() < r * r)
+                                       .map(|&sk| w(si - sk))
+                                       .sum();
+            rest_rho / sum
+        }).collect();
+    }
+
+    pub fn world_samples(&self, body: usize) -> Vec<BoundarySample> {
+        self.samples.iter().zip(self.psi.iter()).map(|(&s, &psi)| {
+            let r = rotate(s, self.angle);
+            BoundarySample {
+                pos: self.pos + r,
+                vel: self.vel + self.omega * perp(r),
+                psi,
+                body,
+            }
+        }).collect()
+    }
+
+    // Applies force `f` at world space point `at`
+    pub fn apply_force(&mut self, f: Vector2f, at: Vector2f) {
+        let r = at - self.pos;
+        self.force += f;
+        self.torque += r.x * f.y - r.y * f.x;
+    }
+
+    // Resolves a contact at world space point `at` with a particle of mass `m`
+    // moving at `vel`, so that they stop approaching each other along `normal`.
+    // Returns the particle's change in velocity. The impulse is split between
+    // the two according to their effective masses, so a light body can't be
+    // kicked harder than the particle could push it
+    pub fn resolve_contact(&mut self, m: f32, vel: Vector2f, at: Vector2f, normal: Vector2f) -> Vector2f {
+        let vn = (vel - self.velocity_at(at)).dot(normal);
+        if vn >= 0.0 {
+            return vec2f_zero();
+        }
+
+        let (inv_mass, inv_inertia) = if self.kinematic {
+            (0.0, 0.0)
+        } else {
+            (1.0 / self.mass, 1.0 / self.inertia)
+        };
+
+        let r = at - self.pos;
+        let rn = r.x * normal.y - r.y * normal.x;
+        let j = -vn / (1.0 / m + inv_mass + rn * rn * inv_inertia);
+
+        self.vel -= (j * inv_mass) * normal;
+        self.omega -= rn * j * inv_inertia;
+
+        (j / m) * normal
+    }
+
+    // Kinematic bodies take the velocity that carries them to their next
+    // pose before the step, so that the fluid sees their boundary samples
+    // moving during the step rather than one step late. `time` is the
+    // simulation time at the start of the step
+    pub fn begin_step(&mut self, time: f32, dt: f32) {
+        if !self.kinematic {
+            return;
+        }
+
+        let (pos, angle) = self.next_pose(time + dt);
+
+        // Full turns, eg. a looping stirrer wrapping from 2pi back to 0,
+        // shouldn't show up as a spike in angular velocity
+        let turn = 2.0 * std::f32::consts::PI;
+        let d_angle = (angle - self.angle + 0.5 * turn).rem_euclid(turn) - 0.5 * turn;
+
+        self.vel = (pos - self.pos) / dt;
+        self.omega = d_angle / dt;
+    }
+
+    // `time` is the simulation time at the start of the step
+    pub fn integrate(&mut self, gravity: Vector2f, time: f32, dt: f32) {
+        if self.kinematic {
+            self.move_kinematic(time + dt);
+            return;
+        }
+
+        self.vel += (self.force / self.mass + gravity) * dt;
+        self.omega += (self.torque / self.inertia) * dt;
+        self.pos += self.vel * dt;
+        self.angle += self.omega * dt;
+
+        self.force = vec2f_zero();
+        self.torque = 0.0;
+    }
+
+    // If `p` is inside the body, returns the closest point on its outline
+    // together with the outward normal there
+    pub fn penetration(&self, p: Vector2f) -> Option<(Vector2f, Vector2f)> {
+        let local = rotate(p - self.pos, -self.angle);
+
+        let (surface, normal) = match self.shape {
+            Shape::Box(w, h) => {
+                let (hw, hh) = (w / 2.0, h / 2.0);
+                if local.x.abs() >= hw || local.y.abs() >= hh {
+                    return None;
+                }
+
+                // Exit through whichever side is closest
+                if hw - local.x.abs() < hh - local.y.abs() {
+                    let sx = local.x.signum();
+                    (Vector2f::new(sx * hw, local.y), Vector2f::new(sx, 0.0))
+                } else {
+                    let sy = local.y.signum();
+                    (Vector2f::new(local.x, sy * hh), Vector2f::new(0.0, sy))
+                }
+            },
+            Shape::Disc(r) => {
+                let d = local.magnitude();
+                if d >= r {
+                    return None;
+                }
+
+                let normal = if d > 1e-6 { local / d } else { Vector2f::new(0.0, 1.0) };
+                (normal * r, normal)
+            },
+        };
+
+        Some((self.pos + rotate(surface, self.angle), rotate(normal, self.angle)))
+    }
+
+    // Velocity of the body at world space point `at`
+    fn velocity_at(&self, at: Vector2f) -> Vector2f {
+        self.vel + self.omega * perp(at - self.pos)
+    }
+
+    // Where a kinematic body should be at time `t`
+    fn next_pose(&self, t: f32) -> (Vector2f, f32) {
+        self.pose_at(t)
+            .or(self.target)
+            .unwrap_or((self.pos, self.angle))
+    }
+
+    fn move_kinematic(&mut self, t: f32) {
+        let (pos, angle) = self.next_pose(t);
+        self.pos = pos;
+        self.angle = angle;
+
+        self.force = vec2f_zero();
+        self.torque = 0.0;
+    }
+
+    // Pushes the body back inside [0, width]x[0, height] if any of its samples
+    // left it, bouncing it off the wall with the coefficient of restitution `cor`
+    pub fn collide_walls(&mut self, width: f32, height: f32, cor: f32) {
+        if self.kinematic {
+            return;
+        }
+
+        let (mut x0, mut x1) = (f32::INFINITY, f32::NEG_INFINITY);
+        let (mut y0, mut y1) = (f32::INFINITY, f32::NEG_INFINITY);
+        for &s in self.samples.iter() {
+            let p = self.pos + rotate(s, self.angle);
+            x0 = x0.min(p.x);
+            x1 = x1.max(p.x);
+            y0 = y0.min(p.y);
+            y1 = y1.max(p.y);
+        }
+
+        if x0 < 0.0 {
+            self.pos.x -= x0;
+            self.vel.x = self.vel.x.abs() * cor;
+        } else if x1 > width {
+            self.pos.x -= x1 - width;
+            self.vel.x = -self.vel.x.abs() * cor;
+        }
+
+        if y0 < 0.0 {
+            self.pos.y -= y0;
+            self.vel.y = self.vel.y.abs() * cor;
+        } else if y1 > height {
+            self.pos.y -= y1 - height;
+            self.vel.y = -self.vel.y.abs() * cor;
+        }
+    }
+}
+
+fn rotate(v: Vector2f, angle: f32) -> Vector2f {
+    let (s, c) = angle.sin_cos();
+    Vector2f::new(c * v.x - s * v.y, s * v.x + c * v.y)
+}
+
+// Rotates v by 90 degrees counter-clockwise, ie. the 2D cross product z x v
+fn perp(v: Vector2f) -> Vector2f {
+    Vector2f::new(-v.y, v.x)
+}
+
+fn sample_box(w: f32, h: f32, spacing: f32) -> Vec<Vector2f> {
+    let nx = ((w / spacing).ceil() as usize).max(1);
+    let ny = ((h / spacing).ceil() as usize).max(1);
+    let (hw, hh) = (w / 2.0, h / 2.0);
+
+    let mut samples = Vec::with_capacity(2 * (nx + ny));
+    for i in 0..nx {
+        let x = -hw + w * (i as f32 / nx as f32);
+        samples.push(Vector2f::new(x, -hh));
+        samples.push(Vector2f::new(-x, hh));
+    }
+    for j in 0..ny {
+        let y = -hh + h * (j as f32 / ny as f32);
+        samples.push(Vector2f::new(hw, y));
+        samples.push(Vector2f::new(-hw, -y));
+    }
+
+    samples
+}
+
+fn sample_disc(r: f32, spacing: f32) -> Vec<Vector2f> {
+    let n = ((2.0 * std::f32::consts::PI * r / spacing).ceil() as usize).max(3);
+    (0..n).map(|i| {
+        let theta = 2.0 * std::f32::consts::PI * (i as f32 / n as f32);
+        Vector2f::new(r * theta.cos(), r * theta.sin())
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keyframe(t: f32, x: f32, y: f32, angle: f32) -> Keyframe {
+        Keyframe{ t, pos: Vector2f::new(x, y), angle }
+    }
+
+    fn obstacle(path: &[Keyframe], looping: bool) -> RigidBody {
+        let mut body = RigidBody::new_kinematic(Shape::Box(20.0, 10.0), vec2f_zero(), 5.0);
+        for k in path.iter() {
+            body.add_keyframe(k.clone());
+        }
+        body.set_looping(looping);
+        body
+    }
+
+    fn assert_pose(pose: Option<(Vector2f, f32)>, x: f32, y: f32, angle: f32) {
+        let (pos, a) = pose.unwrap();
+        assert!((pos - Vector2f::new(x, y)).magnitude() < 1e-4 && (a - angle).abs() < 1e-5,
+                "{:?} {}, expected ({}, {}) {}", pos, a, x, y, angle);
+    }
+
+    #[test]
+    fn pose_is_interpolated_between_keyframes() {
+        let body = obstacle(&[keyframe(1.0, 0.0, 0.0, 0.0), keyframe(3.0, 20.0, 10.0, 1.0),
+                              keyframe(4.0, 20.0, 30.0, 1.0)], false);
+        assert_pose(body.pose_at(2.0), 10.0, 5.0, 0.5);
+        assert_pose(body.pose_at(3.5), 20.0, 20.0, 1.0);
+
+        // Holds the end poses outside the path
+        assert_pose(body.pose_at(0.0), 0.0, 0.0, 0.0);
+        assert_pose(body.pose_at(9.0), 20.0, 30.0, 1.0);
+        assert!(obstacle(&[], false).pose_at(1.0).is_none());
+    }
+
+    #[test]
+    fn looping_paths_repeat() {
+        let body = obstacle(&[keyframe(1.0, 0.0, 0.0, 0.0), keyframe(2.0, 10.0, 0.0, 1.0),
+                              keyframe(3.0, 0.0, 0.0, 0.0)], true);
+        assert_pose(body.pose_at(1.5), 5.0, 0.0, 0.5);
+        assert_pose(body.pose_at(3.5), 5.0, 0.0, 0.5);
+        assert_pose(body.pose_at(12.25), 7.5, 0.0, 0.75);
+
+        // Before the path starts the lap counts backwards
+        assert_pose(body.pose_at(0.5), 5.0, 0.0, 0.5);
+    }
+
+    #[test]
+    fn kinematic_velocity_is_set_before_the_step() {
+        let mut body = obstacle(&[keyframe(0.0, 0.0, 0.0, 0.0), keyframe(1.0, 100.0, 0.0, 0.5)], false);
+        let dt = 0.01;
+        body.begin_step(0.0, dt);
+
+        // The boundary samples carry the velocity of the step they're used in
+        assert!((body.vel - Vector2f::new(100.0, 0.0)).magnitude() < 1e-3, "{:?}", body.vel);
+        assert!((body.omega - 0.5).abs() < 1e-3, "{}", body.omega);
+        for s in body.world_samples(0).iter() {
+            let expected = body.vel + body.omega * perp(s.pos - body.pos);
+            assert!((s.vel - expected).magnitude() < 1e-3);
+        }
+
+        body.integrate(vec2f_zero(), 0.0, dt);
+        assert_pose(Some((body.pos, body.angle)), 1.0, 0.0, 0.005);
+    }
+
+    #[test]
+    fn full_turns_are_not_spun_back() {
+        let mut body = obstacle(&[], false);
+        body.angle = 0.05;
+        body.set_target(vec2f_zero(), 2.0 * std::f32::consts::PI - 0.05);
+        body.begin_step(0.0, 0.1);
+        assert!((body.omega + 1.0).abs() < 1e-4, "{}", body.omega);
+    }
+}
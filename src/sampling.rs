@@ -0,0 +1,126 @@
+use wasm_bindgen::prelude::*;
+use cgmath::{InnerSpace};
+use crate::util::*;
+use crate::particle::Particle;
+use crate::domain::Domain;
+use crate::accelerators::{Accelerator, Grid};
+use crate::kernel::SmoothingKernel;
+use crate::error::SpherroError;
+
+// 2 floats for velocity
+// 1 float for density
+// 1 float for pressure
+// 1 float for vorticity
+pub const RASTER_STRIDE: usize = 5;
+
+// Fluid quantities interpolated at a point
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FieldSample {
+    pub vx: f32,
+    pub vy: f32,
+    pub density: f32,
+    pub pressure: f32,
+    pub vorticity: f32, // counter-clockwise positive
+}
+
+impl FieldSample {
+    pub fn velocity(&self) -> Vector2f {
+        Vector2f::new(self.vx, self.vy)
+    }
+}
+
+// Interpolates the particles' fields at `pos` with the SPH interpolant,
+// normalized by the sum of the kernel weights(Shepard). Normalizing makes it
+// exact for constant fields, even where the kernel is only partly covered by
+// fluid, and makes it independent of how the solver scales its densities.
+// Vorticity is the curl of the same interpolant, skipped unless asked for.
+// Weighted with the solver's kernel, so that the fields match what it sees.
+// Returns None where there is no fluid within reach of the smoothing length h
+pub fn sample(domain: &Domain, grid: &Grid<Particle>, particles: &[Particle],
+              (kernel, h): (SmoothingKernel, f32), pos: Vector2f, vorticity: bool) -> Option<FieldSample> {
+    let neighbours: Vec<(&Particle, Vector2f, f32)> = grid.nearest_by_pos(pos, 2.0 * h)
+        .into_iter()
+        .map(|j| &particles[j])
+        .filter(|pj| pj.rho > 0.0)
+        .map(|pj| (pj, domain.offset(pos, pj.pos), pj.mass / pj.rho))
+        .collect();
+
+    let mut weight = 0.0;
+    let mut sample = FieldSample::default();
+    for &(pj, x, volume) in neighbours.iter() {
+        let w = volume * kernel.f(x.magnitude() / h);
+        weight += w;
+        sample.vx += w * pj.vel.x;
+        sample.vy += w * pj.vel.y;
+        sample.density += w * pj.rho;
+        sample.pressure += w * pj.pressure;
+    }
+
+    if weight <= 0.0 {
+        return None;
+    }
+
+    sample.vx /= weight;
+    sample.vy /= weight;
+    sample.density /= weight;
+    sample.pressure /= weight;
+
+    if vorticity {
+        // Taken relative to the interpolated velocity, so that a uniform flow
+        // has no vorticity even near the surface
+        let v = sample.velocity();
+        let curl: f32 = neighbours.iter().map(|&(pj, x, volume)| {
+            let r = x.magnitude();
+            if r < 1e-6 {
+                return 0.0;
+            }
+
+            let dw = volume * (kernel.df(r / h) / h) * x / r;
+            let dv = pj.vel - v;
+            dw.x * dv.y - dw.y * dv.x
+        }).sum();
+
+        sample.vorticity = curl / weight;
+    }
+
+    Some(sample)
+}
+
+// Samples a cols x rows grid of points spanning (x0, y0) to (x1, y1), both
+// corners included. The samples are interleaved RASTER_STRIDE floats apiece,
+// row by row from (x0, y0). Points with no fluid, and the vorticity unless
+// it was asked for, are NaN
+pub fn rasterize(domain: &Domain, particles: &[Particle], (kernel, h): (SmoothingKernel, f32),
+                 from: Vector2f, to: Vector2f, (cols, rows): (usize, usize),
+                 vorticity: bool) -> Result<Vec<f32>, SpherroError> {
+    if cols == 0 || rows == 0 {
+        return Err(SpherroError::InvalidConfig(format!(
+            "raster must have at least one row and column, got {}x{}", cols, rows,
+        )));
+    }
+    if !(from.x.is_finite() && from.y.is_finite() && to.x.is_finite() && to.y.is_finite()) {
+        return Err(SpherroError::InvalidConfig("raster corners must be finite".to_string()));
+    }
+
+    let grid = Grid::with_domain(*domain, h, particles);
+    let step = |n: usize| if n > 1 { 1.0 / (n - 1) as f32 } else { 0.0 };
+    let (sx, sy) = (step(cols), step(rows));
+
+    let mut buffer = Vec::with_capacity(cols * rows * RASTER_STRIDE);
+    for y in 0..rows {
+        for x in 0..cols {
+            let pos = Vector2f::new(from.x + (to.x - from.x) * x as f32 * sx,
+                                    from.y + (to.y - from.y) * y as f32 * sy);
+            match sample(domain, &grid, particles, (kernel, h), pos, vorticity) {
+                Some(s) => {
+                    let w = if vorticity { s.vorticity } else { f32::NAN };
+                    buffer.extend_from_slice(&[s.vx, s.vy, s.density, s.pressure, w]);
+                },
+                None => buffer.extend_from_slice(&[f32::NAN; RASTER_STRIDE]),
+            }
+        }
+    }
+
+    Ok(buffer)
+}
@@ -0,0 +1,621 @@
+// Canonical benchmark setups built on top of `Universe`, each with a runner
+// that compares the simulation against an analytic or published reference
+// and reports error norms. These exist to justify parameter choices, so the
+// errors are reported rather than asserted on
+use std::fmt;
+use cgmath::{InnerSpace};
+use crate::util::*;
+use crate::particle::Particle;
+use crate::universe::{Universe, MASS, GRAVITY, FLUID_AREA_DENSITY};
+use crate::error::SpherroError;
+use crate::viscosity::Viscosity;
+
+// Error norms of a scenario against its reference. Errors are divided by a
+// scale given by the scenario, so the norms are relative
+#[derive(Clone, Debug)]
+pub struct Report {
+    pub name: &'static str,
+    pub quantity: &'static str, // what was compared
+    pub reference: &'static str, // what it was compared against
+    pub samples: usize,
+    pub l1: f32,
+    pub l2: f32,
+    pub linf: f32,
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {} against {} over {} samples, L1 {:.4} L2 {:.4} Linf {:.4}",
+               self.name, self.quantity, self.reference, self.samples, self.l1, self.l2, self.linf)
+    }
+}
+
+pub trait Scenario {
+    fn name(&self) -> &'static str;
+
+    // Sets up the universe at time zero
+    fn build(&self) -> Result<Universe, SpherroError>;
+
+    // Builds and runs the scenario, then compares it with the reference
+    fn run(&self) -> Result<Report, SpherroError>;
+}
+
+// Every scenario with its default parameters
+pub fn all() -> Vec<Box<dyn Scenario>> {
+    vec![
+        Box::new(DamBreak::default()),
+        Box::new(HydrostaticColumn::default()),
+        Box::new(LidDrivenCavity::default()),
+        Box::new(TaylorGreen::default()),
+        Box::new(Poiseuille::default()),
+        Box::new(Couette::default()),
+    ]
+}
+
+// Spacing of a settled fluid at the default particle mass
+pub fn rest_spacing() -> f32 {
+    (MASS / FLUID_AREA_DENSITY).sqrt()
+}
+
+// Accumulates errors into a report
+struct Errors {
+    sum_abs: f32,
+    sum_sq: f32,
+    max: f32,
+    n: usize,
+}
+
+impl Errors {
+    fn new() -> Errors {
+        Errors { sum_abs: 0.0, sum_sq: 0.0, max: 0.0, n: 0 }
+    }
+
+    fn push(&mut self, error: f32) {
+        let e = error.abs();
+        self.sum_abs += e;
+        self.sum_sq += e * e;
+        self.max = self.max.max(e);
+        self.n += 1;
+    }
+
+    fn report(&self, name: &'static str, quantity: &'static str, reference: &'static str) -> Report {
+        let n = self.n.max(1) as f32;
+        Report {
+            name,
+            quantity,
+            reference,
+            samples: self.n,
+            l1: self.sum_abs / n,
+            l2: (self.sum_sq / n).sqrt(),
+            linf: self.max,
+        }
+    }
+}
+
+// nx by ny particles at rest filling the rectangle with its lower left corner
+// at (x0, y0), `spacing` apart
+fn block(x0: f32, y0: f32, nx: usize, ny: usize, spacing: f32) -> Vec<Particle> {
+    let mut particles = Vec::with_capacity(nx * ny);
+    for j in 0..ny {
+        for i in 0..nx {
+            let pos = Vector2f::new(x0 + (i as f32 + 0.5) * spacing,
+                                    y0 + (j as f32 + 0.5) * spacing);
+            particles.push(Particle::new(particles.len() as u32, pos, MASS));
+        }
+    }
+
+    particles
+}
+
+// Collapse of a square water column(Martin & Moyce 1952). Compares the
+// position of the surge front, relative to the column width
+pub struct DamBreak {
+    pub columns: usize, // particles along each side of the column
+    pub dt: f32,
+}
+
+impl Default for DamBreak {
+    fn default() -> DamBreak {
+        DamBreak { columns: 16, dt: 0.002 }
+    }
+}
+
+impl DamBreak {
+    // Front position Z = z/a against time T = t * sqrt(2g/a), for a
+    // column of width and height a
+    pub const MEASURED: [(f32, f32); 13] = [
+        (0.41, 1.11), (0.84, 1.22), (1.19, 1.44), (1.43, 1.67), (1.63, 1.89),
+        (1.83, 2.11), (1.98, 2.33), (2.20, 2.56), (2.32, 2.78), (2.51, 3.00),
+        (2.65, 3.22), (2.83, 3.44), (2.98, 3.67),
+    ];
+
+    fn width(&self) -> f32 {
+        self.columns as f32 * rest_spacing()
+    }
+
+    fn front(&self, universe: &Universe) -> f32 {
+        let x = universe.get_particles().iter().map(|pi| pi.pos.x).fold(0.0, f32::max);
+        (x + 0.5 * rest_spacing()) / self.width()
+    }
+}
+
+impl Scenario for DamBreak {
+    fn name(&self) -> &'static str {
+        "dam_break"
+    }
+
+    fn build(&self) -> Result<Universe, SpherroError> {
+        let a = self.width();
+        let particles = block(0.0, 0.0, self.columns, self.columns, rest_spacing());
+        Universe::from_particles(5.0 * a, 2.5 * a, particles)
+    }
+
+    fn run(&self) -> Result<Report, SpherroError> {
+        let mut universe = self.build()?;
+        let t_scale = (-2.0 * GRAVITY / self.width()).sqrt();
+
+        let mut errors = Errors::new();
+        let mut prev = (0.0, self.front(&universe));
+        for &(t, z) in DamBreak::MEASURED.iter() {
+            while universe.get_time() * t_scale < t {
+                prev = (universe.get_time() * t_scale, self.front(&universe));
+                universe.update(self.dt)?;
+            }
+
+            let next = (universe.get_time() * t_scale, self.front(&universe));
+            let z_sim = prev.1 + (next.1 - prev.1) * (t - prev.0) / (next.0 - prev.0);
+            errors.push((z_sim - z) / z);
+        }
+
+        Ok(errors.report(self.name(), "surge front position", "Martin & Moyce measurements"))
+    }
+}
+
+// A column of fluid settling under gravity. Compares the pressure against
+// the hydrostatic pressure at the particle's depth, relative to the pressure
+// at the bottom.
+//
+// The pressure force is applied once per pressure iteration and, with the
+// kernel normalized by h^3, comes out h times larger than -grad(p)/rho. The
+// solver is in equilibrium when p = rho * g * depth / (iterations * h), so
+// that is the reference rather than the physical rho * g * depth
+pub struct HydrostaticColumn {
+    pub columns: usize,
+    pub rows: usize,
+    pub dt: f32,
+    pub settle_time: f32,
+}
+
+impl Default for HydrostaticColumn {
+    fn default() -> HydrostaticColumn {
+        HydrostaticColumn { columns: 15, rows: 20, dt: 0.004, settle_time: 6.0 }
+    }
+}
+
+impl Scenario for HydrostaticColumn {
+    fn name(&self) -> &'static str {
+        "hydrostatic_column"
+    }
+
+    fn build(&self) -> Result<Universe, SpherroError> {
+        let s = rest_spacing();
+        let particles = block(0.0, 0.0, self.columns, self.rows, s);
+        Universe::from_particles(self.columns as f32 * s, 2.0 * self.rows as f32 * s, particles)
+    }
+
+    fn run(&self) -> Result<Report, SpherroError> {
+        let mut universe = self.build()?;
+        while universe.get_time() < self.settle_time {
+            universe.update(self.dt)?;
+        }
+
+        let g = -GRAVITY;
+
+        // Averaged over a few frames, since the surface never fully settles
+        let mut errors = Errors::new();
+        for _ in 0..10 {
+            for _ in 0..10 {
+                universe.update(self.dt)?;
+            }
+
+            // The free surface is taken to be half a spacing above the
+            // average height of the top row
+            let mut heights: Vec<f32> = universe.get_particles().iter().map(|pi| pi.pos.y).collect();
+            heights.sort_by(|a, b| b.partial_cmp(a).unwrap());
+            let top_row = &heights[..self.columns.min(heights.len())];
+            let surface = top_row.iter().sum::<f32>() / top_row.len() as f32 + 0.5 * rest_spacing();
+
+            let scale = universe.last_pressure_iterations() as f32 * universe.get_smoothing_length();
+            let particles = universe.get_particles();
+            let rho = particles.iter().map(|pi| pi.rho).sum::<f32>() / particles.len() as f32;
+            let bottom = rho * g * surface / scale;
+            for pi in particles.iter() {
+                let expected = pi.rho * g * (surface - pi.pos.y) / scale;
+                errors.push((pi.pressure - expected) / bottom);
+            }
+        }
+
+        Ok(errors.report(self.name(), "pressure",
+                         "solver equilibrium rho g depth / (iterations h), not rho g depth"))
+    }
+}
+
+// A square cavity closed by no-slip walls, with the lid sliding along at a
+// constant speed(Ghia, Ghia & Shin 1982, Re = 100). Compares the horizontal
+// velocity along the vertical centerline, relative to the lid speed, averaged
+// over the last quarter of the run.
+//
+// The walls and the lid are kinematic obstacles, so the fluid sticks to them,
+// although a single layer of boundary particles only drags the fluid weakly.
+// The lid is long enough to cover the cavity for the whole run
+pub struct LidDrivenCavity {
+    pub size: f32,
+    pub lid_speed: f32,
+    pub reynolds: f32,
+    pub dt: f32,
+    pub duration: f32,
+}
+
+impl Default for LidDrivenCavity {
+    fn default() -> LidDrivenCavity {
+        LidDrivenCavity { size: 300.0, lid_speed: 300.0, reynolds: 100.0, dt: 0.002, duration: 8.0 }
+    }
+}
+
+impl LidDrivenCavity {
+    // Horizontal velocity along the vertical centerline, against height.
+    // Both are relative to the cavity
+    pub const MEASURED: [(f32, f32); 17] = [
+        (0.0000, 0.00000), (0.0547, -0.03717), (0.0625, -0.04192), (0.0703, -0.04775),
+        (0.1016, -0.06434), (0.1719, -0.10150), (0.2813, -0.15662), (0.4531, -0.21090),
+        (0.5000, -0.20581), (0.6172, -0.13641), (0.7344, 0.00332), (0.8516, 0.23151),
+        (0.9531, 0.68717), (0.9609, 0.73722), (0.9688, 0.78871), (0.9766, 0.84123),
+        (1.0000, 1.00000),
+    ];
+
+    // Gap between the cavity and the edge of the universe, with room
+    // for the walls. The universe is laid out before it exists, so this
+    // goes by the default smoothing length
+    fn margin(&self) -> f32 {
+        2.0 * Universe::default_smoothing_length()
+    }
+}
+
+impl Scenario for LidDrivenCavity {
+    fn name(&self) -> &'static str {
+        "lid_driven_cavity"
+    }
+
+    fn build(&self) -> Result<Universe, SpherroError> {
+        let (l, m) = (self.size, self.margin());
+        let n = (l / rest_spacing()).round().max(1.0) as usize;
+        let particles = block(m, m, n, n, l / n as f32);
+
+        let mut universe = Universe::from_particles(l + 2.0 * m, l + 2.0 * m, particles)?;
+        let h = universe.get_smoothing_length();
+        universe.set_gravity(0.0, 0.0);
+        universe.set_viscosity(self.lid_speed * l / self.reynolds / h);
+
+        // Walls one h thick, with their inner faces on the edges of the cavity
+        universe.add_box_obstacle(m - 0.5 * h, m + 0.5 * l, h, l + 2.0 * h);
+        universe.add_box_obstacle(m + l + 0.5 * h, m + 0.5 * l, h, l + 2.0 * h);
+        universe.add_box_obstacle(m + 0.5 * l, m - 0.5 * h, l + 2.0 * h, h);
+
+        let travel = self.lid_speed * self.duration;
+        let length = l + 2.0 * h + travel;
+        let x0 = m - h - travel + 0.5 * length;
+        let y = m + l + 0.5 * h;
+        let lid = universe.add_box_obstacle(x0, y, length, h);
+        universe.add_obstacle_keyframe(lid, 0.0, x0, y, 0.0)?;
+        universe.add_obstacle_keyframe(lid, self.duration, x0 + travel, y, 0.0)?;
+
+        Ok(universe)
+    }
+
+    fn run(&self) -> Result<Report, SpherroError> {
+        let mut universe = self.build()?;
+        let l = self.size;
+        let m = 0.5 * (universe.get_width() - l);
+
+        let points: Vec<Vector2f> = LidDrivenCavity::MEASURED.iter().map(|&(y, _)| {
+            Vector2f::new(m + 0.5 * l, m + y * l)
+        }).collect();
+        let mut profile = vec![0.0; points.len()];
+        let mut frames = 0;
+        while universe.get_time() < self.duration {
+            universe.update(self.dt)?;
+
+            if universe.get_time() < 0.75 * self.duration {
+                continue;
+            }
+
+            for (u, sample) in profile.iter_mut().zip(universe.sample_many(&points)) {
+                *u += sample.map_or(0.0, |s| s.vx);
+            }
+            frames += 1;
+        }
+
+        let mut errors = Errors::new();
+        for (u, &(_, expected)) in profile.iter().zip(LidDrivenCavity::MEASURED.iter()) {
+            let u = u / frames.max(1) as f32 / self.lid_speed;
+            errors.push(u - expected);
+        }
+
+        Ok(errors.report(self.name(), "centerline velocity", "Ghia, Ghia & Shin"))
+    }
+}
+
+// A decaying array of vortices in a square box that is periodic along both
+// axes, where u = U sin(kx) cos(ky), v = -U cos(kx) sin(ky) with k = 2pi/L
+// is an exact solution that decays as exp(-2 nu k^2 t). Compares the particle
+// velocities at the end of the run, relative to U
+pub struct TaylorGreen {
+    pub size: f32,
+    pub speed: f32,
+    pub viscosity: f32,
+    pub dt: f32,
+    pub duration: f32,
+}
+
+impl Default for TaylorGreen {
+    fn default() -> TaylorGreen {
+        TaylorGreen { size: 400.0, speed: 500.0, viscosity: 50.0, dt: 0.002, duration: 0.5 }
+    }
+}
+
+impl TaylorGreen {
+    fn k(&self) -> f32 {
+        2.0 * std::f32::consts::PI / self.size
+    }
+
+    fn velocity(&self, pos: Vector2f, decay: f32) -> Vector2f {
+        let (kx, ky) = (self.k() * pos.x, self.k() * pos.y);
+        decay * self.speed * Vector2f::new(kx.sin() * ky.cos(), -kx.cos() * ky.sin())
+    }
+}
+
+impl Scenario for TaylorGreen {
+    fn name(&self) -> &'static str {
+        "taylor_green"
+    }
+
+    fn build(&self) -> Result<Universe, SpherroError> {
+        let l = self.size;
+        let n = (l / rest_spacing()).round().max(1.0) as usize;
+        let mut particles = block(0.0, 0.0, n, n, l / n as f32);
+        for pi in particles.iter_mut() {
+            pi.vel = self.velocity(pi.pos, 1.0);
+        }
+
+        let mut universe = Universe::from_particles(l, l, particles)?;
+        universe.set_periodic(true, true);
+        universe.set_gravity(0.0, 0.0);
+        universe.set_viscosity(self.viscosity);
+        Ok(universe)
+    }
+
+    fn run(&self) -> Result<Report, SpherroError> {
+        let mut universe = self.build()?;
+        while universe.get_time() < self.duration {
+            universe.update(self.dt)?;
+        }
+
+        let nu = universe.kinematic_viscosity();
+        let decay = (-2.0 * nu * self.k().powi(2) * universe.get_time()).exp();
+
+        let mut errors = Errors::new();
+        for pi in universe.get_particles().iter() {
+            let expected = self.velocity(pi.pos, decay);
+            errors.push((pi.vel - expected).magnitude() / self.speed);
+        }
+
+        Ok(errors.report(self.name(), "velocity", "exact decay"))
+    }
+}
+
+// Sets up fluid between two no-slip plates, in a channel that is periodic
+// along x. The plates are kinematic obstacles exactly as wide as the
+// universe, so that their boundary particles line up across the seam.
+// Returns the universe and the index of the top plate
+fn channel(columns: usize, rows: usize, viscosity: Viscosity) -> Result<(Universe, usize), SpherroError> {
+    let s = rest_spacing();
+    let (w, h, m) = (columns as f32 * s, rows as f32 * s, 2.0 * Universe::default_smoothing_length());
+    let particles = block(0.0, m, columns, rows, s);
+
+    let mut universe = Universe::from_particles(w, h + 2.0 * m, particles)?;
+    universe.set_periodic(true, false);
+    universe.set_gravity(0.0, 0.0);
+    universe.set_viscosity_model(viscosity);
+
+    // Plates one smoothing length thick
+    let t = universe.get_smoothing_length();
+    universe.add_box_obstacle(0.5 * w, m - 0.5 * t, w, t);
+    let top = universe.add_box_obstacle(0.5 * w, m + h + 0.5 * t, w, t);
+    Ok((universe, top))
+}
+
+// Horizontal velocity across a channel at heights `ys` relative to the
+// channel, averaged along x. The channel is in the middle of the universe
+fn channel_profile(universe: &Universe, rows: usize, ys: &[f32]) -> Vec<f32> {
+    let (w, h) = (universe.get_width(), rows as f32 * rest_spacing());
+    let m = 0.5 * (universe.get_height() - h);
+    let stations = 8;
+
+    let points: Vec<Vector2f> = ys.iter().flat_map(|&y| {
+        (0..stations).map(move |i| Vector2f::new((i as f32 + 0.5) * w / stations as f32, m + y * h))
+    }).collect();
+
+    universe.sample_many(&points).chunks(stations).map(|row| {
+        row.iter().map(|s| s.map_or(0.0, |s| s.vx)).sum::<f32>() / stations as f32
+    }).collect()
+}
+
+// Heights the channel flows are compared at, relative to the channel. The
+// samples closest to the plates are left out, since the kernel reaches
+// into the plates there
+const CHANNEL_SAMPLES: [f32; 7] = [0.2, 0.3, 0.4, 0.5, 0.6, 0.7, 0.8];
+
+// Terms of the series solutions summed for the startup of the channel flows
+const SERIES_TERMS: usize = 64;
+
+// Flow between two plates at rest, driven by a constant body force f along
+// the channel. Starting from rest,
+// u(y, t) = f/(2 nu) y(h - y) - sum over odd n of
+//           4 f h^2 / (nu pi^3 n^3) sin(n pi y/h) exp(-n^2 pi^2 nu t/h^2).
+// Compares the velocity across the channel at the end of the run, relative
+// to the steady state speed at the center.
+//
+// The boundary particles of the plates pin the fluid next to them a little,
+// so the flow lags behind more the weaker it is driven
+pub struct Poiseuille {
+    pub columns: usize,
+    pub rows: usize,
+    pub viscosity: Viscosity,
+    pub max_speed: f32, // steady state speed at the center
+    pub dt: f32,
+    pub duration: f32,
+}
+
+impl Default for Poiseuille {
+    fn default() -> Poiseuille {
+        Poiseuille { columns: 16, rows: 12, viscosity: Viscosity::Laplacian(200.0), max_speed: 1000.0, dt: 0.002, duration: 2.0 }
+    }
+}
+
+impl Poiseuille {
+    pub fn height(&self) -> f32 {
+        self.rows as f32 * rest_spacing()
+    }
+
+    // Body force giving `max_speed` at the center once steady
+    fn force(&self, nu: f32) -> f32 {
+        8.0 * nu * self.max_speed / self.height().powi(2)
+    }
+
+    // Analytic velocity at height y above the bottom plate
+    pub fn velocity(&self, y: f32, nu: f32, t: f32) -> f32 {
+        let (h, f) = (self.height(), self.force(nu));
+        let pi = std::f32::consts::PI;
+        let transient = (0..SERIES_TERMS).map(|i| {
+            let n = (2 * i + 1) as f32;
+            4.0 * f * h * h / (nu * pi.powi(3) * n.powi(3))
+                * (n * pi * y / h).sin() * (-(n * pi / h).powi(2) * nu * t).exp()
+        }).sum::<f32>();
+
+        f / (2.0 * nu) * y * (h - y) - transient
+    }
+}
+
+impl Poiseuille {
+    // Simulated velocity along the channel at heights `ys` relative to the
+    // channel, averaged along it
+    pub fn profile(&self, universe: &Universe, ys: &[f32]) -> Vec<f32> {
+        channel_profile(universe, self.rows, ys)
+    }
+}
+
+impl Scenario for Poiseuille {
+    fn name(&self) -> &'static str {
+        "poiseuille"
+    }
+
+    fn build(&self) -> Result<Universe, SpherroError> {
+        let (mut universe, _) = channel(self.columns, self.rows, self.viscosity)?;
+        let f = self.force(universe.kinematic_viscosity());
+        universe.set_body_force(move |_, _| Vector2f::new(f, 0.0))?;
+        Ok(universe)
+    }
+
+    fn run(&self) -> Result<Report, SpherroError> {
+        let mut universe = self.build()?;
+        while universe.get_time() < self.duration {
+            universe.update(self.dt)?;
+        }
+
+        let (nu, t) = (universe.kinematic_viscosity(), universe.get_time());
+        let profile = channel_profile(&universe, self.rows, &CHANNEL_SAMPLES);
+
+        let mut errors = Errors::new();
+        for (u, &y) in profile.iter().zip(CHANNEL_SAMPLES.iter()) {
+            let expected = self.velocity(y * self.height(), nu, t);
+            errors.push((u - expected) / self.max_speed);
+        }
+
+        Ok(errors.report(self.name(), "velocity across the channel", "series solution"))
+    }
+}
+
+// Flow between a plate at rest and one sliding along at speed U, starting
+// from rest. u(y, t) = U y/h - U sum over n of
+// 2 (-1)^(n+1) / (n pi) sin(n pi y/h) exp(-n^2 pi^2 nu t/h^2).
+// Compares the velocity across the channel at the end of the run, relative
+// to U
+pub struct Couette {
+    pub columns: usize,
+    pub rows: usize,
+    pub viscosity: Viscosity,
+    pub plate_speed: f32,
+    pub dt: f32,
+    pub duration: f32,
+}
+
+impl Default for Couette {
+    fn default() -> Couette {
+        Couette { columns: 16, rows: 12, viscosity: Viscosity::Laplacian(200.0), plate_speed: 100.0, dt: 0.002, duration: 2.0 }
+    }
+}
+
+impl Couette {
+    fn height(&self) -> f32 {
+        self.rows as f32 * rest_spacing()
+    }
+
+    fn velocity(&self, y: f32, nu: f32, t: f32) -> f32 {
+        let h = self.height();
+        let pi = std::f32::consts::PI;
+        let transient = (1..SERIES_TERMS+1).map(|n| {
+            let sign = if n % 2 == 1 { 1.0 } else { -1.0 };
+            let n = n as f32;
+            sign * 2.0 / (n * pi) * (n * pi * y / h).sin() * (-(n * pi / h).powi(2) * nu * t).exp()
+        }).sum::<f32>();
+
+        self.plate_speed * (y / h - transient)
+    }
+}
+
+impl Scenario for Couette {
+    fn name(&self) -> &'static str {
+        "couette"
+    }
+
+    fn build(&self) -> Result<Universe, SpherroError> {
+        let (mut universe, top) = channel(self.columns, self.rows, self.viscosity)?;
+
+        // The plate's boundary particles wrap around, so it can slide
+        // arbitrarily far
+        let body = &universe.get_bodies()[top];
+        let (x0, y) = (body.pos.x, body.pos.y);
+        let travel = self.plate_speed * self.duration;
+        universe.add_obstacle_keyframe(top, 0.0, x0, y, 0.0)?;
+        universe.add_obstacle_keyframe(top, self.duration, x0 + travel, y, 0.0)?;
+        Ok(universe)
+    }
+
+    fn run(&self) -> Result<Report, SpherroError> {
+        let mut universe = self.build()?;
+        while universe.get_time() < self.duration {
+            universe.update(self.dt)?;
+        }
+
+        let (nu, t) = (universe.kinematic_viscosity(), universe.get_time());
+        let profile = channel_profile(&universe, self.rows, &CHANNEL_SAMPLES);
+
+        let mut errors = Errors::new();
+        for (u, &y) in profile.iter().zip(CHANNEL_SAMPLES.iter()) {
+            let expected = self.velocity(y * self.height(), nu, t);
+            errors.push((u - expected) / self.plate_speed);
+        }
+
+        Ok(errors.report(self.name(), "velocity across the channel", "series solution"))
+    }
+}
@@ -0,0 +1,23 @@
+use wasm_bindgen::prelude::*;
+use crate::particle::Particle;
+
+// How many pressure iterations each step takes. Every iteration pushes the
+// particles apart some more, so more iterations make for a stiffer fluid
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PressureSolver {
+    Fixed, // always the iteration limit
+    Adaptive, // stops early once the fluid is compressed less than the tolerance
+}
+
+// How much the particles are compressed past the rest density on average,
+// relative to it. Particles below the rest density count as zero, so that
+// the free surface doesn't make up for the compressed bulk
+pub(crate) fn average_compression(particles: &[Particle], rest_rho: f32) -> f32 {
+    if particles.is_empty() {
+        return 0.0;
+    }
+
+    let total: f32 = particles.iter().map(|pi| (pi.rho / rest_rho - 1.0).max(0.0)).sum();
+    total / particles.len() as f32
+}
@@ -0,0 +1,119 @@
+use wasm_bindgen::prelude::*;
+use cgmath::{InnerSpace};
+use crate::particle::Particle;
+
+// A particle crossing this fraction of the smoothing radius in a single step
+// is moving too fast for its neighbourhood to keep up. A settled tank stays
+// well under 0.2, a hard push with the mouse peaks around 0.5
+const MAX_CFL: f32 = 1.0;
+
+// Compression relative to the rest density. A settled tank peaks around 0.7
+const MAX_DENSITY_ERROR: f32 = 2.0;
+
+// Number of times a failing step is retried, halving dt every time
+const MAX_RETRIES: u32 = 3;
+
+// Velocities are scaled by this before the last retry
+const DAMPING: f32 = 0.5;
+
+// Diagnostic records are dropped once there are more than this many
+// waiting to be read
+pub const MAX_TRIPS: usize = 64;
+
+// What the stability monitor caught
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StabilityCheck {
+    NonFinite, // position, velocity, density or temperature is NaN or infinite
+    Speed, // value is the CFL number, speed * dt / H
+    Density, // value is the relative compression, (rho - rest) / rest
+}
+
+// What was done about it
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Recovery {
+    Substep, // rolled back and retried with a smaller dt
+    Damp, // rolled back, damped velocities and retried with the smallest dt
+    Failed, // rolled back and gave up on the step
+}
+
+// A diagnostic record of a step that tripped the stability monitor
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug)]
+pub struct Trip {
+    pub time: f32, // simulation time at the start of the step
+    pub dt: f32, // dt of the attempt that tripped
+    pub check: StabilityCheck,
+    pub id: u32, // worst offending particle
+    pub value: f32,
+    pub threshold: f32,
+    pub recovery: Recovery,
+}
+
+pub struct StabilityMonitor {
+    pub enabled: bool,
+    pub max_cfl: f32,
+    pub max_density_error: f32,
+    pub max_retries: u32,
+    pub damping: f32,
+}
+
+impl StabilityMonitor {
+    pub fn new() -> StabilityMonitor {
+        StabilityMonitor {
+            enabled: false,
+            max_cfl: MAX_CFL,
+            max_density_error: MAX_DENSITY_ERROR,
+            max_retries: MAX_RETRIES,
+            damping: DAMPING,
+        }
+    }
+
+    // Checks the particles after a step of `dt` starting at `time`. Returns
+    // the worst violation, with non-finite values taking precedence. The
+    // recovery is filled in by the caller
+    pub fn check(&self, particles: &[Particle], rest_rho: f32, h: f32,
+                 time: f32, dt: f32) -> Option<Trip> {
+        let trip = |check, id, value, threshold| Trip {
+            time, dt, check, id, value, threshold, recovery: Recovery::Substep,
+        };
+
+        let bad = particles.iter().find(|pi| {
+            !pi.pos.x.is_finite() || !pi.pos.y.is_finite()
+            || !pi.vel.x.is_finite() || !pi.vel.y.is_finite()
+            || !pi.rho.is_finite() || !pi.temperature.is_finite()
+        });
+        if let Some(pi) = bad {
+            return Some(trip(StabilityCheck::NonFinite, pi.id, f32::NAN, 0.0));
+        }
+
+        let mut worst: Option<Trip> = None;
+        for pi in particles.iter() {
+            let cfl = pi.vel.magnitude() * dt / h;
+            let compression = (pi.rho - rest_rho) / rest_rho;
+
+            // Compare how far over the limit each one is, so that a speed and
+            // a density violation can be ranked against each other
+            let candidates = [
+                (StabilityCheck::Speed, cfl, self.max_cfl),
+                (StabilityCheck::Density, compression, self.max_density_error),
+            ];
+            for &(check, value, threshold) in candidates.iter() {
+                if value <= threshold {
+                    continue;
+                }
+
+                let worse = match worst {
+                    Some(t) => value / threshold > t.value / t.threshold,
+                    None => true,
+                };
+                if worse {
+                    worst = Some(trip(check, pi.id, value, threshold));
+                }
+            }
+        }
+
+        worst
+    }
+}
@@ -0,0 +1,105 @@
+use wasm_bindgen::prelude::*;
+
+// Solver health for the last call to `Universe::update`. Times are in
+// milliseconds and add up over every substep and rolled back attempt
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct StepStats {
+    pub time: f32, // simulation time at the end of the update
+    pub dt: f32,
+    pub substeps: u32,
+    pub particles: usize,
+
+    // Relative to the rest density, (rho - rest) / rest
+    pub density_error_min: f32,
+    pub density_error_max: f32,
+    pub density_error_avg: f32,
+
+    pub pressure_iterations: u32,
+    pub max_speed: f32,
+    pub kinetic_energy: f32,
+    pub potential_energy: f32, // relative to the origin, along gravity
+    pub total_mass: f32,
+
+    pub neighbours_avg: f32,
+    pub neighbours_max: usize,
+
+    pub neighbours_ms: f32, // grid construction and neighbour queries
+    pub density_ms: f32,
+    pub forces_ms: f32, // surface normals and non-pressure forces
+    pub pressure_ms: f32, // pressure iterations, including their density updates
+    pub boundary_ms: f32, // walls and body collisions
+    pub bodies_ms: f32,
+    pub events_ms: f32,
+    pub tracers_ms: f32,
+    pub total_ms: f32,
+}
+
+#[wasm_bindgen]
+impl StepStats {
+    // A single line of JSON, for logs and dashboards
+    pub fn to_json(&self) -> String {
+        format!(concat!(
+            "{{\"time\":{},\"dt\":{},\"substeps\":{},\"particles\":{},",
+            "\"density_error_min\":{},\"density_error_max\":{},\"density_error_avg\":{},",
+            "\"pressure_iterations\":{},\"max_speed\":{},",
+            "\"kinetic_energy\":{},\"potential_energy\":{},\"total_mass\":{},",
+            "\"neighbours_avg\":{},\"neighbours_max\":{},",
+            "\"neighbours_ms\":{},\"density_ms\":{},\"forces_ms\":{},\"pressure_ms\":{},",
+            "\"boundary_ms\":{},\"bodies_ms\":{},\"events_ms\":{},\"tracers_ms\":{},\"total_ms\":{}}}"),
+            json_f32(self.time), json_f32(self.dt), self.substeps, self.particles,
+            json_f32(self.density_error_min), json_f32(self.density_error_max),
+            json_f32(self.density_error_avg),
+            self.pressure_iterations, json_f32(self.max_speed),
+            json_f32(self.kinetic_energy), json_f32(self.potential_energy),
+            json_f32(self.total_mass),
+            json_f32(self.neighbours_avg), self.neighbours_max,
+            json_f32(self.neighbours_ms), json_f32(self.density_ms),
+            json_f32(self.forces_ms), json_f32(self.pressure_ms),
+            json_f32(self.boundary_ms), json_f32(self.bodies_ms),
+            json_f32(self.events_ms), json_f32(self.tracers_ms),
+            json_f32(self.total_ms),
+        )
+    }
+}
+
+impl StepStats {
+    // Every field by its name in `to_json`, as a float
+    pub fn get(&self, name: &str) -> Option<f32> {
+        Some(match name {
+            "time" => self.time,
+            "dt" => self.dt,
+            "substeps" => self.substeps as f32,
+            "particles" => self.particles as f32,
+            "density_error_min" => self.density_error_min,
+            "density_error_max" => self.density_error_max,
+            "density_error_avg" => self.density_error_avg,
+            "pressure_iterations" => self.pressure_iterations as f32,
+            "max_speed" => self.max_speed,
+            "kinetic_energy" => self.kinetic_energy,
+            "potential_energy" => self.potential_energy,
+            "total_mass" => self.total_mass,
+            "neighbours_avg" => self.neighbours_avg,
+            "neighbours_max" => self.neighbours_max as f32,
+            "neighbours_ms" => self.neighbours_ms,
+            "density_ms" => self.density_ms,
+            "forces_ms" => self.forces_ms,
+            "pressure_ms" => self.pressure_ms,
+            "boundary_ms" => self.boundary_ms,
+            "bodies_ms" => self.bodies_ms,
+            "events_ms" => self.events_ms,
+            "tracers_ms" => self.tracers_ms,
+            "total_ms" => self.total_ms,
+            _ => return None,
+        })
+    }
+}
+
+// JSON has no NaN or infinity
+fn json_f32(v: f32) -> String {
+    if v.is_finite() {
+        v.to_string()
+    } else {
+        "null".to_string()
+    }
+}
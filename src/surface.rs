@@ -0,0 +1,114 @@
+use wasm_bindgen::prelude::*;
+use cgmath::{InnerSpace};
+use crate::util::*;
+use crate::domain::Domain;
+use crate::particle::Particle;
+use crate::universe::H;
+
+// Particles whose colour field gradient is larger than this are flagged
+// as being on the free surface
+const COLOR_FIELD_THRESHOLD: f32 = 0.5;
+
+// A particle in the bulk has around 45 neighbours at rest, one on a flat
+// surface has roughly 30. That's for the default smoothing length, the
+// counts grow with the area of the neighbourhood
+const NEIGHBOUR_COUNT_THRESHOLD: f32 = 36.0;
+
+// Ratio between the smaller and larger eigenvalue of the neighbourhood
+// covariance. A full disc of neighbours gives 1.0, a half disc about 0.3
+const COVARIANCE_THRESHOLD: f32 = 0.6;
+
+// Method used to decide whether a particle lies on the free surface.
+// ColorField uses the surface normal computed for surface tension,
+// NeighbourCount looks for particle deficiency and Covariance
+// looks for a lopsided neighbourhood
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SurfaceClassifier {
+    ColorField,
+    NeighbourCount,
+    Covariance,
+}
+
+// Returns true if particle `i` is on the free surface, given its neighbours
+// within 2h. `near_wall` particles are never on the free surface, since the
+// walls aren't sampled by particles and their particle deficiency looks
+// exactly like a free surface
+pub fn is_surface(classifier: SurfaceClassifier, domain: &Domain, particles: &[Particle],
+                  i: usize, neighbours: &[usize], h: f32, near_wall: bool) -> bool {
+    if near_wall {
+        return false;
+    }
+
+    let pi = &particles[i];
+    match classifier {
+        SurfaceClassifier::ColorField => {
+            pi.normal.magnitude() > COLOR_FIELD_THRESHOLD
+        },
+        SurfaceClassifier::NeighbourCount => {
+            (neighbours.len() as f32) < NEIGHBOUR_COUNT_THRESHOLD * (h / H).powi(2)
+        },
+        SurfaceClassifier::Covariance => {
+            if neighbours.len() < 3 {
+                return true;
+            }
+
+            let n = neighbours.len() as f32;
+            let mean = neighbours.iter()
+                                 .map(|&j| domain.offset(particles[j].pos, pi.pos))
+                                 .sum::<Vector2f>() / n;
+
+            let (mut cxx, mut cxy, mut cyy) = (0.0, 0.0, 0.0);
+            for &j in neighbours.iter() {
+                let d = domain.offset(particles[j].pos, pi.pos) - mean;
+                cxx += d.x * d.x;
+                cxy += d.x * d.y;
+                cyy += d.y * d.y;
+            }
+
+            // Eigenvalues of the symmetric 2x2 covariance matrix
+            let tr = (cxx + cyy) / n;
+            let det = (cxx * cyy - cxy * cxy) / (n * n);
+            let disc = (tr * tr / 4.0 - det).max(0.0).sqrt();
+            let l_max = tr / 2.0 + disc;
+            let l_min = tr / 2.0 - disc;
+
+            l_max <= 0.0 || l_min / l_max < COVARIANCE_THRESHOLD
+        },
+    }
+}
+
+// Estimates the length of the free surface by treating the surface particles
+// as vertices of a polyline, each one connected to its two closest surface
+// neighbours within `r`
+pub fn surface_length(domain: &Domain, particles: &[Particle], r: f32) -> f32 {
+    let surface: Vec<&Particle> = particles.iter().filter(|pi| pi.is_surface).collect();
+
+    let mut length = 0.0;
+    for (i, pi) in surface.iter().enumerate() {
+        let (mut d1, mut d2) = (f32::INFINITY, f32::INFINITY);
+        for (j, pj) in surface.iter().enumerate() {
+            let d = domain.offset(pi.pos, pj.pos).magnitude();
+            if i == j || d > r {
+                continue;
+            }
+
+            if d < d1 {
+                d2 = d1;
+                d1 = d;
+            } else if d < d2 {
+                d2 = d;
+            }
+        }
+
+        // Every edge is counted from both of its endpoints
+        if d1.is_finite() {
+            length += 0.5 * d1;
+        }
+        if d2.is_finite() {
+            length += 0.5 * d2;
+        }
+    }
+
+    length
+}
@@ -0,0 +1,241 @@
+use std::collections::{HashMap, VecDeque};
+use wasm_bindgen::prelude::*;
+use crate::util::*;
+use crate::domain::Domain;
+
+// Positions kept per tracer by default, including the current one
+const HISTORY: usize = 64;
+
+// The oldest tracers are dropped once there are more than this many, so that
+// emitters can run forever
+pub const MAX_TRACERS: usize = 4096;
+
+// Shortest period an emitter can have. Shorter ones would release a pile of
+// tracers on top of each other every step
+pub const MIN_EMITTER_PERIOD: f32 = 1e-3;
+
+// Most releases an emitter makes at once. The ones due beyond that, after a
+// long step, are skipped
+const MAX_RELEASES: usize = 16;
+
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TracerIntegrator {
+    Rk2, // midpoint
+    Rk4,
+}
+
+// A massless marker carried along by the fluid. It takes no part in the
+// simulation
+#[derive(Clone, Debug)]
+pub struct Tracer {
+    pub id: u32,
+    pub pos: Vector2f,
+    pub age: f32,
+
+    // Emitter and point along it that released the tracer, if any. Tracers
+    // from the same source make up a streakline
+    pub source: Option<(usize, usize)>,
+
+    // Past positions, oldest first and ending with the current one
+    pub history: VecDeque<Vector2f>,
+}
+
+// Releases a tracer at each of `count` points evenly spread from `from` to
+// `to`, every `period` seconds
+#[derive(Clone, Debug)]
+pub struct Emitter {
+    pub from: Vector2f,
+    pub to: Vector2f,
+    pub count: usize,
+    pub period: f32,
+    next: f32, // simulation time of the next release
+}
+
+impl Emitter {
+    pub fn new(from: Vector2f, to: Vector2f, count: usize, period: f32, time: f32) -> Emitter {
+        Emitter { from, to, count, period, next: time }
+    }
+}
+
+// `count` points evenly spread from `from` to `to`, both included
+fn line_points(from: Vector2f, to: Vector2f, count: usize) -> Vec<Vector2f> {
+    (0..count).map(|i| {
+        let t = if count > 1 { i as f32 / (count - 1) as f32 } else { 0.0 };
+        from + (to - from) * t
+    }).collect()
+}
+
+pub struct Tracers {
+    tracers: Vec<Tracer>,
+    emitters: Vec<Emitter>,
+    next_id: u32,
+    pub integrator: TracerIntegrator,
+    pub history: usize,
+    pub lifetime: f32, // seconds before a tracer is dropped, or 0 to keep it
+}
+
+impl Tracers {
+    pub fn new() -> Tracers {
+        Tracers {
+            tracers: Vec::new(),
+            emitters: Vec::new(),
+            next_id: 0,
+            integrator: TracerIntegrator::Rk4,
+            history: HISTORY,
+            lifetime: 0.0,
+        }
+    }
+
+    pub fn get(&self) -> &[Tracer] {
+        &self.tracers
+    }
+
+    pub fn len(&self) -> usize {
+        self.tracers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tracers.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.tracers.clear();
+        self.emitters.clear();
+    }
+
+    // Returns the tracer's id
+    pub fn seed(&mut self, pos: Vector2f, source: Option<(usize, usize)>) -> u32 {
+        let id = self.next_id;
+        self.next_id += 1;
+
+        let mut history = VecDeque::with_capacity(self.history);
+        if self.history > 0 {
+            history.push_back(pos);
+        }
+        self.tracers.push(Tracer { id, pos, age: 0.0, source, history });
+
+        if self.tracers.len() > MAX_TRACERS {
+            let excess = self.tracers.len() - MAX_TRACERS;
+            self.tracers.drain(..excess);
+        }
+
+        id
+    }
+
+    pub fn seed_line(&mut self, from: Vector2f, to: Vector2f, count: usize) {
+        for p in line_points(from, to, count) {
+            self.seed(p, None);
+        }
+    }
+
+    pub fn add_emitter(&mut self, emitter: Emitter) -> usize {
+        self.emitters.push(emitter);
+        self.emitters.len() - 1
+    }
+
+    // Releases the tracers that are due at `time`
+    pub fn emit(&mut self, time: f32) {
+        for e in 0..self.emitters.len() {
+            let (next, period) = (self.emitters[e].next, self.emitters[e].period);
+            if next > time {
+                continue;
+            }
+
+            // Counted rather than stepped through, since late in a run a
+            // short period can be lost to rounding when added to `next`
+            let due = ((time - next) / period).floor() + 1.0;
+            for _ in 0..(due as usize).min(MAX_RELEASES) {
+                let emitter = &self.emitters[e];
+                let points = line_points(emitter.from, emitter.to, emitter.count);
+                for (k, p) in points.into_iter().enumerate() {
+                    self.seed(p, Some((e, k)));
+                }
+            }
+
+            self.emitters[e].next = next + due * period;
+        }
+    }
+
+    // Moves every tracer through the velocity field `vel` over `dt`. The
+    // field is taken to be frozen over the step. Tracers outside the fluid,
+    // where `vel` is None, stay where they are
+    pub fn advect<F>(&mut self, vel: F, domain: &Domain, dt: f32) where F: Fn(Vector2f) -> Option<Vector2f> {
+        let v = |p: Vector2f| vel(p).unwrap_or_else(vec2f_zero);
+
+        for tracer in self.tracers.iter_mut() {
+            let x = tracer.pos;
+            let dx = match self.integrator {
+                TracerIntegrator::Rk2 => {
+                    let k1 = v(x);
+                    dt * v(x + 0.5 * dt * k1)
+                },
+                TracerIntegrator::Rk4 => {
+                    let k1 = v(x);
+                    let k2 = v(x + 0.5 * dt * k1);
+                    let k3 = v(x + 0.5 * dt * k2);
+                    let k4 = v(x + dt * k3);
+                    (dt / 6.0) * (k1 + 2.0 * k2 + 2.0 * k3 + k4)
+                },
+            };
+
+            // Kept inside the tank, and wrapped around periodic axes
+            let p = domain.wrap(x + dx);
+            let clamp = |v: f32, size: f32, periodic: bool| {
+                if periodic { v } else { clamp_f32(v, 0.0, size) }
+            };
+            tracer.pos = Vector2f::new(clamp(p.x, domain.width, domain.periodic_x),
+                                       clamp(p.y, domain.height, domain.periodic_y));
+            tracer.age += dt;
+
+            if self.history > 0 {
+                tracer.history.push_back(tracer.pos);
+                while tracer.history.len() > self.history {
+                    tracer.history.pop_front();
+                }
+            } else {
+                tracer.history.clear();
+            }
+        }
+
+        if self.lifetime > 0.0 {
+            let lifetime = self.lifetime;
+            self.tracers.retain(|t| t.age <= lifetime);
+        }
+    }
+
+    // Path lines: every tracer's history, as line segments. Appended to `out`
+    // SEGMENT_STRIDE floats apiece
+    pub fn pathlines(&self, domain: &Domain, out: &mut Vec<f32>) {
+        for tracer in self.tracers.iter() {
+            for (a, b) in tracer.history.iter().zip(tracer.history.iter().skip(1)) {
+                push_segment(domain, *a, *b, out);
+            }
+        }
+    }
+
+    // Streaklines: the tracers released from the same point, joined in the
+    // order they were released. Appended to `out` like `pathlines`
+    pub fn streaklines(&self, domain: &Domain, out: &mut Vec<f32>) {
+        let mut last: HashMap<(usize, usize), Vector2f> = HashMap::new();
+        for tracer in self.tracers.iter() {
+            if let Some(source) = tracer.source {
+                if let Some(prev) = last.insert(source, tracer.pos) {
+                    push_segment(domain, prev, tracer.pos, out);
+                }
+            }
+        }
+    }
+}
+
+// Segments that cross a periodic seam would be drawn across the whole domain,
+// so they are left out
+fn push_segment(domain: &Domain, a: Vector2f, b: Vector2f, out: &mut Vec<f32>) {
+    let d = b - a;
+    let wrapped = domain.offset(b, a);
+    if (d.x - wrapped.x).abs() > 1e-3 || (d.y - wrapped.y).abs() > 1e-3 {
+        return;
+    }
+
+    out.extend_from_slice(&[a.x, a.y, b.x, b.y]);
+}
@@ -1,15 +1,35 @@
+use std::collections::{HashMap, VecDeque};
 use wasm_bindgen::prelude::*;
 use cgmath::{InnerSpace};
-use rand::Rng;
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
 use crate::util::*;
 use crate::particle::{Particle};
 use crate::accelerators::{Accelerator, Grid};
 use crate::initializer;
 use crate::kernel::*;
 use crate::force::Force;
-
-const MASS: f32 = 100.0;
-const H: f32 = 35.0;
+use crate::surface::{self, SurfaceClassifier};
+use crate::domain::Domain;
+use crate::rigid::{RigidBody, BoundarySample, Shape, Keyframe};
+use crate::body_force::{BodyForce, BodyForcePreset};
+use crate::removal::Removal;
+use crate::error::SpherroError;
+use crate::stability::{self, StabilityMonitor, Recovery, Trip};
+use crate::stats::StepStats;
+use crate::heat::{HeatSource, HeatZone, Wall};
+use crate::sampling::{self, FieldSample};
+use crate::viscosity::{self, Viscosity, ViscosityModel};
+use crate::rheology::{self, Rheology, RheologyPreset};
+use crate::tracers::{Tracers, Tracer, Emitter, TracerIntegrator, MIN_EMITTER_PERIOD};
+use crate::recorder::{Input, Recording};
+use crate::solver::{self, PressureSolver};
+
+// Defaults of the particle mass, smoothing length, viscosity coefficient and
+// stiffness, which can all be changed at runtime. The rest density follows
+// the particle mass
+pub(crate) const MASS: f32 = 100.0;
+pub(crate) const H: f32 = 35.0;
 const VISC: f32 = 0.5;
 const REST_RHO: f32 = MASS / (130.0 * 130.0);
 
@@ -18,155 +38,1723 @@ const REST_RHO: f32 = MASS / (130.0 * 130.0);
 // to this value. Note that this is scaled by dt
 const MAX_FORCE_MAG: f32 = 450.0;
 
-// Boundary parameters
-const BOUNDARY_COR: f32 = 0.5; // Coefficient of restitution
-const BOUNDARY_MIN_DV: f32 = 500.0; // If particle is too slow, it is accelerated to atleast this much
+// Boundary parameters
+const BOUNDARY_COR: f32 = 0.5; // Coefficient of restitution
+const BOUNDARY_MIN_DV: f32 = 500.0; // If particle is too slow, it is accelerated to atleast this much
+
+pub(crate) const GRAVITY: f32 = -10000.0;
+const K: f32 = 10.0;
+
+// Exponent of the equation of state
+const GAMMA: i32 = 7;
+
+const PRESSURE_ITERATIONS: u32 = 4;
+
+// Average compression the adaptive pressure solver settles for, relative to
+// the rest density. The equation of state is soft, so settled fluid is
+// compressed by about 0.3 with 4 iterations and 0.2 with 8
+const DEFAULT_PRESSURE_TOLERANCE: f32 = 0.25;
+
+// Mass per unit area of the fluid once it has settled under gravity. This was
+// measured from a settled dam break; the equation of state is soft enough that
+// it is noticeably higher than what REST_RHO alone would suggest
+pub(crate) const FLUID_AREA_DENSITY: f32 = MASS / 262.0;
+
+// Highest kinematic viscosity, times dt/h^2, that non-Newtonian phases can
+// reach. Keeps the explicit viscosity step stable where the strain rate
+// goes to zero. It also means that Bingham fluids creep instead of holding
+// their shape below the yield stress
+const MAX_RHEOLOGY_VISCOSITY: f32 = 0.1;
+
+// Shear modulus of granular phases, relative to the stiffness. Much stiffer
+// than the equation of state, so that piles barely deform under their own
+// weight before they yield. Shear waves still take about 5 steps to cross a
+// neighbourhood at dt = 0.005
+const SHEAR_MODULUS: f32 = 30.0 * GAMMA as f32;
+
+// The velocity gradients are only corrected where the correction matrix has
+// at least this determinant. It is about 1 inside the fluid
+const MIN_GRADIENT_CORRECTION: f32 = 0.5;
+
+// Smoothing length the velocity gradients are taken with, relative to the
+// smoothing length. The corrected gradient is exact for linear fields, but
+// still smooths out curvature by about (k h)^2 / 3 for a wave number k, so a
+// narrower kernel keeps more of small vortices. Much narrower and there are
+// too few neighbours for the granular stress to stay smooth
+const GRADIENT_SMOOTHING: f32 = 0.85;
+
+// Distance between the boundary particles sampled along rigid bodies,
+// relative to the smoothing length
+const BODY_SAMPLE_SPACING: f32 = 0.5;
+
+enum Event {
+    Spawn(usize, Vector2f),
+    Despawn(Removal),
+}
+
+#[wasm_bindgen]
+pub struct Universe {
+    particles: Vec<Particle>,
+    id_to_index: HashMap<u32, usize>,
+    next_id: u32,
+    width: f32,
+    height: f32,
+    periodic_x: bool,
+    periodic_y: bool,
+    forces: Vec<Force>,
+    events: Vec<Event>,
+    surface_tension: f32,
+    adhesion: f32,
+    surface_classifier: SurfaceClassifier,
+    bodies: Vec<RigidBody>,
+    boundary: Vec<BoundarySample>,
+    time: f32,
+    gravity: Vector2f,
+    body_force: BodyForce,
+    viscosity: Viscosity,
+    xsph: f32,
+    rheologies: Vec<Rheology>,
+    vorticity_confinement: f32,
+    conductivity: f32,
+    thermal_expansion: f32,
+    reference_temperature: f32,
+    heat_sources: Vec<HeatSource>,
+    tracers: Tracers,
+    monitor: StabilityMonitor,
+    trips: VecDeque<Trip>,
+    h: f32, // smoothing length
+    mass: f32, // of new particles
+    stiffness: f32,
+    kernel: SmoothingKernel,
+    pressure_solver: PressureSolver,
+    pressure_iterations: u32,
+    pressure_tolerance: f32,
+    last_pressure_iterations: u32, // run in the last step
+    stats: StepStats,
+    steps: u64,
+    rng: StdRng,
+    config: Option<initializer::Config>,
+    recording: Option<Recording>,
+    pristine: bool, // nothing has been done to it since it was made
+}
+
+// State needed to roll back a step that went unstable
+struct Checkpoint {
+    particles: Vec<Particle>,
+    bodies: Vec<RigidBody>,
+    time: f32,
+}
+
+type Neighbours = Vec<Vec<usize>>;
+
+fn validate_size(width: f32, height: f32) -> Result<(), SpherroError> {
+    if !(width.is_finite() && width > 0.0 && height.is_finite() && height > 0.0) {
+        return Err(SpherroError::InvalidConfig(format!(
+            "universe must have a positive size, got {}x{}", width, height,
+        )));
+    }
+
+    Ok(())
+}
+
+// Gradient of the smoothing kernel with respect to x_i. Coincident particles
+// have no defined direction, so they exert no force on each other
+fn grad_w(kernel: SmoothingKernel, h: f32, x_ij: Vector2f) -> Vector2f {
+    let r = x_ij.magnitude();
+    if r < 1e-6 {
+        return vec2f_zero();
+    }
+
+    let df = kernel.df(r / h);
+    (1.0 / h.powi(3)) * df * x_ij / r //TODO: Should be powi(2), but that explodes
+}
+
+#[wasm_bindgen]
+#[allow(non_snake_case)]
+impl Universe {
+    pub fn new(width: f32, height: f32, config: &initializer::Config) -> Result<Universe, SpherroError> {
+        if cfg!(target_arch="wasm32") {
+            set_panic_hook();
+        }
+
+        validate_size(width, height)?;
+        config.validate()?;
+
+        let particles = initializer::initialize(config, width, height, MASS);
+        let mut universe = Universe::with_particles(width, height, particles);
+        universe.config = Some(*config);
+        Ok(universe)
+    }
+
+    // Advances the simulation by dt. Despawn failures don't stop the step, but
+    // are reported after it. With the stability monitor on, a step that goes
+    // unstable is rolled back and retried, see `step_monitored`. Otherwise a
+    // step that leaves a non-finite particle is an error, and the universe
+    // should be recreated
+    pub fn update(&mut self, dt: f32) -> Result<(), SpherroError> {
+        if !(dt.is_finite() && dt > 0.0) {
+            return Err(SpherroError::InvalidTimeStep(dt));
+        }
+        self.record(Input::Update { dt, count: 1 });
+        self.steps += 1;
+
+        let mut clock = Stopwatch::start();
+        self.stats = StepStats::default();
+
+        if self.monitor.enabled {
+            self.stats.substeps = self.step_monitored(dt)?;
+        } else {
+            self.step(dt);
+            self.stats.substeps = 1;
+            self.check_finite()?;
+        }
+
+        let mut events_clock = Stopwatch::start();
+        let events = self.update_events();
+        self.stats.events_ms = events_clock.lap();
+
+        let mut tracers_clock = Stopwatch::start();
+        self.update_tracers(dt);
+        self.stats.tracers_ms = tracers_clock.lap();
+
+        self.update_stats(dt);
+        self.stats.total_ms = clock.lap();
+        events
+    }
+
+    // Steps the physics, without touching the event queue. Adds the time
+    // spent in every phase to the stats
+    fn step(&mut self, dt: f32) {
+        let mut clock = Stopwatch::start();
+        for body in self.bodies.iter_mut() {
+            body.begin_step(self.time, dt);
+        }
+        self.update_boundary_samples();
+
+        // This assumes that the neighbours remain the same for the
+        // entire update
+        let (neighbours, force_neighbours, boundary_neighbours) = self.compute_neighbours();
+        self.stats.neighbours_ms += clock.lap();
+
+        self.update_particle_fields(&neighbours, &boundary_neighbours);
+        self.stats.density_ms += clock.lap();
+
+        self.update_surface_normals(&neighbours);
+        self.update_velocity_gradients(&neighbours, dt);
+        self.update_nonpressure_forces(&neighbours, &force_neighbours, &boundary_neighbours, dt);
+        self.update_temperature(&neighbours, &boundary_neighbours, dt);
+        self.stats.forces_ms += clock.lap();
+
+        self.last_pressure_iterations = self.solve_pressure(&neighbours, &boundary_neighbours, dt);
+        self.stats.pressure_iterations += self.last_pressure_iterations;
+        self.stats.pressure_ms += clock.lap();
+
+        self.update_boundary();
+        self.update_body_collisions();
+        self.stats.boundary_ms += clock.lap();
+
+        self.update_bodies(dt);
+        self.stats.bodies_ms += clock.lap();
+
+        let counts = neighbours.iter().map(|n| n.len());
+        self.stats.neighbours_max = counts.clone().max().unwrap_or(0);
+        self.stats.neighbours_avg = counts.sum::<usize>() as f32 / neighbours.len().max(1) as f32;
+
+        self.time += dt;
+    }
+
+    // Takes a step and checks it with the stability monitor. If it trips,
+    // the step is rolled back and retried with dt halved, up to the retry
+    // limit. The last resort is damping the velocities before the final retry,
+    // which is kept unless it still leaves non-finite particles. Every trip is
+    // recorded and can be read back with `pop_trip`. Returns the number of
+    // substeps the step was split into
+    fn step_monitored(&mut self, dt: f32) -> Result<u32, SpherroError> {
+        let checkpoint = self.checkpoint();
+
+        let mut substeps: u32 = 1;
+        let mut retries = 0;
+        let mut damped = false;
+        loop {
+            let h = dt / substeps as f32;
+            for _ in 0..substeps {
+                self.step(h);
+            }
+
+            let mut trip = match self.monitor.check(&self.particles, self.rest_rho(), self.h, checkpoint.time, h) {
+                Some(trip) => trip,
+                None => return Ok(substeps),
+            };
+
+            let non_finite = trip.check == stability::StabilityCheck::NonFinite;
+            if damped && !non_finite {
+                // Still over the limits, but finite. Keep it
+                return Ok(substeps);
+            }
+
+            self.restore(&checkpoint);
+            trip.recovery = if damped {
+                Recovery::Failed
+            } else if retries < self.monitor.max_retries {
+                retries += 1;
+                substeps *= 2;
+                Recovery::Substep
+            } else {
+                damped = true;
+                Recovery::Damp
+            };
+            self.record_trip(trip);
+
+            match trip.recovery {
+                Recovery::Failed => return Err(SpherroError::Unstable{ id: trip.id }),
+                Recovery::Damp => {
+                    for pi in self.particles.iter_mut() {
+                        pi.vel *= self.monitor.damping;
+                    }
+                },
+                Recovery::Substep => {},
+            }
+        }
+    }
+
+    // Returns number of particles currently in the sim
+    pub fn get_size(&self) -> usize {
+        self.particles.len()
+    }
+
+    // Returns number of particles that would be added/removed if everything
+    // in the queue is popped
+    pub fn get_queue_diff(&self) -> isize {
+        let mut diff: isize = 0;
+        for event in self.events.iter() {
+            diff += match event {
+                Event::Spawn(count, _) =>   *count as isize,
+                Event::Despawn(removal) => -(removal.estimate(&self.particles) as isize),
+            };
+        }
+
+        diff
+    }
+
+    pub fn add_force(&mut self, force: Force) {
+        self.record(Input::AddForce(force));
+        self.forces.push(force);
+    }
+
+    pub fn clear_forces(&mut self) {
+        self.record(Input::ClearForces);
+        self.forces.clear();
+    }
+
+    // Cohesion and curvature coefficient(gamma). Zero disables surface tension.
+    // Values around 1e3 give visible droplets at the default scale
+    pub fn set_surface_tension(&mut self, gamma: f32) {
+        self.record(Input::SetSurfaceTension(gamma));
+        self.surface_tension = gamma;
+    }
+
+    // Attraction of the fluid towards the walls(beta). Zero disables adhesion.
+    // The adhesion spline is tiny at pixel scale, so useful values are ~1e10
+    pub fn set_adhesion(&mut self, beta: f32) {
+        self.record(Input::SetAdhesion(beta));
+        self.adhesion = beta;
+    }
+
+    // Makes the tank wrap around along x and/or y instead of having walls.
+    // Particles leaving one side come back in on the other, and interact
+    // across the seam. Rigid bodies still collide with the walls
+    pub fn set_periodic(&mut self, x: bool, y: bool) {
+        self.record(Input::SetPeriodic(x, y));
+        self.periodic_x = x;
+        self.periodic_y = y;
+
+        let domain = self.domain();
+        for pi in self.particles.iter_mut() {
+            pi.pos = domain.wrap(pi.pos);
+        }
+    }
+
+    pub fn is_periodic_x(&self) -> bool {
+        self.periodic_x
+    }
+
+    pub fn is_periodic_y(&self) -> bool {
+        self.periodic_y
+    }
+
+    pub fn set_surface_classifier(&mut self, classifier: SurfaceClassifier) {
+        self.record(Input::SetSurfaceClassifier(classifier));
+        self.surface_classifier = classifier;
+    }
+
+    // Kernel used for densities and forces. Changing it also recomputes the
+    // volumes of the boundary particles of every body
+    pub fn set_smoothing_kernel(&mut self, kernel: SmoothingKernel) {
+        self.record(Input::SetSmoothingKernel(kernel));
+        self.kernel = kernel;
+        self.update_body_volumes();
+    }
+
+    pub fn get_smoothing_kernel(&self) -> SmoothingKernel {
+        self.kernel
+    }
+
+    pub fn set_pressure_solver(&mut self, solver: PressureSolver) {
+        self.record(Input::SetPressureSolver(solver));
+        self.pressure_solver = solver;
+    }
+
+    pub fn get_pressure_solver(&self) -> PressureSolver {
+        self.pressure_solver
+    }
+
+    // Number of pressure iterations per step, or the most the adaptive
+    // solver takes. Defaults to 4. More iterations make a stiffer fluid
+    pub fn set_pressure_iterations(&mut self, iterations: u32) -> Result<(), SpherroError> {
+        if iterations == 0 {
+            return Err(SpherroError::InvalidConfig(
+                "there must be at least one pressure iteration".to_string(),
+            ));
+        }
+
+        self.record(Input::SetPressureIterations(iterations));
+        self.pressure_iterations = iterations;
+        Ok(())
+    }
+
+    pub fn get_pressure_iterations(&self) -> u32 {
+        self.pressure_iterations
+    }
+
+    // Average compression, relative to the rest density, below which the
+    // adaptive solver stops iterating. Defaults to 0.25
+    pub fn set_pressure_tolerance(&mut self, tolerance: f32) -> Result<(), SpherroError> {
+        if !(tolerance.is_finite() && tolerance > 0.0) {
+            return Err(SpherroError::InvalidConfig(format!(
+                "pressure tolerance must be positive, got {}", tolerance,
+            )));
+        }
+
+        self.record(Input::SetPressureTolerance(tolerance));
+        self.pressure_tolerance = tolerance;
+        Ok(())
+    }
+
+    pub fn get_pressure_tolerance(&self) -> f32 {
+        self.pressure_tolerance
+    }
+
+    // Radius of the kernel is twice the smoothing length. Neighbourhoods are
+    // only sensible while it is about 1-3 times the particle spacing, which
+    // is about 16 for fluid at rest. Defaults to 35
+    pub fn set_smoothing_length(&mut self, h: f32) -> Result<(), SpherroError> {
+        if !(h.is_finite() && h > 0.0) {
+            return Err(SpherroError::InvalidConfig(format!(
+                "smoothing length must be positive, got {}", h,
+            )));
+        }
+
+        self.record(Input::SetSmoothingLength(h));
+        self.h = h;
+        self.update_body_volumes();
+        Ok(())
+    }
+
+    pub fn get_smoothing_length(&self) -> f32 {
+        self.h
+    }
+
+    // Mass of every particle, including the ones spawned later. Particles
+    // that were given other masses keep their ratio to it. The rest density
+    // is proportional to it, so heavier particles make for weaker pressure
+    // forces. Defaults to 100
+    pub fn set_particle_mass(&mut self, mass: f32) -> Result<(), SpherroError> {
+        if !(mass.is_finite() && mass > 0.0) {
+            return Err(SpherroError::InvalidConfig(format!(
+                "particle mass must be positive, got {}", mass,
+            )));
+        }
+
+        self.record(Input::SetParticleMass(mass));
+        let scale = mass / self.mass;
+        for pi in self.particles.iter_mut() {
+            pi.mass *= scale;
+        }
+        self.mass = mass;
+        self.update_body_volumes();
+        Ok(())
+    }
+
+    pub fn get_particle_mass(&self) -> f32 {
+        self.mass
+    }
+
+    // Stiffness of the equation of state, p = k((rho/rho_0)^7 - 1). Stiffer
+    // fluids compress less, but need smaller time steps. Defaults to 10
+    pub fn set_stiffness(&mut self, k: f32) -> Result<(), SpherroError> {
+        if !(k.is_finite() && k > 0.0) {
+            return Err(SpherroError::InvalidConfig(format!(
+                "stiffness must be positive, got {}", k,
+            )));
+        }
+
+        self.record(Input::SetStiffness(k));
+        self.stiffness = k;
+        Ok(())
+    }
+
+    pub fn get_stiffness(&self) -> f32 {
+        self.stiffness
+    }
+
+    // Speed of sound given by the equation of state at the rest density, in
+    // real units, with the current parameters
+    pub fn get_sound_speed(&self) -> f32 {
+        (GAMMA as f32 * self.stiffness * self.h / self.rest_rho()).sqrt()
+    }
+
+    // Returns number of particles flagged as being on the free surface
+    pub fn get_surface_count(&self) -> usize {
+        self.particles.iter().filter(|pi| pi.is_surface).count()
+    }
+
+    // Returns an estimate of the length of the free surface
+    pub fn get_surface_length(&self) -> f32 {
+        surface::surface_length(&self.domain(), &self.particles, self.h * 2.0)
+    }
+
+    // Adds a rigid box centered at (x, y) and returns its index. A relative
+    // density below 1.0 floats, above 1.0 sinks. The size and the density
+    // must be positive
+    pub fn add_box_body(&mut self, x: f32, y: f32, width: f32, height: f32,
+                        relative_density: f32) -> Result<usize, SpherroError> {
+        let idx = self.add_body(Shape::Box(width, height), Vector2f::new(x, y), relative_density)?;
+        self.record(Input::AddBoxBody(x, y, width, height, relative_density));
+        Ok(idx)
+    }
+
+    // Adds a rigid disc centered at (x, y) and returns its index
+    pub fn add_disc_body(&mut self, x: f32, y: f32, radius: f32, relative_density: f32) -> Result<usize, SpherroError> {
+        let idx = self.add_body(Shape::Disc(radius), Vector2f::new(x, y), relative_density)?;
+        self.record(Input::AddDiscBody(x, y, radius, relative_density));
+        Ok(idx)
+    }
+
+    // Adds a kinematic box obstacle(paddle, stirrer, piston...) and returns
+    // its index. It doesn't move unless it's given a target or a path
+    pub fn add_box_obstacle(&mut self, x: f32, y: f32, width: f32, height: f32) -> usize {
+        self.record(Input::AddBoxObstacle(x, y, width, height));
+        self.add_obstacle(Shape::Box(width, height), Vector2f::new(x, y))
+    }
+
+    pub fn add_disc_obstacle(&mut self, x: f32, y: f32, radius: f32) -> usize {
+        self.record(Input::AddDiscObstacle(x, y, radius));
+        self.add_obstacle(Shape::Disc(radius), Vector2f::new(x, y))
+    }
+
+    // Moves the obstacle to this pose over the next step. Has no
+    // effect on bodies that aren't obstacles
+    pub fn set_obstacle_target(&mut self, i: usize, x: f32, y: f32, angle: f32) -> Result<(), SpherroError> {
+        self.body_mut(i)?.set_target(Vector2f::new(x, y), angle);
+        self.record(Input::SetObstacleTarget(i, x, y, angle));
+        Ok(())
+    }
+
+    // Appends a pose to the obstacle's path, to be reached at simulation time `t`
+    pub fn add_obstacle_keyframe(&mut self, i: usize, t: f32, x: f32, y: f32, angle: f32) -> Result<(), SpherroError> {
+        self.body_mut(i)?.add_keyframe(Keyframe{ t, pos: Vector2f::new(x, y), angle });
+        self.record(Input::AddObstacleKeyframe(i, t, x, y, angle));
+        Ok(())
+    }
+
+    pub fn set_obstacle_looping(&mut self, i: usize, looping: bool) -> Result<(), SpherroError> {
+        self.body_mut(i)?.set_looping(looping);
+        self.record(Input::SetObstacleLooping(i, looping));
+        Ok(())
+    }
+
+    // Viscosity coefficient of the fluid, for the original Laplacian model.
+    // The kinematic viscosity it results in is larger by the smoothing
+    // length, see `kinematic_viscosity`
+    pub fn set_viscosity(&mut self, viscosity: f32) {
+        self.record(Input::SetViscosity(viscosity));
+        self.viscosity = Viscosity::Laplacian(viscosity);
+    }
+
+    // The coefficient `set_viscosity` would need for the current model to
+    // give the same kinematic viscosity
+    pub fn get_viscosity(&self) -> f32 {
+        self.kinematic_viscosity() / self.h
+    }
+
+    // The original Laplacian model, given a kinematic viscosity in real units
+    pub fn set_kinematic_viscosity(&mut self, nu: f32) {
+        self.record(Input::SetKinematicViscosity(nu));
+        self.viscosity = Viscosity::Laplacian(nu / self.h);
+    }
+
+    // Morris laminar viscosity, given a kinematic viscosity in real units
+    pub fn set_laminar_viscosity(&mut self, nu: f32) {
+        self.record(Input::SetLaminarViscosity(nu));
+        self.viscosity = Viscosity::Morris(nu);
+    }
+
+    // Monaghan artificial viscosity. alpha is usually around 0.01-0.1 and
+    // beta twice alpha, or 0
+    pub fn set_artificial_viscosity(&mut self, alpha: f32, beta: f32) {
+        self.record(Input::SetArtificialViscosity(alpha, beta));
+        self.viscosity = Viscosity::Monaghan { alpha, beta };
+    }
+
+    pub fn get_viscosity_model(&self) -> ViscosityModel {
+        self.viscosity.model()
+    }
+
+    // XSPH smoothing, which nudges every particle's velocity towards the
+    // average of its neighbours by `epsilon`, usually 0.1-0.5, every step.
+    // It is applied on top of the viscosity model. 0 turns it off
+    pub fn set_xsph(&mut self, epsilon: f32) {
+        self.record(Input::SetXsph(epsilon));
+        self.xsph = epsilon;
+    }
+
+    pub fn get_xsph(&self) -> f32 {
+        self.xsph
+    }
+
+    // Non-Newtonian rheologies for the particles of `phase`, see `Rheology`.
+    // Viscosities are kinematic and in real units
+    pub fn set_power_law(&mut self, phase: u32, k: f32, n: f32) {
+        self.record(Input::SetPowerLaw(phase, k, n));
+        self.store_rheology(phase, Rheology::PowerLaw { k, n });
+    }
+
+    pub fn set_cross(&mut self, phase: u32, nu_0: f32, nu_inf: f32, lambda: f32, n: f32) {
+        self.record(Input::SetCross(phase, nu_0, nu_inf, lambda, n));
+        self.store_rheology(phase, Rheology::Cross { nu_0, nu_inf, lambda, n });
+    }
+
+    pub fn set_carreau(&mut self, phase: u32, nu_0: f32, nu_inf: f32, lambda: f32, n: f32) {
+        self.record(Input::SetCarreau(phase, nu_0, nu_inf, lambda, n));
+        self.store_rheology(phase, Rheology::Carreau { nu_0, nu_inf, lambda, n });
+    }
+
+    pub fn set_bingham(&mut self, phase: u32, nu_plastic: f32, yield_stress: f32) {
+        self.record(Input::SetBingham(phase, nu_plastic, yield_stress));
+        self.store_rheology(phase, Rheology::Bingham { nu_plastic, yield_stress });
+    }
+
+    // Makes `phase` granular, see `Rheology::DruckerPrager`. The friction
+    // angle is in degrees
+    pub fn set_drucker_prager(&mut self, phase: u32, friction_angle: f32, cohesion: f32) {
+        self.record(Input::SetDruckerPrager(phase, friction_angle, cohesion));
+        let friction_angle = friction_angle.to_radians();
+        self.store_rheology(phase, Rheology::DruckerPrager { friction_angle, cohesion });
+    }
+
+    pub fn set_rheology_preset(&mut self, phase: u32, preset: RheologyPreset) {
+        self.record(Input::SetRheologyPreset(phase, preset));
+        self.store_rheology(phase, Rheology::preset(preset));
+    }
+
+    // Makes `phase` follow the viscosity model again
+    pub fn set_newtonian(&mut self, phase: u32) {
+        self.record(Input::SetNewtonian(phase));
+        self.store_rheology(phase, Rheology::Newtonian);
+    }
+
+    // Puts the particles in the rectangle into `phase`, and returns how many
+    // there were
+    pub fn set_phase_in_rect(&mut self, x0: f32, y0: f32, x1: f32, y1: f32, phase: u32) -> usize {
+        self.record(Input::SetPhaseInRect(x0, y0, x1, y1, phase));
+        let (min, max) = (Vector2f::new(x0.min(x1), y0.min(y1)), Vector2f::new(x0.max(x1), y0.max(y1)));
+        self.set_phase_where(phase, |p| p.x >= min.x && p.x <= max.x && p.y >= min.y && p.y <= max.y)
+    }
+
+    pub fn set_phase_in_circle(&mut self, x: f32, y: f32, r: f32, phase: u32) -> usize {
+        self.record(Input::SetPhaseInCircle(x, y, r, phase));
+        let center = Vector2f::new(x, y);
+        self.set_phase_where(phase, |p| (p - center).magnitude() <= r)
+    }
+
+    // Strength of the vorticity confinement force, which puts back some of
+    // the swirl that the viscosity and the low resolution damp out. It has
+    // units of speed. 0 turns it off
+    pub fn set_vorticity_confinement(&mut self, strength: f32) {
+        self.record(Input::SetVorticityConfinement(strength));
+        self.vorticity_confinement = strength;
+    }
+
+    pub fn get_vorticity_confinement(&self) -> f32 {
+        self.vorticity_confinement
+    }
+
+    // Thermal diffusivity. Zero disables conduction. Like the viscosity, the
+    // diffusivity it results in is about h times larger
+    pub fn set_conductivity(&mut self, conductivity: f32) {
+        self.record(Input::SetConductivity(conductivity));
+        self.conductivity = conductivity;
+    }
+
+    pub fn get_conductivity(&self) -> f32 {
+        self.conductivity
+    }
+
+    // Boussinesq buoyancy: particles are pushed against gravity by
+    // beta * (temperature - reference) times gravity. Zero disables buoyancy
+    pub fn set_thermal_expansion(&mut self, beta: f32) {
+        self.record(Input::SetThermalExpansion(beta));
+        self.thermal_expansion = beta;
+    }
+
+    // Temperature at which the fluid is neither buoyant nor heavy. Spawned
+    // particles start at this temperature
+    pub fn set_reference_temperature(&mut self, temperature: f32) {
+        self.record(Input::SetReferenceTemperature(temperature));
+        self.reference_temperature = temperature;
+    }
+
+    // Heat sources pull the temperature of the particles in their zone
+    // towards `temperature`, closing the gap at `rate` per second. They
+    // return their index, and sinks are sources colder than the fluid
+    pub fn add_heat_rect(&mut self, x0: f32, y0: f32, x1: f32, y1: f32, temperature: f32, rate: f32) -> usize {
+        self.record(Input::AddHeatRect(x0, y0, x1, y1, temperature, rate));
+        self.add_heat_source(HeatZone::Rect(Vector2f::new(x0, y0), Vector2f::new(x1, y1)), temperature, rate)
+    }
+
+    pub fn add_heat_circle(&mut self, x: f32, y: f32, r: f32, temperature: f32, rate: f32) -> usize {
+        self.record(Input::AddHeatCircle(x, y, r, temperature, rate));
+        self.add_heat_source(HeatZone::Circle(Vector2f::new(x, y), r), temperature, rate)
+    }
+
+    // Acts on the particles within the smoothing length of the wall
+    pub fn add_heat_wall(&mut self, wall: Wall, temperature: f32, rate: f32) -> usize {
+        self.record(Input::AddHeatWall(wall, temperature, rate));
+        self.add_heat_source(HeatZone::Wall(wall, self.h), temperature, rate)
+    }
+
+    // Acts on the particles touching the body
+    pub fn add_heat_body(&mut self, i: usize, temperature: f32, rate: f32) -> Result<usize, SpherroError> {
+        self.body_mut(i)?;
+        self.record(Input::AddHeatBody(i, temperature, rate));
+        Ok(self.add_heat_source(HeatZone::Body(i), temperature, rate))
+    }
+
+    pub fn clear_heat_sources(&mut self) {
+        self.record(Input::ClearHeatSources);
+        self.heat_sources.clear();
+    }
+
+    // Gravity can be changed between steps, eg. to follow the orientation of
+    // the device the simulation is running on
+    pub fn set_gravity(&mut self, x: f32, y: f32) {
+        self.record(Input::SetGravity(x, y));
+        self.gravity = Vector2f::new(x, y);
+    }
+
+    // Points gravity `angle` radians counter-clockwise from straight down,
+    // keeping the default magnitude. This is the same as tilting the tank
+    // clockwise by `angle`
+    pub fn set_gravity_angle(&mut self, angle: f32) {
+        self.record(Input::SetGravityAngle(angle));
+        let (s, c) = angle.sin_cos();
+        self.gravity = Vector2f::new(-GRAVITY * s, GRAVITY * c);
+    }
+
+    pub fn get_gravity_x(&self) -> f32 {
+        self.gravity.x
+    }
+
+    pub fn get_gravity_y(&self) -> f32 {
+        self.gravity.y
+    }
+
+    // `strength` is the acceleration the preset reaches at the walls
+    pub fn set_body_force_preset(&mut self, preset: BodyForcePreset, strength: f32) {
+        self.record(Input::SetBodyForcePreset(preset, strength));
+        self.body_force = BodyForce::Preset(preset, strength);
+    }
+
+    // Sets the body force from accelerations sampled on a regular grid spanning
+    // the tank. `data` holds (x, y) pairs in row-major order, starting at the
+    // bottom left
+    pub fn set_body_force_grid(&mut self, cols: usize, rows: usize, data: Vec<f32>) -> Result<(), SpherroError> {
+        if cols == 0 || rows == 0 || data.len() != cols * rows * 2 {
+            return Err(SpherroError::InvalidBodyForceGrid{ cols, rows, len: data.len() });
+        }
+        self.record(Input::SetBodyForceGrid(cols, rows, data.clone()));
+
+        let data = data.chunks(2).map(|a| Vector2f::new(a[0], a[1])).collect();
+        self.body_force = BodyForce::Grid(cols, rows, data);
+        Ok(())
+    }
+
+    pub fn clear_body_force(&mut self) {
+        self.record(Input::ClearBodyForce);
+        self.body_force = BodyForce::None;
+    }
+
+    // Returns the simulation time, ie. the sum of all dts so far
+    pub fn get_time(&self) -> f32 {
+        self.time
+    }
+
+    pub fn get_width(&self) -> f32 {
+        self.width
+    }
+
+    pub fn get_height(&self) -> f32 {
+        self.height
+    }
+
+    pub fn get_body_count(&self) -> usize {
+        self.bodies.len()
+    }
+
+    pub fn clear_bodies(&mut self) {
+        self.record(Input::ClearBodies);
+        self.bodies.clear();
+        self.boundary.clear();
+    }
+
+    pub fn queue_spawn_particles(&mut self, count: usize, x: f32, y: f32) {
+        self.record(Input::QueueSpawnParticles(count, x, y));
+        let pos = Vector2f::new(x, y);
+        self.events.push(Event::Spawn(count, pos));
+    }
+
+    // Removes the `count` fastest particles
+    pub fn queue_despawn_particles(&mut self, count: usize) {
+        self.record(Input::QueueDespawnParticles(count));
+        self.events.push(Event::Despawn(Removal::Fastest(count)));
+    }
+
+    pub fn queue_despawn_oldest(&mut self, count: usize) {
+        self.record(Input::QueueDespawnOldest(count));
+        self.events.push(Event::Despawn(Removal::Oldest(count)));
+    }
+
+    pub fn queue_despawn_random(&mut self, count: usize, seed: u32) {
+        self.record(Input::QueueDespawnRandom(count, seed));
+        self.events.push(Event::Despawn(Removal::Random(count, seed as u64)));
+    }
+
+    pub fn queue_despawn_densest(&mut self, count: usize) {
+        self.record(Input::QueueDespawnDensest(count));
+        self.events.push(Event::Despawn(Removal::Densest(count)));
+    }
+
+    pub fn queue_despawn_in_rect(&mut self, x0: f32, y0: f32, x1: f32, y1: f32) {
+        self.record(Input::QueueDespawnInRect(x0, y0, x1, y1));
+        let removal = Removal::InRect(Vector2f::new(x0, y0), Vector2f::new(x1, y1));
+        self.events.push(Event::Despawn(removal));
+    }
+
+    pub fn queue_despawn_in_circle(&mut self, x: f32, y: f32, r: f32) {
+        self.record(Input::QueueDespawnInCircle(x, y, r));
+        self.events.push(Event::Despawn(Removal::InCircle(Vector2f::new(x, y), r)));
+    }
+
+    // Immediately removes the particle with the given id. Returns false if
+    // there is no such particle. The last particle takes the removed one's index
+    pub fn despawn_by_id(&mut self, id: u32) -> bool {
+        self.record(Input::DespawnById(id));
+        let idx = match self.id_to_index.remove(&id) {
+            Some(idx) => idx,
+            None => return false,
+        };
+
+        self.particles.swap_remove(idx);
+        if idx < self.particles.len() {
+            self.id_to_index.insert(self.particles[idx].id, idx);
+        }
+
+        true
+    }
+
+    // Returns the current index of the particle with the given id
+    pub fn get_particle_index(&self, id: u32) -> Option<usize> {
+        self.id_to_index.get(&id).cloned()
+    }
+
+    // Off by default, since every monitored step copies the particles and
+    // bodies first so that it can be rolled back
+    pub fn set_stability_monitor(&mut self, enabled: bool) {
+        self.record(Input::SetStabilityMonitor(enabled));
+        self.monitor.enabled = enabled;
+    }
+
+    // `max_cfl` is the largest fraction of the smoothing radius a particle may
+    // cross in a step, `max_density_error` the largest compression relative to
+    // the rest density
+    pub fn set_stability_limits(&mut self, max_cfl: f32, max_density_error: f32) {
+        self.record(Input::SetStabilityLimits(max_cfl, max_density_error));
+        self.monitor.max_cfl = max_cfl;
+        self.monitor.max_density_error = max_density_error;
+    }
+
+    // Number of times a failing step is retried with half the dt before the
+    // velocities are damped, and the factor they are damped by
+    pub fn set_stability_recovery(&mut self, max_retries: u32, damping: f32) {
+        self.record(Input::SetStabilityRecovery(max_retries, damping));
+        self.monitor.max_retries = max_retries;
+        self.monitor.damping = damping;
+    }
+
+    // Velocity, density, pressure and vorticity interpolated at (x, y), or
+    // nothing if there is no fluid there
+    pub fn sample_point(&self, x: f32, y: f32) -> Option<FieldSample> {
+        self.sample_at(Vector2f::new(x, y))
+    }
+
+    // Samples a cols x rows grid spanning (x0, y0) to (x1, y1) in one go,
+    // see `sampling::rasterize` for the layout. Vorticity is left out
+    pub fn rasterize(&self, x0: f32, y0: f32, x1: f32, y1: f32,
+                     cols: usize, rows: usize) -> Result<Vec<f32>, SpherroError> {
+        sampling::rasterize(&self.domain(), &self.particles, (self.kernel, self.h),
+                            Vector2f::new(x0, y0), Vector2f::new(x1, y1), (cols, rows), false)
+    }
+
+    pub fn rasterize_with_vorticity(&self, x0: f32, y0: f32, x1: f32, y1: f32,
+                                    cols: usize, rows: usize) -> Result<Vec<f32>, SpherroError> {
+        sampling::rasterize(&self.domain(), &self.particles, (self.kernel, self.h),
+                            Vector2f::new(x0, y0), Vector2f::new(x1, y1), (cols, rows), true)
+    }
+
+    // Number of floats per point returned by `rasterize`
+    pub fn raster_stride() -> usize {
+        sampling::RASTER_STRIDE
+    }
+
+    // Adds a passive tracer at (x, y), and returns its id
+    pub fn add_tracer(&mut self, x: f32, y: f32) -> u32 {
+        self.record(Input::AddTracer(x, y));
+        self.tracers.seed(Vector2f::new(x, y), None)
+    }
+
+    // Adds `count` tracers evenly spread from (x0, y0) to (x1, y1)
+    pub fn add_tracer_line(&mut self, x0: f32, y0: f32, x1: f32, y1: f32, count: usize) {
+        self.record(Input::AddTracerLine(x0, y0, x1, y1, count));
+        self.tracers.seed_line(Vector2f::new(x0, y0), Vector2f::new(x1, y1), count);
+    }
+
+    // Releases a tracer at (x, y) now and then every `period` seconds, which
+    // must be at least 1ms
+    pub fn add_tracer_emitter(&mut self, x: f32, y: f32, period: f32) -> Result<usize, SpherroError> {
+        let pos = Vector2f::new(x, y);
+        let emitter = self.add_emitter(pos, pos, 1, period)?;
+        self.record(Input::AddTracerEmitter(x, y, period));
+        Ok(emitter)
+    }
+
+    // Like `add_tracer_emitter`, for `count` points evenly spread from
+    // (x0, y0) to (x1, y1)
+    pub fn add_tracer_line_emitter(&mut self, x0: f32, y0: f32, x1: f32, y1: f32,
+                                   count: usize, period: f32) -> Result<usize, SpherroError> {
+        let emitter = self.add_emitter(Vector2f::new(x0, y0), Vector2f::new(x1, y1), count, period)?;
+        self.record(Input::AddTracerLineEmitter(x0, y0, x1, y1, count, period));
+        Ok(emitter)
+    }
+
+    pub fn set_tracer_integrator(&mut self, integrator: TracerIntegrator) {
+        self.record(Input::SetTracerIntegrator(integrator));
+        self.tracers.integrator = integrator;
+    }
+
+    // Number of past positions kept per tracer for path lines, including the
+    // current one. 0 keeps none
+    pub fn set_tracer_history(&mut self, length: usize) {
+        self.record(Input::SetTracerHistory(length));
+        self.tracers.history = length;
+    }
+
+    // Tracers older than this many seconds are dropped. 0 keeps them forever
+    pub fn set_tracer_lifetime(&mut self, lifetime: f32) {
+        self.record(Input::SetTracerLifetime(lifetime));
+        self.tracers.lifetime = lifetime.max(0.0);
+    }
+
+    pub fn get_tracer_count(&self) -> usize {
+        self.tracers.len()
+    }
+
+    // Removes all tracers and emitters
+    pub fn clear_tracers(&mut self) {
+        self.record(Input::ClearTracers);
+        self.tracers.clear();
+    }
+
+    // Seeds the random numbers used to spread out spawned particles. Every
+    // universe starts with the same seed
+    pub fn set_seed(&mut self, seed: u32) {
+        self.record(Input::SetSeed(seed));
+        self.rng = StdRng::seed_from_u64(seed as u64);
+    }
+
+    // Starts logging every call that changes the universe, see `Recording`.
+    // Only universes made with `new` can be recorded, before anything else is
+    // done to them, so that the log can be replayed from the start
+    pub fn start_recording(&mut self) -> Result<(), SpherroError> {
+        let config = match self.config {
+            Some(config) => config,
+            None => return Err(SpherroError::CannotRecord("the universe wasn't made from a config".to_string())),
+        };
+        if !self.pristine {
+            return Err(SpherroError::CannotRecord("the universe has already been changed".to_string()));
+        }
+
+        self.recording = Some(Recording::new(self.width, self.height, config));
+        Ok(())
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recording.is_some()
+    }
+
+    // The log so far as text, which `Recording::parse` reads back
+    pub fn get_recording(&self) -> Option<String> {
+        self.recording.as_ref().map(|r| r.to_text())
+    }
+
+    // Stops recording, and returns the log
+    pub fn stop_recording(&mut self) -> Option<String> {
+        self.recording.take().map(|r| r.to_text())
+    }
+
+    // Stats for the last call to `update`
+    pub fn get_stats(&self) -> StepStats {
+        self.stats
+    }
+
+    // Returns the oldest unread diagnostic record, if any
+    pub fn pop_trip(&mut self) -> Option<Trip> {
+        self.trips.pop_front()
+    }
+
+    pub fn is_unstable(&self) -> bool {
+        self.particles.iter().any(|pi| {
+            !pi.pos.x.is_finite() || !pi.pos.y.is_finite()
+        })
+    }
+}
+
+#[allow(non_snake_case)]
+impl Universe {
+
+    pub fn get_particles(&self) -> &Vec<Particle> {
+        &self.particles
+    }
+
+    pub fn sample_at(&self, pos: Vector2f) -> Option<FieldSample> {
+        self.sample_many(&[pos]).pop().unwrap()
+    }
+
+    // Samples every point in `points`, building the neighbour grid only once
+    pub fn sample_many(&self, points: &[Vector2f]) -> Vec<Option<FieldSample>> {
+        let (domain, kernel, h) = (self.domain(), self.kernel, self.h);
+        let grid = Grid::with_domain(domain, h, &self.particles);
+        points.iter().map(|&pos| sampling::sample(&domain, &grid, &self.particles, (kernel, h), pos, true)).collect()
+    }
+
+    pub fn get_tracers(&self) -> &[Tracer] {
+        self.tracers.get()
+    }
+
+    pub(crate) fn get_tracer_set(&self) -> &Tracers {
+        &self.tracers
+    }
+
+    // Number of calls to `update` so far
+    pub fn get_steps(&self) -> u64 {
+        self.steps
+    }
+
+    pub fn recording(&self) -> Option<&Recording> {
+        self.recording.as_ref()
+    }
+
+    // Logs a call that changes the universe, if it is being recorded
+    fn record(&mut self, input: Input) {
+        self.pristine = false;
+        if let Some(recording) = self.recording.as_mut() {
+            recording.push(input);
+        }
+    }
+
+    pub fn get_particle_by_id(&self, id: u32) -> Option<&Particle> {
+        self.get_particle_index(id).map(|idx| &self.particles[idx])
+    }
+
+    // Immediately removes the particles picked by `removal`, and returns how
+    // many were removed. Nothing is removed if it can't be satisfied
+    pub fn despawn(&mut self, removal: &Removal) -> Result<usize, SpherroError> {
+        let removed = self.remove_particles(removal)?;
+        self.record(Input::Despawn(removal.clone()));
+        Ok(removed)
+    }
+
+    // `despawn` without recording it, for the queued despawns
+    fn remove_particles(&mut self, removal: &Removal) -> Result<usize, SpherroError> {
+        let mut indices = removal.select(&self.particles)?;
+
+        // Going from the back keeps the indices that are yet to be removed valid
+        indices.sort_unstable_by(|a, b| b.cmp(a));
+        indices.dedup();
+        for idx in indices.iter() {
+            self.particles.swap_remove(*idx);
+        }
+
+        self.rebuild_id_map();
+        Ok(indices.len())
+    }
+
+    // Creates a universe from particles laid out by the caller, eg. for
+    // scenes that the config can't describe. The ids must be unique and
+    // every particle must be finite. Particles should use `default_particle_mass`
+    // to behave like the rest of the fluid
+    pub fn from_particles(width: f32, height: f32, particles: Vec<Particle>) -> Result<Universe, SpherroError> {
+        validate_size(width, height)?;
+
+        if let Some(pi) = particles.iter().find(|pi| {
+            !pi.pos.x.is_finite() || !pi.pos.y.is_finite()
+            || !pi.vel.x.is_finite() || !pi.vel.y.is_finite()
+            || !pi.temperature.is_finite()
+        }) {
+            return Err(SpherroError::InvalidConfig(format!("particle {} isn't finite", pi.id)));
+        }
+
+        let universe = Universe::with_particles(width, height, particles);
+        if universe.id_to_index.len() != universe.particles.len() {
+            return Err(SpherroError::InvalidConfig("particle ids must be unique".to_string()));
+        }
 
-const GRAVITY: f32 = -10000.0;
-const K: f32 = 10.0;
+        Ok(universe)
+    }
 
-enum Event {
-    Spawn(usize, Vector2f),
-    Despawn(usize),
-}
+    // Mass particles are given until `set_particle_mass` is called
+    pub fn default_particle_mass() -> f32 {
+        MASS
+    }
 
-#[wasm_bindgen]
-pub struct Universe {
-    particles: Vec<Particle>,
-    width: f32,
-    height: f32,
-    forces: Vec<Force>,
-    events: Vec<Event>,
-}
+    // Smoothing length until `set_smoothing_length` is called
+    pub fn default_smoothing_length() -> f32 {
+        H
+    }
 
-type Neighbours = Vec<Vec<usize>>;
+    // The density is normalized by h^3 instead of h^2(see the TODO in
+    // `update_particle_fields`), which makes the particle volumes, and with
+    // them the Laplacian viscosity term, h times larger. For the artificial
+    // viscosity, this is what it amounts to in laminar flow
+    pub fn kinematic_viscosity(&self) -> f32 {
+        self.viscosity.kinematic(self.get_sound_speed(), self.h)
+    }
 
-#[wasm_bindgen]
-#[allow(non_snake_case)]
-impl Universe {
-    pub fn new(width: f32, height: f32, config: &initializer::Config) -> Universe {
-        if cfg!(target_arch="wasm32") {
-            set_panic_hook();
+    pub fn set_rheology(&mut self, phase: u32, rheology: Rheology) {
+        self.record(Input::SetRheology(phase, rheology));
+        self.store_rheology(phase, rheology);
+    }
+
+    // `set_rheology` without recording it, for the setters that record
+    // themselves
+    fn store_rheology(&mut self, phase: u32, rheology: Rheology) {
+        let phase = phase as usize;
+        if self.rheologies.len() <= phase {
+            self.rheologies.resize(phase + 1, Rheology::Newtonian);
         }
+        self.rheologies[phase] = rheology;
+    }
 
-        let particles = initializer::initialize(config, width, height, MASS);
+    pub fn get_rheology(&self, phase: u32) -> Rheology {
+        self.rheologies.get(phase as usize).cloned().unwrap_or(Rheology::Newtonian)
+    }
+
+    // Native counterpart of the viscosity setters
+    pub fn set_viscosity_model(&mut self, viscosity: Viscosity) {
+        self.record(Input::SetViscosityModel(viscosity));
+        self.viscosity = viscosity;
+    }
+
+    // Speed of sound given by the equation of state at the rest density,
+    // in real units, with the default parameters. See `get_sound_speed` for
+    // the universe's own
+    pub fn default_sound_speed() -> f32 {
+        (GAMMA as f32 * K * H / REST_RHO).sqrt()
+    }
 
-        Universe {
-            particles: particles,
-            width: width,
-            height: height,
+    fn with_particles(width: f32, height: f32, particles: Vec<Particle>) -> Universe {
+        let next_id = particles.iter().map(|pi| pi.id + 1).max().unwrap_or(0);
+
+        let mut universe = Universe {
+            particles,
+            id_to_index: HashMap::new(),
+            next_id,
+            width,
+            height,
+            periodic_x: false,
+            periodic_y: false,
             forces: Vec::new(),
             events: Vec::new(),
+            surface_tension: 0.0,
+            adhesion: 0.0,
+            surface_classifier: SurfaceClassifier::ColorField,
+            bodies: Vec::new(),
+            boundary: Vec::new(),
+            time: 0.0,
+            gravity: Vector2f::new(0.0, GRAVITY),
+            body_force: BodyForce::None,
+            viscosity: Viscosity::Laplacian(VISC),
+            xsph: 0.0,
+            rheologies: Vec::new(),
+            vorticity_confinement: 0.0,
+            conductivity: 0.0,
+            thermal_expansion: 0.0,
+            reference_temperature: 0.0,
+            heat_sources: Vec::new(),
+            tracers: Tracers::new(),
+            monitor: StabilityMonitor::new(),
+            trips: VecDeque::new(),
+            h: H,
+            mass: MASS,
+            stiffness: K,
+            kernel: SmoothingKernel::CubicSpline,
+            pressure_solver: PressureSolver::Fixed,
+            pressure_iterations: PRESSURE_ITERATIONS,
+            pressure_tolerance: DEFAULT_PRESSURE_TOLERANCE,
+            last_pressure_iterations: PRESSURE_ITERATIONS,
+            stats: StepStats::default(),
+            steps: 0,
+            rng: StdRng::seed_from_u64(0),
+            config: None,
+            recording: None,
+            pristine: true,
+        };
+        universe.rebuild_id_map();
+
+        universe
+    }
+
+    fn rest_rho(&self) -> f32 {
+        REST_RHO * self.mass / MASS
+    }
+
+    // Mass per unit area of settled fluid made of this universe's particles
+    pub(crate) fn fluid_area_density(&self) -> f32 {
+        FLUID_AREA_DENSITY * self.mass / MASS
+    }
+
+    // Pressure iterations the last step ran. The fluid feels about that many
+    // times the pressures
+    pub(crate) fn last_pressure_iterations(&self) -> u32 {
+        self.last_pressure_iterations
+    }
+
+    pub(crate) fn domain(&self) -> Domain {
+        Domain {
+            width: self.width,
+            height: self.height,
+            periodic_x: self.periodic_x,
+            periodic_y: self.periodic_y,
         }
     }
 
-    pub fn update(&mut self, dt: f32) {
-        // This assumes that the neighbours remain the same for the
-        // entire update
-        let (neighbours, force_neighbours) = self.compute_neighbours();
+    fn rebuild_id_map(&mut self) {
+        self.id_to_index = self.particles.iter()
+                                         .enumerate()
+                                         .map(|(idx, pi)| (pi.id, idx))
+                                         .collect();
+    }
 
-        self.update_particle_fields(&neighbours);
-        self.update_nonpressure_forces(&neighbours, &force_neighbours, dt);
+    pub fn get_bodies(&self) -> &Vec<RigidBody> {
+        &self.bodies
+    }
 
-        for _ in 0..4 { //TODO: this condition should take density error
-            self.update_particle_fields(&neighbours);
-            self.update_pressure_forces(&neighbours, dt);
+    // Sets the body force to an arbitrary function of position and simulation
+    // time. Functions can't be recorded or replayed, so this fails while the
+    // universe is being recorded, and afterwards `start_recording` does
+    pub fn set_body_force<F>(&mut self, f: F) -> Result<(), SpherroError>
+            where F: Fn(Vector2f, f32) -> Vector2f + Send + Sync + 'static {
+        if self.recording.is_some() {
+            return Err(SpherroError::CannotRecord("body force functions can't be replayed".to_string()));
         }
 
-        self.update_boundary();
+        self.pristine = false;
+        self.body_force = BodyForce::Callback(Box::new(f));
+        Ok(())
+    }
 
-        self.update_events();
+    fn body_mut(&mut self, i: usize) -> Result<&mut RigidBody, SpherroError> {
+        self.bodies.get_mut(i).ok_or(SpherroError::InvalidBody(i))
     }
 
-    // Returns number of particles currently in the sim
-    pub fn get_size(&self) -> usize {
-        self.particles.len()
+    // Diagnostic records that haven't been popped yet, oldest first
+    pub fn get_trips(&self) -> &VecDeque<Trip> {
+        &self.trips
     }
 
-    // Returns number of particles that would be added/removed if everything
-    // in the queue is popped
-    pub fn get_queue_diff(&self) -> isize {
-        let mut diff: isize = 0;
-        for event in self.events.iter() {
-            diff += match event {
-                Event::Spawn(count, _) =>   *count as isize,
-                Event::Despawn(count)  => -(*count as isize),
-            };
+    fn checkpoint(&self) -> Checkpoint {
+        Checkpoint {
+            particles: self.particles.clone(),
+            bodies: self.bodies.clone(),
+            time: self.time,
         }
+    }
 
-        diff
+    // The particles only move during a step, so the id map stays valid
+    fn restore(&mut self, checkpoint: &Checkpoint) {
+        self.particles.clone_from(&checkpoint.particles);
+        self.bodies.clone_from(&checkpoint.bodies);
+        self.time = checkpoint.time;
     }
 
-    pub fn add_force(&mut self, force: Force) {
-        self.forces.push(force);
+    // Fills in the stats that describe the state at the end of an update
+    fn update_stats(&mut self, dt: f32) {
+        let rest_rho = self.rest_rho();
+        let stats = &mut self.stats;
+        stats.time = self.time;
+        stats.dt = dt;
+        stats.particles = self.particles.len();
+
+        let (mut err_min, mut err_max, mut err_sum) = (f32::INFINITY, f32::NEG_INFINITY, 0.0);
+        let (mut max_speed, mut kinetic, mut potential, mut mass) = (0.0f32, 0.0, 0.0, 0.0);
+        for pi in self.particles.iter() {
+            let err = (pi.rho - rest_rho) / rest_rho;
+            err_min = err_min.min(err);
+            err_max = err_max.max(err);
+            err_sum += err;
+
+            let speed = pi.vel.magnitude();
+            max_speed = max_speed.max(speed);
+            kinetic += 0.5 * pi.mass * speed * speed;
+            potential -= pi.mass * self.gravity.dot(pi.pos);
+            mass += pi.mass;
+        }
+
+        let n = self.particles.len();
+        stats.density_error_min = if n > 0 { err_min } else { 0.0 };
+        stats.density_error_max = if n > 0 { err_max } else { 0.0 };
+        stats.density_error_avg = err_sum / n.max(1) as f32;
+        stats.max_speed = max_speed;
+        stats.kinetic_energy = kinetic;
+        stats.potential_energy = potential;
+        stats.total_mass = mass;
     }
 
-    pub fn clear_forces(&mut self) {
-        self.forces.clear();
+    fn record_trip(&mut self, trip: Trip) {
+        if self.trips.len() >= stability::MAX_TRIPS {
+            self.trips.pop_front();
+        }
+        self.trips.push_back(trip);
     }
 
-    pub fn queue_spawn_particles(&mut self, count: usize, x: f32, y: f32) {
-        let pos = Vector2f::new(x, y);
-        self.events.push(Event::Spawn(count, pos));
+    // Finds the first particle that is no longer finite
+    fn check_finite(&self) -> Result<(), SpherroError> {
+        let bad = self.particles.iter().find(|pi| {
+            !pi.pos.x.is_finite() || !pi.pos.y.is_finite()
+            || !pi.vel.x.is_finite() || !pi.vel.y.is_finite()
+            || !pi.temperature.is_finite()
+        });
+
+        match bad {
+            Some(pi) => Err(SpherroError::Unstable{ id: pi.id }),
+            None => Ok(()),
+        }
     }
 
-    pub fn queue_despawn_particles(&mut self, count: usize) {
-        self.events.push(Event::Despawn(count));
+    fn set_phase_where<F>(&mut self, phase: u32, inside: F) -> usize where F: Fn(Vector2f) -> bool {
+        let mut count = 0;
+        for pi in self.particles.iter_mut().filter(|pi| inside(pi.pos)) {
+            pi.phase = phase;
+            count += 1;
+        }
+        count
     }
 
-    pub fn is_unstable(&self) -> bool {
-        self.particles.iter().any(|pi| {
-            !pi.pos.x.is_finite() || !pi.pos.y.is_finite()
-        })
+    fn add_emitter(&mut self, from: Vector2f, to: Vector2f, count: usize, period: f32) -> Result<usize, SpherroError> {
+        if !(period.is_finite() && period >= MIN_EMITTER_PERIOD) {
+            return Err(SpherroError::InvalidConfig(format!(
+                "tracer emitter period must be at least {}, got {}", MIN_EMITTER_PERIOD, period,
+            )));
+        }
+
+        let idx = self.tracers.add_emitter(Emitter::new(from, to, count, period, self.time));
+        self.tracers.emit(self.time);
+        Ok(idx)
     }
-}
 
-#[allow(non_snake_case)]
-impl Universe {
+    fn add_heat_source(&mut self, zone: HeatZone, temperature: f32, rate: f32) -> usize {
+        self.heat_sources.push(HeatSource { zone, temperature, rate });
+        self.heat_sources.len() - 1
+    }
 
-    pub fn get_particles(&self) -> &Vec<Particle> {
-        &self.particles
+    fn add_body(&mut self, shape: Shape, pos: Vector2f, relative_density: f32) -> Result<usize, SpherroError> {
+        let positive = |v: f32| v.is_finite() && v > 0.0;
+        let sized = match shape {
+            Shape::Box(w, h) => positive(w) && positive(h),
+            Shape::Disc(r) => positive(r),
+        };
+        if !sized {
+            return Err(SpherroError::InvalidConfig(format!("body size must be positive, got {:?}", shape)));
+        }
+        if !positive(relative_density) {
+            return Err(SpherroError::InvalidConfig(format!(
+                "relative density must be positive, got {}", relative_density,
+            )));
+        }
+
+        let mass = relative_density * self.fluid_area_density() * shape.area();
+        Ok(self.push_body(RigidBody::new(shape, pos, mass, BODY_SAMPLE_SPACING * self.h)))
+    }
+
+    fn add_obstacle(&mut self, shape: Shape, pos: Vector2f) -> usize {
+        self.push_body(RigidBody::new_kinematic(shape, pos, BODY_SAMPLE_SPACING * self.h))
+    }
+
+    fn push_body(&mut self, mut body: RigidBody) -> usize {
+        self.compute_psi(&mut body);
+        self.bodies.push(body);
+        self.bodies.len() - 1
+    }
+
+    // Sets the volumes of the boundary particles of `body` for the current
+    // kernel, smoothing length and rest density
+    fn compute_psi(&self, body: &mut RigidBody) {
+        let (kernel, h) = (self.kernel, self.h);
+        body.compute_psi(self.rest_rho(), h*2.0, |x| {
+            kernel.f(x.magnitude() / h) / h.powi(3) //TODO: Should be powi(2), but that explodes
+        });
+    }
+
+    fn update_body_volumes(&mut self) {
+        let mut bodies = std::mem::take(&mut self.bodies);
+        for body in bodies.iter_mut() {
+            self.compute_psi(body);
+        }
+        self.bodies = bodies;
     }
 
     // Updates the density and pressure for every particle
-    fn update_particle_fields(&mut self, neighbours: &Neighbours) {
-        for i in 0..self.particles.len() {
-            let pi = &self.particles[i];
+    fn update_particle_fields(&mut self, neighbours: &Neighbours, boundary_neighbours: &Neighbours) {
+        let h = self.h;
+        let rest_rho = self.rest_rho();
+        let stiffness = self.stiffness;
+        let domain = self.domain();
+        let kernel = self.kernel;
+        let (particles, boundary) = (&self.particles, &self.boundary);
+        let rhos = par_map(particles.len(), |i| {
+            let pi = &particles[i];
             let rho: f32 = neighbours[i].iter().map(|&j| {
-                let pj = &self.particles[j];
-                let x_ij = pi.pos - pj.pos;
-                let q = x_ij.magnitude() / H;
-                let Wj = cubicspline_f(q) / H.powi(3); //TODO: Should be powi(2), but that explodes
+                let pj = &particles[j];
+                let x_ij = domain.offset(pi.pos, pj.pos);
+                let q = x_ij.magnitude() / h;
+                let Wj = kernel.f(q) / h.powi(3); //TODO: Should be powi(2), but that explodes
                 pj.mass * Wj
             }).sum();
 
-            let pressure = K * ((rho / REST_RHO).powi(7) - 1.0);
-            self.particles[i].rho = rho;
-            self.particles[i].pressure = pressure;
+            // Boundary particles contribute with their volume scaled by the rest density
+            rho + boundary_neighbours[i].iter().map(|&b| {
+                let sb = &boundary[b];
+                let q = domain.offset(pi.pos, sb.pos).magnitude() / h;
+                sb.psi * kernel.f(q) / h.powi(3)
+            }).sum::<f32>()
+        });
+
+        for (pi, rho) in self.particles.iter_mut().zip(rhos) {
+            pi.rho = rho;
+            pi.pressure = stiffness * ((rho / rest_rho).powi(GAMMA) - 1.0);
+        }
+    }
+
+    // Computes the surface normals used by the surface tension model, and flags
+    // the particles that are on the free surface. Requires up-to-date densities
+    fn update_surface_normals(&mut self, neighbours: &Neighbours) {
+        let h = self.h;
+        let domain = self.domain();
+        let kernel = self.kernel;
+        let normals: Vec<Vector2f> = self.particles.iter().zip(neighbours.iter()).map(|(pi, js)| {
+            // Scaled by the support radius 2H, but grad_w is already missing
+            // the 1/h from differentiating q
+            2.0 * js.iter().map(|&j| {
+                let pj = &self.particles[j];
+                (pj.mass / pj.rho) * grad_w(kernel, h, domain.offset(pi.pos, pj.pos))
+            }).sum::<Vector2f>()
+        }).collect();
+
+        for (pi, n) in self.particles.iter_mut().zip(normals) {
+            pi.normal = n;
+        }
+
+        let flags: Vec<bool> = neighbours.iter().enumerate().map(|(i, js)| {
+            let pos = self.particles[i].pos;
+            let near_wall = (!self.periodic_x && (pos.x < h || pos.x > self.width - h))
+                         || (!self.periodic_y && (pos.y < h || pos.y > self.height - h));
+
+            surface::is_surface(self.surface_classifier, &domain, &self.particles, i, js, h, near_wall)
+        }).collect();
+
+        for (pi, is_surface) in self.particles.iter_mut().zip(flags) {
+            pi.is_surface = is_surface;
+        }
+    }
+
+    // Vorticity and strain rate from the gradient of the SPH velocity
+    // interpolant, taken relative to the particle's own velocity. The
+    // gradient is corrected so that it is exact for linear velocity
+    // fields(Bonet and Lok 1999), and taken over a narrower kernel, see
+    // GRADIENT_SMOOTHING. Near the surface, where the correction is
+    // ill-conditioned, it is normalized by the kernel weights like
+    // `sampling::sample` instead. Neither depends on the solver's density
+    // scaling
+    fn update_velocity_gradients(&mut self, neighbours: &Neighbours, dt: f32) {
+        let gradients = self.velocity_gradients(neighbours);
+
+        for (pi, g) in self.particles.iter_mut().zip(gradients.iter()) {
+            let dxy = 0.5 * (g[0][1] + g[1][0]);
+            pi.vorticity = g[1][0] - g[0][1];
+            pi.strain_rate = (2.0 * (g[0][0] * g[0][0] + g[1][1] * g[1][1] + 2.0 * dxy * dxy)).sqrt();
+        }
+
+        self.update_stress(&gradients, dt);
+    }
+
+    // g[a][b] is the derivative of v_a along b, see `update_velocity_gradients`
+    fn velocity_gradients(&self, neighbours: &Neighbours) -> Vec<[[f32; 2]; 2]> {
+        let h = GRADIENT_SMOOTHING * self.h;
+        let domain = self.domain();
+        let kernel = self.kernel;
+        self.particles.iter().enumerate().map(|(i, pi)| {
+            // g[a][b] is the derivative of v_a along b, and m the same for
+            // the positions, which would be the identity for an exact kernel
+            let mut g = [[0.0; 2]; 2];
+            let mut m = [[0.0; 2]; 2];
+            let mut weight = 0.0;
+            for &j in neighbours[i].iter() {
+                let pj = &self.particles[j];
+                let x_ij = domain.offset(pi.pos, pj.pos);
+                let volume = pj.mass / pj.rho;
+                let dw = volume * grad_w(kernel, h, x_ij);
+                let dv = pj.vel - pi.vel;
+                g[0][0] += dv.x * dw.x;
+                g[0][1] += dv.x * dw.y;
+                g[1][0] += dv.y * dw.x;
+                g[1][1] += dv.y * dw.y;
+                m[0][0] -= x_ij.x * dw.x;
+                m[0][1] -= x_ij.x * dw.y;
+                m[1][0] -= x_ij.y * dw.x;
+                m[1][1] -= x_ij.y * dw.y;
+                weight += volume * kernel.f(x_ij.magnitude() / h) / (h * h);
+            }
+
+            let det = m[0][0] * m[1][1] - m[0][1] * m[1][0];
+            if det > MIN_GRADIENT_CORRECTION {
+                // g m^-1
+                let inv = [[m[1][1] / det, -m[0][1] / det], [-m[1][0] / det, m[0][0] / det]];
+                let mul = |r: [f32; 2]| [r[0] * inv[0][0] + r[1] * inv[1][0], r[0] * inv[0][1] + r[1] * inv[1][1]];
+                [mul(g[0]), mul(g[1])]
+            } else if weight > 0.0 {
+                [[g[0][0] / weight, g[0][1] / weight], [g[1][0] / weight, g[1][1] / weight]]
+            } else {
+                [[0.0; 2]; 2]
+            }
+        }).collect()
+    }
+
+    // Advances the elastic stress of granular particles, and caps it at the
+    // Drucker-Prager yield surface, where the material flows plastically.
+    // Uses the Jaumann rate, so that the stress rotates with the material
+    fn update_stress(&mut self, gradients: &[[[f32; 2]; 2]], dt: f32) {
+        let shear_modulus = SHEAR_MODULUS * self.stiffness;
+        for (pi, g) in self.particles.iter_mut().zip(gradients.iter()) {
+            let (friction_angle, cohesion) = match self.rheologies.get(pi.phase as usize) {
+                Some(&Rheology::DruckerPrager { friction_angle, cohesion }) => (friction_angle, cohesion),
+                _ => {
+                    pi.stress = [0.0; 3];
+                    continue;
+                },
+            };
+
+            let [sxx, sxy, syy] = pi.stress;
+            let (dxx, dyy, dxy) = (g[0][0], g[1][1], 0.5 * (g[0][1] + g[1][0]));
+            let w = 0.5 * (g[0][1] - g[1][0]);
+            let trace = 0.5 * (dxx + dyy);
+
+            // 2G D' + W S - S W, with W the spin tensor
+            let (sxx, sxy, syy) = (
+                sxx + dt * (2.0 * shear_modulus * (dxx - trace) + 2.0 * w * sxy),
+                sxy + dt * (2.0 * shear_modulus * dxy + w * (syy - sxx)),
+                syy + dt * (2.0 * shear_modulus * (dyy - trace) - 2.0 * w * sxy),
+            );
+
+            // Granular material can't pull, so it has no strength in tension
+            // unless it is cohesive. The pressure is applied once per pressure
+            // iteration, so the fluid feels about as many times the EOS
+            // pressure as the last step ran iterations, and the first
+            // invariant is 3 times that in plane strain
+            let (alpha, k) = rheology::drucker_prager(friction_angle, cohesion);
+            let iterations = self.last_pressure_iterations as f32;
+            let limit = (3.0 * alpha * iterations * pi.pressure + k).max(0.0);
+            let j2 = (0.5 * (sxx * sxx + syy * syy) + sxy * sxy).sqrt();
+            let scale = if j2 > limit { limit / j2 } else { 1.0 };
+            pi.stress = [sxx * scale, sxy * scale, syy * scale];
+        }
+    }
+
+    // Accelerations from the divergence of the granular stresses
+    fn compute_stress_dv(&self, neighbours: &Neighbours) -> Vec<Vector2f> {
+        let mut stress_dv = vec![vec2f_zero(); self.particles.len()];
+        if !self.rheologies.iter().any(|r| matches!(r, Rheology::DruckerPrager { .. })) {
+            return stress_dv;
+        }
+
+        let domain = self.domain();
+        let kernel = self.kernel;
+        let over_rho2 = |p: &Particle| {
+            let r2 = p.rho * p.rho;
+            [p.stress[0] / r2, p.stress[1] / r2, p.stress[2] / r2]
+        };
+        for (i, pi) in self.particles.iter().enumerate() {
+            let si = over_rho2(pi);
+            stress_dv[i] = neighbours[i].iter().map(|&j| {
+                let pj = &self.particles[j];
+                let sj = over_rho2(pj);
+                let dw = grad_w(kernel, self.h, domain.offset(pi.pos, pj.pos));
+                let (sxx, sxy, syy) = (si[0] + sj[0], si[1] + sj[1], si[2] + sj[2]);
+                pj.mass * Vector2f::new(sxx * dw.x + sxy * dw.y, sxy * dw.x + syy * dw.y)
+            }).sum::<Vector2f>();
+        }
+
+        stress_dv
+    }
+
+    // Vorticity confinement(Fedkiw et al. 2001) as accelerations. Pushes
+    // the fluid around each vortex along N x w, where N points towards the
+    // vortex core, ie. up the gradient of |w|
+    fn compute_confinement_dv(&self, neighbours: &Neighbours) -> Vec<Vector2f> {
+        let mut confinement_dv = vec![vec2f_zero(); self.particles.len()];
+        if self.vorticity_confinement == 0.0 {
+            return confinement_dv;
+        }
+
+        let domain = self.domain();
+        let kernel = self.kernel;
+        for (i, pi) in self.particles.iter().enumerate() {
+            let eta = neighbours[i].iter().map(|&j| {
+                let pj = &self.particles[j];
+                (pj.mass / pj.rho) * (pj.vorticity.abs() - pi.vorticity.abs()) * grad_w(kernel, self.h, domain.offset(pi.pos, pj.pos))
+            }).sum::<Vector2f>();
+
+            let mag = eta.magnitude();
+            if mag < 1e-6 {
+                continue;
+            }
+
+            let n = eta / mag;
+            confinement_dv[i] = self.vorticity_confinement * pi.vorticity * Vector2f::new(n.y, -n.x);
+        }
+
+        confinement_dv
+    }
+
+    // Surface tension(cohesion + curvature) and wall adhesion, as accelerations
+    fn compute_surface_dv(&self, neighbours: &Neighbours) -> Vec<Vector2f> {
+        let mass = self.mass;
+        let mut surface_dv = vec![vec2f_zero(); self.particles.len()];
+        if self.surface_tension == 0.0 && self.adhesion == 0.0 {
+            return surface_dv;
+        }
+
+        let c = 2.0 * self.h;
+        let domain = self.domain();
+        for (i, pi) in self.particles.iter().enumerate() {
+            let mut dv = vec2f_zero();
+
+            if self.surface_tension != 0.0 {
+                for &j in neighbours[i].iter() {
+                    let pj = &self.particles[j];
+                    let x_ij = domain.offset(pi.pos, pj.pos);
+                    let r = x_ij.magnitude();
+                    if r < 1e-6 {
+                        continue;
+                    }
+
+                    // Corrects for particle deficiency near the surface
+                    let k_ij = 2.0 * self.rest_rho() / (pi.rho + pj.rho);
+
+                    // Weighted by the neighbour's volume and scaled by the
+                    // support radius, like the normals, so that both terms
+                    // are about as strong at a flat surface. Weighted by mass
+                    // as in the paper, cohesion is lost at this scale
+                    let cohesion = c * (pj.mass / pj.rho) * cohesion_f(r, c) * (x_ij / r);
+                    let curvature = pi.normal - pj.normal;
+                    dv -= self.surface_tension * k_ij * (cohesion + curvature);
+                }
+            }
+
+            if self.adhesion != 0.0 {
+                // Each wall acts as a single boundary sample at the closest
+                // point. Periodic axes have no walls
+                let walls = [
+                    (self.periodic_x, pi.pos.x,               Vector2f::new(-1.0,  0.0)),
+                    (self.periodic_x, self.width - pi.pos.x,  Vector2f::new( 1.0,  0.0)),
+                    (self.periodic_y, pi.pos.y,               Vector2f::new( 0.0, -1.0)),
+                    (self.periodic_y, self.height - pi.pos.y, Vector2f::new( 0.0,  1.0)),
+                ];
+                for (_, dist, dir) in walls.iter().filter(|w| !w.0) {
+                    dv += self.adhesion * mass * adhesion_f(*dist, c) * dir;
+                }
+            }
+
+            surface_dv[i] = dv;
         }
+
+        surface_dv
     }
 
     // Performs the first part of the splitting solver: updates position and velocity
     // without considering forces which arise from differences in pressure
-    fn update_nonpressure_forces(&mut self, neighbours: &Neighbours, force_neighbours: &Neighbours, dt: f32) {
+    fn update_nonpressure_forces(&mut self, neighbours: &Neighbours, force_neighbours: &Neighbours,
+                                 boundary_neighbours: &Neighbours, dt: f32) {
+        let h = self.h;
         let mut force_dv = vec![vec2f_zero(); self.particles.len()];
+        let surface_dv = self.compute_surface_dv(neighbours);
+        let confinement_dv = self.compute_confinement_dv(neighbours);
+        let stress_dv = self.compute_stress_dv(neighbours);
+        let domain = self.domain();
+        let kernel = self.kernel;
+        let sound_speed = self.get_sound_speed();
+
+        // Viscosities of the particles in non-Newtonian phases, at their
+        // current strain rates
+        let max_nu = MAX_RHEOLOGY_VISCOSITY * h * h / dt;
+        let nus: Vec<Option<f32>> = self.particles.iter().map(|pi| {
+            self.get_rheology(pi.phase).viscosity(pi.strain_rate, max_nu)
+        }).collect();
+        let newtonian_nu = self.kinematic_viscosity();
+
+        // Boundaries always use the Laplacian model, with the same kinematic
+        // viscosity as the fluid
+        let boundary_viscosity = self.kinematic_viscosity() / h;
+
+        if self.xsph != 0.0 {
+            self.apply_xsph(neighbours);
+        }
 
         // Forces update
         for (force, neighbours) in izip!(self.forces.iter(), force_neighbours.iter()) {
             for j in neighbours.iter() {
                 let pj = &self.particles[*j];
 
-                let dir = (pj.pos - force.pos()).normalize();
-                let dist2 = (pj.pos - force.pos()).magnitude2();
-                let mag = (force.power / dist2).min(MAX_FORCE_MAG / dt);
-                let vel = dir * mag;
+                let mut vel = force.acceleration(pj.pos, pj.vel);
+                let mag = vel.magnitude();
+                if mag > MAX_FORCE_MAG / dt {
+                    vel *= (MAX_FORCE_MAG / dt) / mag;
+                }
 
                 force_dv[*j] += vel;
             }
         }
 
         // Viscosity and gravity update
-        let gravity_dv = Vector2f::new(0.0, GRAVITY);
+        let neighbours_idx = neighbours;
         for i in 0..self.particles.len() {
             let pi = &self.particles[i];
             let neighbours: Vec<&Particle> = neighbours[i]
@@ -176,38 +1764,130 @@ impl Universe {
 
             // Compute x_ijs
             let x_ijs: Vec<Vector2f> = neighbours.iter().map(|pj| {
-                pi.pos - pj.pos
+                domain.offset(pi.pos, pj.pos)
             }).collect();
 
             // Compute gradient of W
-            let dWs: Vec<Vector2f> = izip!(&neighbours, &x_ijs).map(|(pj, x_ij)| {
-                let q = x_ij.magnitude() / H;
-                let df = cubicspline_df(q);
-
-                let dq = (pi.pos - pj.pos) / (H * q); // gradient of q
-                let dW = (1.0 / H.powi(3)) * df * dq; //TODO: Should be powi(2), but that explodes
+            let dWs: Vec<Vector2f> = x_ijs.iter().map(|&x_ij| grad_w(kernel, h, x_ij)).collect();
+
+            // Compute viscosity. Pairs involving a non-Newtonian particle use
+            // the Morris model with each particle's own viscosity
+            let ddv = izip!(&neighbours_idx[i], &neighbours, &x_ijs, &dWs).map(|(&j, pj, x_ij, dW)| {
+                match (nus[i], nus[j]) {
+                    (None, None) => self.viscosity.pair_dv(pi, pj, *x_ij, *dW, sound_speed, h),
+                    (nu_i, nu_j) => {
+                        let pair = (nu_i.unwrap_or(newtonian_nu), nu_j.unwrap_or(newtonian_nu));
+                        viscosity::morris_dv(pi, pj, pair, *x_ij, *dW, h)
+                    },
+                }
+            }).sum::<Vector2f>();
 
-                dW
-            }).collect();
+            // Friction against moving boundaries, with the opposite force
+            // applied to the body
+            let mut boundary_ddv = vec2f_zero();
+            for &b in boundary_neighbours[i].iter() {
+                let sb = &self.boundary[b];
+                let x_ib = domain.offset(pi.pos, sb.pos);
+                let q1 = (sb.psi / self.rest_rho()) * (pi.vel - sb.vel);
+                let q2 = (x_ib.dot(grad_w(kernel, h, x_ib))) / (x_ib.dot(x_ib) + 0.01*h*h);
+                let dv = nus[i].map_or(boundary_viscosity, |nu| nu / h) * 2.0 * q1 * q2;
+
+                boundary_ddv += dv;
+                self.bodies[sb.body].apply_force(-pi.mass * dv, sb.pos);
+            }
 
-            // Compute viscosity
-            let ddv = 2.0 * izip!(&neighbours, &x_ijs, &dWs).map(|(pj, x_ij, dW)| {
-                let q1 = (pj.mass / pj.rho) * (pi.vel - pj.vel);
-                let q2 = (x_ij.dot(*dW)) / (x_ij.dot(*x_ij) + 0.01*H*H);
-                q1 * q2
-            }).sum::<Vector2f>();
+            let buoyancy = -self.thermal_expansion * (pi.temperature - self.reference_temperature) * self.gravity;
 
             let vel = pi.vel
-                    + (VISC * ddv + gravity_dv + force_dv[i]) * dt;
+                    + (ddv + boundary_ddv + force_dv[i] + surface_dv[i] + confinement_dv[i] + stress_dv[i]) * dt
+                    + (self.gravity + buoyancy + self.body_force.sample(pi.pos, self.time, self.width, self.height)) * dt;
 
             self.particles[i].vel = vel;
             self.particles[i].pos += vel * dt;
         }
     }
 
+    // Moves every velocity towards the kernel weighted average of its
+    // neighbours'(Monaghan 1989). Uses the velocities from before smoothing
+    // throughout
+    fn apply_xsph(&mut self, neighbours: &Neighbours) {
+        let h = self.h;
+        let domain = self.domain();
+        let kernel = self.kernel;
+        let dvs: Vec<Vector2f> = self.particles.iter().enumerate().map(|(i, pi)| {
+            neighbours[i].iter().map(|&j| {
+                let pj = &self.particles[j];
+                let q = domain.offset(pi.pos, pj.pos).magnitude() / h;
+                let rho = 0.5 * (pi.rho + pj.rho);
+                (pj.mass / rho) * (kernel.f(q) / h.powi(3)) * (pj.vel - pi.vel)
+            }).sum::<Vector2f>()
+        }).collect();
+
+        for (pi, dv) in self.particles.iter_mut().zip(dvs) {
+            pi.vel += self.xsph * dv;
+        }
+    }
+
+    // Conducts heat between neighbours and applies the heat sources. Uses the
+    // temperatures from the start of the step throughout
+    fn update_temperature(&mut self, neighbours: &Neighbours, boundary_neighbours: &Neighbours, dt: f32) {
+        if self.conductivity == 0.0 && self.heat_sources.is_empty() {
+            return;
+        }
+
+        let domain = self.domain();
+        let kernel = self.kernel;
+        let temperatures: Vec<f32> = (0..self.particles.len()).map(|i| {
+            let pi = &self.particles[i];
+
+            // Discretized the same way as the viscosity
+            let conduction = 2.0 * neighbours[i].iter().map(|&j| {
+                let pj = &self.particles[j];
+                let x_ij = domain.offset(pi.pos, pj.pos);
+                let q1 = (pj.mass / pj.rho) * (pi.temperature - pj.temperature);
+                let q2 = (x_ij.dot(grad_w(kernel, self.h, x_ij))) / (x_ij.dot(x_ij) + 0.01*self.h*self.h);
+                q1 * q2
+            }).sum::<f32>();
+
+            let mut t = pi.temperature + self.conductivity * conduction * dt;
+
+            let touches = |body| boundary_neighbours[i].iter().any(|&b| self.boundary[b].body == body);
+            for source in self.heat_sources.iter() {
+                if source.contains(pi.pos, self.width, self.height, touches) {
+                    t += source.heat(t, dt);
+                }
+            }
+
+            t
+        }).collect();
+
+        for (pi, t) in self.particles.iter_mut().zip(temperatures) {
+            pi.temperature = t;
+        }
+    }
+
+    // Runs the pressure iterations with the chosen solver, and returns how
+    // many it took
+    fn solve_pressure(&mut self, neighbours: &Neighbours, boundary_neighbours: &Neighbours, dt: f32) -> u32 {
+        for iteration in 0..self.pressure_iterations {
+            self.update_particle_fields(neighbours, boundary_neighbours);
+            if self.pressure_solver == PressureSolver::Adaptive && iteration > 0 &&
+               solver::average_compression(&self.particles, self.rest_rho()) < self.pressure_tolerance {
+                return iteration;
+            }
+
+            self.update_pressure_forces(neighbours, boundary_neighbours, dt);
+        }
+
+        self.pressure_iterations
+    }
+
     // Performs the second part of the splitting solver: updates position and velocity
     // with only pressure forces
-    fn update_pressure_forces(&mut self, neighbours: &Neighbours, dt: f32) {
+    fn update_pressure_forces(&mut self, neighbours: &Neighbours, boundary_neighbours: &Neighbours, dt: f32) {
+        let domain = self.domain();
+        let kernel = self.kernel;
+        let h = self.h;
         for i in 0..self.particles.len() {
             let pi = &self.particles[i];
             let neighbours: Vec<&Particle> = neighbours[i]
@@ -216,22 +1896,24 @@ impl Universe {
                                             .collect();
 
             // Compute gradient of W
-            let dWs: Vec<Vector2f> = neighbours.iter().map(|pj| {
-                let x_ij = pi.pos - pj.pos;
-                let q = x_ij.magnitude() / H;
-                let df = cubicspline_df(q);
-
-                let dq = (pi.pos - pj.pos) / (H * q); // gradient of q
-                let dW = (1.0 / H.powi(3)) * df * dq; //TODO: Should be powi(2), but that explodes
-
-                dW
-            }).collect();
+            let dWs: Vec<Vector2f> = neighbours.iter().map(|pj| grad_w(kernel, h, domain.offset(pi.pos, pj.pos))).collect();
 
             let dP = pi.rho * izip!(&neighbours, &dWs).map(|(pj, dW)| {
                 pj.mass * (pi.pressure / pi.rho.powi(2) + pj.pressure / pj.rho.powi(2)) * dW
             }).sum::<Vector2f>();
 
-            let p_dv = -dP / pi.rho;
+            let mut p_dv = -dP / pi.rho;
+
+            // Boundary particles mirror the fluid particle's pressure. Negative
+            // pressures are clamped so that the fluid doesn't stick to bodies
+            let p_rho2 = pi.pressure.max(0.0) / pi.rho.powi(2);
+            for &b in boundary_neighbours[i].iter() {
+                let sb = &self.boundary[b];
+                let dv = -sb.psi * p_rho2 * grad_w(kernel, h, domain.offset(pi.pos, sb.pos));
+
+                p_dv += dv;
+                self.bodies[sb.body].apply_force(-pi.mass * dv, sb.pos);
+            }
 
             self.particles[i].vel += dt * p_dv;
             self.particles[i].pos += dt * dt * p_dv;
@@ -239,24 +1921,72 @@ impl Universe {
     }
 
     // the first return value is the neighbours for each particle,
-    // the second return value is the neighbours for all the forces
-    fn compute_neighbours(&self) -> (Neighbours, Neighbours) {
-        let accel = Grid::new(self.width, self.height, H, &self.particles);
-        let neighbours: Neighbours = (0..self.particles.len()).map(|i| {
-            accel.nearest_by_idx(i, H*2.0)
-        }).collect();
+    // the second return value is the neighbours for all the forces,
+    // the third return value is the boundary particles near each particle
+    fn compute_neighbours(&self) -> (Neighbours, Neighbours, Neighbours) {
+        let h = self.h;
+        let accel = Grid::with_domain(self.domain(), h, &self.particles);
+        let neighbours: Neighbours = par_map(self.particles.len(), |i| {
+            accel.nearest_by_idx(i, h*2.0)
+        });
         let force_neighbours: Neighbours = self.forces.iter().map(|f| {
             accel.nearest_by_pos(f.pos(), f.r)
         }).collect();
 
-        (neighbours, force_neighbours)
+        let particles = &self.particles;
+        let boundary_accel = Grid::with_domain(self.domain(), h, &self.boundary);
+        let boundary_neighbours: Neighbours = par_map(particles.len(), |i| {
+            boundary_accel.nearest_by_pos(particles[i].pos, h*2.0)
+        });
+
+        (neighbours, force_neighbours, boundary_neighbours)
+    }
+
+    // Places the boundary particles of every body at its current position
+    fn update_boundary_samples(&mut self) {
+        self.boundary.clear();
+        for (i, body) in self.bodies.iter().enumerate() {
+            self.boundary.extend(body.world_samples(i));
+        }
+    }
+
+    // Pressure alone can't stop fast particles from tunneling through the single
+    // layer of boundary particles. Any particle that ends up inside a body is
+    // pushed back to its outline and exchanges momentum with the body
+    fn update_body_collisions(&mut self) {
+        for pi in self.particles.iter_mut() {
+            for body in self.bodies.iter_mut() {
+                if let Some((surface, normal)) = body.penetration(pi.pos) {
+                    pi.vel += body.resolve_contact(pi.mass, pi.vel, surface, normal);
+                    pi.pos = surface + 1e-2 * normal;
+                }
+            }
+        }
     }
 
-    // Reverses velocity if the particle is outside bounds
+    // Integrates the rigid bodies with the forces the fluid applied on them
+    fn update_bodies(&mut self, dt: f32) {
+        for body in self.bodies.iter_mut() {
+            let g = self.gravity + self.body_force.sample(body.pos, self.time, self.width, self.height);
+            body.integrate(g, self.time, dt);
+            body.collide_walls(self.width, self.height, BOUNDARY_COR);
+        }
+    }
+
+    // Reverses velocity if the particle is outside bounds, or brings it back
+    // in from the opposite side along periodic axes
     fn update_boundary(&mut self) {
+        let domain = self.domain();
         for i in 0..self.particles.len() {
             let pi = &self.particles[i];
-            self.particles[i].vel = self.boundary_correction_vel(&pi.pos, &pi.vel);
+            let (pos, vel) = match self.get_rheology(pi.phase) {
+                Rheology::DruckerPrager { friction_angle, .. } => {
+                    self.boundary_friction(&pi.pos, &pi.vel, friction_angle.tan())
+                },
+                _ => (pi.pos, self.boundary_correction_vel(&pi.pos, &pi.vel)),
+            };
+            self.particles[i].vel = vel;
+            self.particles[i].pos = domain.wrap(pos);
         }
     }
 
@@ -266,79 +1996,110 @@ impl Universe {
         let mut vel: Vector2f = *vel;
 
         // Bounce off walls
-        if pos.x < 0.0 {
+        if !self.periodic_x && pos.x < 0.0 {
             vel.x = ( BOUNDARY_MIN_DV).max(-BOUNDARY_COR*vel.x);
-        } else if pos.x > self.width {
+        } else if !self.periodic_x && pos.x > self.width {
             vel.x = (-BOUNDARY_MIN_DV).min(-BOUNDARY_COR*vel.x);
-        } else if pos.y < 0.0 {
+        } else if !self.periodic_y && pos.y < 0.0 {
             vel.y = ( BOUNDARY_MIN_DV).max(-BOUNDARY_COR*vel.y);
-        } else if pos.y > self.height {
+        } else if !self.periodic_y && pos.y > self.height {
             vel.y = (-BOUNDARY_MIN_DV).min(-BOUNDARY_COR*vel.y);
         }
 
         vel
     }
 
-    // Handles the particle spawning and despawning events
-    pub fn update_events(&mut self) {
-        let mut rng = rand::thread_rng();
+    // Granular particles don't bounce off the walls. They are put back on
+    // the wall, lose their velocity into it, and Coulomb friction takes up to
+    // `friction` times that much off their velocity along it
+    fn boundary_friction(&self, pos: &Vector2f, vel: &Vector2f, friction: f32) -> (Vector2f, Vector2f) {
+        let (mut pos, mut vel) = (*pos, *vel);
+        let slow = |v: f32, dv: f32| v.signum() * (v.abs() - friction * dv.abs()).max(0.0);
+
+        if !self.periodic_x && (pos.x < 0.0 || pos.x > self.width) {
+            let dv = if pos.x < 0.0 { vel.x.min(0.0) } else { vel.x.max(0.0) };
+            pos.x = pos.x.max(0.0).min(self.width);
+            vel.x -= dv;
+            vel.y = slow(vel.y, dv);
+        }
+        if !self.periodic_y && (pos.y < 0.0 || pos.y > self.height) {
+            let dv = if pos.y < 0.0 { vel.y.min(0.0) } else { vel.y.max(0.0) };
+            pos.y = pos.y.max(0.0).min(self.height);
+            vel.y -= dv;
+            vel.x = slow(vel.x, dv);
+        }
+
+        (pos, vel)
+    }
 
-        for event in self.events.iter() {
+    // Carries the tracers along the interpolated velocity field, then
+    // releases the ones that are due. Runs once per update, after the
+    // substeps and events, with the field frozen at the end of the step
+    fn update_tracers(&mut self, dt: f32) {
+        let (kernel, h) = (self.kernel, self.h);
+        if !self.tracers.is_empty() {
+            let domain = self.domain();
+            let grid = Grid::with_domain(domain, h, &self.particles);
+            let particles = &self.particles;
+            self.tracers.advect(|p| {
+                sampling::sample(&domain, &grid, particles, (kernel, h), domain.wrap(p), false).map(|s| s.velocity())
+            }, &domain, dt);
+        }
+
+        self.tracers.emit(self.time);
+    }
+
+    // Handles the particle spawning and despawning events. Every event is
+    // handled, even if some of the despawns fail. The first failure is returned
+    fn update_events(&mut self) -> Result<(), SpherroError> {
+        let h = self.h;
+        let mut result = Ok(());
+
+        let events: Vec<Event> = self.events.drain(..).collect();
+        for event in events.iter() {
             match event {
                 Event::Spawn(count, pos) => {
                     for _ in 0..*count {
                         // If we cluster all the points at the exact same location,
                         // the pressure force will become extremly high and destabilize the
                         // simulation
-                        let x: f32 = pos.x + (rng.gen::<f32>() - 0.5) * (0.3 * H);
-                        let y: f32 = pos.y + (rng.gen::<f32>() - 0.5) * (0.3 * H);
-                        let pi = Particle{
-                            pos: Vector2f::new(x, y),
-                            vel: vec2f_zero(),
-                            col: Color::new(0.0, 0.0, 1.0),
-                            mass: MASS,
-                            rho: 0.0,
-                            pressure: 0.0,
-                        };
+                        let x: f32 = pos.x + (self.rng.gen::<f32>() - 0.5) * (0.3 * h);
+                        let y: f32 = pos.y + (self.rng.gen::<f32>() - 0.5) * (0.3 * h);
+                        let mut pi = Particle::new(self.next_id, Vector2f::new(x, y), self.mass);
+                        pi.temperature = self.reference_temperature;
                         self.particles.push(pi);
+                        self.next_id += 1;
                     }
                 },
-                Event::Despawn(count) => {
-                    // We remove the particles which have the highest velocity
-                    // This is a heuristic to prevent destabilization
-                    assert!(*count <= self.particles.len());
-
-                    let mut s: Vec<(f32, usize)> = self.particles
-                                                  .iter()
-                                                  .enumerate()
-                                                  .map(|(idx, pi)| (pi.vel.magnitude2(), idx))
-                                                  .collect();
-
-                    // TODO: Use a faster sort algorithm, we only need top-k
-                    s.sort_unstable_by(|a, b| b.partial_cmp(a).unwrap());
-                    let order: Vec<_> = s.iter().map(|t| t.1).collect();
-                    let top_k = &order[order.len()-count-1 .. order.len()];
-
-                    self.particles = self.particles
-                                    .clone() //TODO: Avoid this clone
-                                    .into_iter()
-                                    .enumerate()
-                                    .filter(|(idx, _)| !top_k.contains(idx))
-                                    .map(|t| t.1)
-                                    .collect();
+                Event::Despawn(removal) => {
+                    if let Err(e) = self.remove_particles(removal) {
+                        if result.is_ok() {
+                            result = Err(e);
+                        }
+                    }
                 }
             }
         }
-        self.events.clear();
+
+        if !events.is_empty() {
+            self.rebuild_id_map();
+        }
+
+        result
     }
 }
 
 // All debug functions
 impl Universe {
     pub fn debug_single_particle(&mut self) {
+        self.record(Input::DebugSingleParticle);
+        let h = self.h;
         const CHOSEN_IDX: usize = 247;
-        let accel = Grid::new(self.width, self.height, H, &self.particles);
-        let neighbours = accel.nearest_by_idx(CHOSEN_IDX, H*2.0);
+        if self.particles.len() <= CHOSEN_IDX {
+            return;
+        }
+        let accel = Grid::new(self.width, self.height, h, &self.particles);
+        let neighbours = accel.nearest_by_idx(CHOSEN_IDX, h*2.0);
         self.particles[CHOSEN_IDX].col = Color::new(0.0, 0.0, 0.0);
         for j in neighbours.into_iter() {
             self.particles[j].col = Color::new(1.0, 1.0, 0.0);
@@ -346,10 +2107,11 @@ impl Universe {
     }
 
     pub fn debug_first_force(&mut self) {
+        self.record(Input::DebugFirstForce);
         if self.forces.len() == 0 {
             return;
         }
-        let accel = Grid::new(self.width, self.height, H, &self.particles);
+        let accel = Grid::new(self.width, self.height, self.h, &self.particles);
         let force = &self.forces[0];
         let neighbours = accel.nearest_by_pos(force.pos(), force.r);
         for j in neighbours.into_iter() {
@@ -357,30 +2119,21 @@ impl Universe {
         }
     }
 
-    pub fn debug_check_nans(&self, old_particles: &Vec<Particle>) {
-        let mut is_bad = false;
-        for (i, pi) in self.particles.iter().enumerate() {
-            if !pi.pos.x.is_finite() || !pi.pos.y.is_finite() {
-                println!("Found bad particle with idx {}: {:?}", i, pi);
-
-                let accel = Grid::new(self.width, self.height, H, old_particles);
-                let neighbours = accel.nearest_by_idx(i, H*2.0);
-                println!("Previous frame: {:?}\nNeighbours:{}", old_particles[i], neighbours.len());
-                is_bad = true;
-            }
-        }
-
-        if is_bad {
-            panic!();
-        }
+    // The first particle that is no longer finite, along with its state in
+    // `old_particles` from before the step
+    pub fn debug_check_nans(&self, old_particles: &[Particle]) -> Option<(Particle, Option<Particle>)> {
+        let bad = self.particles.iter().find(|pi| !pi.pos.x.is_finite() || !pi.pos.y.is_finite())?;
+        let before = old_particles.iter().find(|pj| pj.id == bad.id).cloned();
+        Some((bad.clone(), before))
     }
 
     pub fn debug_splits(&self) -> Vec<(Vector2f, Vector2f)> {
-        let accel = Grid::new(self.width, self.height, H*2.0, &self.particles);
+        let accel = Grid::new(self.width, self.height, self.h*2.0, &self.particles);
         accel.debug_get_splits()
     }
 
     pub fn clear_colors(&mut self) {
+        self.record(Input::ClearColors);
         for pi in self.particles.iter_mut() {
             pi.col = Color::new(0.0, 0.0, 1.0);
         }
@@ -5,6 +5,10 @@ use cgmath::{Vector2, Vector3};
 pub type Vector2f = Vector2<f32>;
 pub type Color = Vector3<f32>;
 
+// 2 floats for each end of a line segment, in the buffers of path lines,
+// streaklines and reconstructed contours
+pub const SEGMENT_STRIDE: usize = 4;
+
 pub fn vec2f_zero() -> Vector2f {
     Vector2f::new(0.0, 0.0)
 }
@@ -29,4 +33,65 @@ pub fn set_panic_hook() {
 }
 
 #[inline]
-pub fn clamp_f32(v: f32, a: f32, b: f32) -> f32 { b.min(v.max(a)) }
\ No newline at end of file
+pub fn clamp_f32(v: f32, a: f32, b: f32) -> f32 { b.min(v.max(a)) }
+
+// Measures wall clock time between laps, in milliseconds. On wasm this uses
+// performance.now() and reads zero where that isn't available
+#[cfg(not(target_arch = "wasm32"))]
+pub struct Stopwatch {
+    last: std::time::Instant,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Stopwatch {
+    pub fn start() -> Stopwatch {
+        Stopwatch { last: std::time::Instant::now() }
+    }
+
+    // Returns the time since the last lap, or since the start
+    pub fn lap(&mut self) -> f32 {
+        let now = std::time::Instant::now();
+        let elapsed = now - self.last;
+        self.last = now;
+        elapsed.as_secs_f32() * 1000.0
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+pub struct Stopwatch {
+    last: f64,
+}
+
+#[cfg(target_arch = "wasm32")]
+impl Stopwatch {
+    pub fn start() -> Stopwatch {
+        Stopwatch { last: now_ms() }
+    }
+
+    pub fn lap(&mut self) -> f32 {
+        let now = now_ms();
+        let elapsed = now - self.last;
+        self.last = now;
+        elapsed as f32
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+fn now_ms() -> f64 {
+    web_sys::window().and_then(|w| w.performance())
+                     .map(|p| p.now())
+                     .unwrap_or(0.0)
+}
+
+// Maps `f` over 0..n, spread over rayon's thread pool on native targets. The
+// results come back in order, so they don't depend on the number of threads
+#[cfg(not(target_arch = "wasm32"))]
+pub fn par_map<T, F>(n: usize, f: F) -> Vec<T> where T: Send, F: Fn(usize) -> T + Sync + Send {
+    use rayon::prelude::*;
+    (0..n).into_par_iter().map(f).collect()
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn par_map<T, F>(n: usize, f: F) -> Vec<T> where F: Fn(usize) -> T {
+    (0..n).map(f).collect()
+}
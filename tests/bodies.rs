@@ -0,0 +1,87 @@
+extern crate spherro;
+
+use spherro::Universe;
+use spherro::util::Vector2f;
+use spherro::scenarios::rest_spacing;
+
+mod common;
+use common::{block, moving_block};
+
+// Drops a 60x60 box of the given relative density into the middle of a
+// settled tank. Returns the height of the fluid surface and of the box
+// once it has settled
+fn drop_box(relative_density: f32) -> (f32, f32) {
+    let width = 30.0 * rest_spacing();
+    let mut universe = Universe::from_particles(width, 600.0, block(0.0, 0.0, 30, 20)).unwrap();
+    for _ in 0..200 {
+        universe.update(0.004).unwrap();
+    }
+
+    let surface = universe.get_particles().iter().map(|pi| pi.pos.y).fold(0.0, f32::max);
+    universe.add_box_body(0.5 * width, 150.0, 60.0, 60.0, relative_density).unwrap();
+    for _ in 0..500 {
+        universe.update(0.004).unwrap();
+    }
+
+    (surface, universe.get_bodies()[0].pos.y)
+}
+
+fn momentum(universe: &Universe) -> Vector2f {
+    universe.get_particles().iter().fold(Vector2f::new(0.0, 0.0), |p, pi| p + pi.mass * pi.vel)
+}
+
+#[test]
+fn light_bodies_float() {
+    let (surface, y) = drop_box(0.5);
+    assert!((y - surface).abs() < 60.0, "box at {}, surface at {}", y, surface);
+}
+
+#[test]
+fn heavy_bodies_sink() {
+    // It comes to rest on the bottom layer of particles
+    let (_, y) = drop_box(2.0);
+    assert!(y < 30.0 + 3.0 * rest_spacing(), "box at {}", y);
+}
+
+#[test]
+fn fluid_and_body_exchange_momentum() {
+    // Uniform flow past a disc in a periodic box, with no gravity. Whatever
+    // momentum the disc picks up is lost by the fluid
+    let n = 30;
+    let size = n as f32 * rest_spacing();
+    let center = Vector2f::new(0.5 * size, 0.5 * size);
+    let radius = 50.0;
+    let particles = moving_block(0.0, 0.0, n, n, |_| Vector2f::new(100.0, 0.0))
+        .into_iter()
+        .filter(|pi| (pi.pos - center).x.hypot((pi.pos - center).y) > radius + 0.5 * rest_spacing())
+        .collect();
+
+    let mut universe = Universe::from_particles(size, size, particles).unwrap();
+    universe.set_periodic(true, true);
+    universe.set_gravity(0.0, 0.0);
+    universe.add_disc_body(center.x, center.y, radius, 1.0).unwrap();
+
+    let before = momentum(&universe);
+    for _ in 0..20 {
+        universe.update(0.002).unwrap();
+    }
+
+    let body = &universe.get_bodies()[0];
+    let gained = body.mass * body.vel.x;
+    let lost = before.x - momentum(&universe).x;
+    assert!(gained > 0.01 * before.x, "{} {}", gained, before.x);
+    assert!((gained - lost).abs() < 0.1 * gained, "body gained {}, fluid lost {}", gained, lost);
+}
+
+#[test]
+fn bodies_need_a_size_and_a_density() {
+    let mut universe = Universe::from_particles(600.0, 600.0, block(0.0, 0.0, 10, 10)).unwrap();
+    assert!(universe.add_box_body(300.0, 300.0, 0.0, 60.0, 1.0).is_err());
+    assert!(universe.add_box_body(300.0, 300.0, -60.0, -60.0, 1.0).is_err());
+    assert!(universe.add_box_body(300.0, 300.0, 60.0, 60.0, 0.0).is_err());
+    assert!(universe.add_disc_body(300.0, 300.0, f32::INFINITY, 1.0).is_err());
+    assert!(universe.add_disc_body(300.0, 300.0, 30.0, f32::NAN).is_err());
+    assert!(universe.get_bodies().is_empty());
+
+    assert_eq!(universe.add_disc_body(300.0, 300.0, 30.0, 1.0).ok(), Some(0));
+}
@@ -0,0 +1,41 @@
+// Helpers shared by the integration tests. Each test only uses some of them
+#![allow(dead_code)]
+
+use spherro::{Universe, Particle};
+use spherro::util::Vector2f;
+use spherro::scenarios::rest_spacing;
+
+// nx by ny particles at the settled spacing, filling the rectangle with its
+// lower left corner at (x0, y0), with velocities given by `vel` as a function
+// of position
+pub fn moving_block<F>(x0: f32, y0: f32, nx: usize, ny: usize, vel: F) -> Vec<Particle>
+        where F: Fn(Vector2f) -> Vector2f {
+    let spacing = rest_spacing();
+    let mut particles = Vec::new();
+    for j in 0..ny {
+        for i in 0..nx {
+            let pos = Vector2f::new(x0 + (i as f32 + 0.5) * spacing, y0 + (j as f32 + 0.5) * spacing);
+            let mut pi = Particle::new(particles.len() as u32, pos, Universe::default_particle_mass());
+            pi.vel = vel(pos);
+            particles.push(pi);
+        }
+    }
+
+    particles
+}
+
+// The same block at rest
+pub fn block(x0: f32, y0: f32, nx: usize, ny: usize) -> Vec<Particle> {
+    moving_block(x0, y0, nx, ny, |_| Vector2f::new(0.0, 0.0))
+}
+
+// A block of fluid in the middle of a 700x700 tank, with velocities given by
+// `vel` as a function of position. Takes a tiny step so that the particles
+// have their densities and velocity gradients, without moving them noticeably
+pub fn universe_with<F>(vel: F) -> Universe where F: Fn(Vector2f) -> Vector2f {
+    let mut universe = Universe::from_particles(700.0, 700.0, moving_block(155.0, 155.0, 24, 24, vel)).unwrap();
+    universe.set_gravity(0.0, 0.0);
+    universe.set_viscosity(0.0);
+    universe.update(1e-6).unwrap();
+    universe
+}
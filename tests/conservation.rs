@@ -0,0 +1,145 @@
+extern crate spherro;
+
+use spherro::{Universe, Force};
+use spherro::util::Vector2f;
+use spherro::scenarios::{rest_spacing, DamBreak};
+
+mod common;
+use common::block;
+
+const H: f32 = 35.0;
+const GRAVITY: f32 = 10000.0;
+
+fn momentum(universe: &Universe) -> Vector2f {
+    universe.get_particles()
+            .iter()
+            .fold(Vector2f::new(0.0, 0.0), |p, pi| p + pi.mass * pi.vel)
+}
+
+// Highest particle in each 100 pixel wide column of the tank
+fn surface_profile(universe: &Universe) -> Vec<f32> {
+    let mut heights = vec![0.0f32; 7];
+    for pi in universe.get_particles().iter() {
+        let bin = ((pi.pos.x / 100.0) as usize).min(heights.len() - 1);
+        heights[bin] = heights[bin].max(pi.pos.y);
+    }
+
+    heights
+}
+
+fn center_of_mass_height(universe: &Universe) -> f32 {
+    let particles = universe.get_particles();
+    particles.iter().map(|pi| pi.pos.y).sum::<f32>() / particles.len() as f32
+}
+
+#[test]
+fn fluid_at_rest_keeps_its_shape() {
+    let mut universe = Universe::from_particles(700.0, 700.0, block(0.0, 0.0, 30, 10)).unwrap();
+    for _ in 0..600 {
+        universe.update(0.005).unwrap();
+    }
+
+    // The surface keeps sloshing a little, since particles that reach a wall
+    // are kicked back at a minimum speed. It should stay level and the fluid
+    // shouldn't compact or spread
+    let settled = surface_profile(&universe);
+    let level = settled.iter().sum::<f32>() / settled.len() as f32;
+    let height = center_of_mass_height(&universe);
+
+    for frame in 0..400 {
+        universe.update(0.005).unwrap();
+        if frame % 20 != 0 {
+            continue;
+        }
+
+        let profile = surface_profile(&universe);
+        for h in profile.iter() {
+            assert!((h - level).abs() < 0.75 * H, "surface {:?}, settled level {}", profile, level);
+        }
+
+        let h = center_of_mass_height(&universe);
+        assert!((h - height).abs() < 0.1 * H, "center of mass at {}, settled at {}", h, height);
+    }
+}
+
+#[test]
+fn dam_break_front_matches_martin_moyce() {
+    // Martin & Moyce(1952), collapse of a square column of width a. Surge
+    // front position Z = z/a against time T = t * sqrt(2g/a)
+    let n = 16;
+    let a = n as f32 * rest_spacing();
+    let mut universe = Universe::from_particles(1400.0, 700.0, block(0.0, 0.0, n, n)).unwrap();
+    let t_scale = (2.0 * GRAVITY / a).sqrt();
+
+    let front = |universe: &Universe| {
+        let x = universe.get_particles().iter().map(|pi| pi.pos.x).fold(0.0, f32::max);
+        (x + 0.5 * rest_spacing()) / a
+    };
+
+    let mut prev = (0.0, front(&universe));
+    for &(t, z) in DamBreak::MEASURED.iter() {
+        while universe.get_time() * t_scale < t {
+            prev = (universe.get_time() * t_scale, front(&universe));
+            universe.update(0.002).unwrap();
+        }
+
+        // Interpolate to the measured time
+        let next = (universe.get_time() * t_scale, front(&universe));
+        let z_sim = prev.1 + (next.1 - prev.1) * (t - prev.0) / (next.0 - prev.0);
+        assert!((z_sim - z).abs() < 0.2 * z, "front at {} at T = {}, measured {}", z_sim, t, z);
+    }
+}
+
+#[test]
+fn mass_is_conserved_without_spawns() {
+    let mut universe = Universe::from_particles(700.0, 700.0, block(0.0, 0.0, 16, 16)).unwrap();
+    universe.add_disc_body(450.0, 300.0, 30.0, 0.5).unwrap();
+    universe.add_force(Force::new(150.0, 100.0, 2e8, 100.0));
+
+    let count = universe.get_size();
+    let mass: f32 = universe.get_particles().iter().map(|pi| pi.mass).sum();
+    let mut ids: Vec<u32> = universe.get_particles().iter().map(|pi| pi.id).collect();
+    ids.sort();
+
+    // Large steps so that the stability monitor has to roll some of them back
+    universe.set_stability_monitor(true);
+    for _ in 0..100 {
+        universe.update(0.02).unwrap();
+        assert_eq!(universe.get_size(), count);
+        assert!((universe.get_stats().total_mass - mass).abs() < 1e-3 * mass);
+    }
+
+    let mut after: Vec<u32> = universe.get_particles().iter().map(|pi| pi.id).collect();
+    after.sort();
+    assert_eq!(ids, after);
+    assert!(!universe.get_trips().is_empty());
+}
+
+#[test]
+fn momentum_is_conserved_without_walls_or_gravity() {
+    // A blob drifting through the middle of a big tank, never reaching
+    // the walls
+    let mut particles = block(500.0, 500.0, 15, 15);
+    for (i, pi) in particles.iter_mut().enumerate() {
+        let phase = i as f32 * 0.7;
+        pi.vel = Vector2f::new(200.0 + 150.0 * phase.sin(), 100.0 * phase.cos());
+    }
+
+    let mut universe = Universe::from_particles(1400.0, 1400.0, particles).unwrap();
+    universe.set_gravity(0.0, 0.0);
+
+    let p0 = momentum(&universe);
+    for _ in 0..250 {
+        universe.update(0.002).unwrap();
+
+        let clear_of_walls = universe.get_particles().iter().all(|pi| {
+            pi.pos.x > H && pi.pos.x < 1400.0 - H && pi.pos.y > H && pi.pos.y < 1400.0 - H
+        });
+        assert!(clear_of_walls);
+
+        // Relative to the total amount of momentum flowing around
+        let scale: f32 = universe.get_particles().iter().map(|pi| pi.mass * (pi.vel.x.hypot(pi.vel.y))).sum();
+        let dp = momentum(&universe) - p0;
+        assert!(dp.x.hypot(dp.y) < 0.01 * scale, "momentum changed by {:?} out of {}", dp, scale);
+    }
+}
@@ -0,0 +1,178 @@
+extern crate spherro;
+
+use std::collections::HashSet;
+use spherro::{Universe, Particle, Removal, SpherroError};
+use spherro::util::Vector2f;
+use spherro::scenarios::rest_spacing;
+
+mod common;
+use common::block;
+
+fn universe(nx: usize, ny: usize) -> Universe {
+    let size = 20.0 * rest_spacing();
+    let mut universe = Universe::from_particles(size, size, block(0.0, 0.0, nx, ny)).unwrap();
+    universe.set_gravity(0.0, 0.0);
+    universe
+}
+
+// Every particle can be found by its id, and ids that were removed can't
+fn assert_ids_consistent(universe: &Universe, removed: &[u32]) {
+    for (idx, pi) in universe.get_particles().iter().enumerate() {
+        assert_eq!(universe.get_particle_index(pi.id), Some(idx), "id {}", pi.id);
+    }
+    for &id in removed.iter() {
+        assert_eq!(universe.get_particle_index(id), None, "id {}", id);
+    }
+}
+
+#[test]
+fn despawn_by_id_keeps_the_index_map() {
+    let mut universe = universe(10, 10);
+    let mut removed = Vec::new();
+
+    // The last particle, which swap_remove doesn't move anything into,
+    // then the first and some from the middle
+    for &id in [99, 0, 57, 1, 98, 42].iter() {
+        assert!(universe.despawn_by_id(id));
+        removed.push(id);
+        assert_eq!(universe.get_size(), 100 - removed.len());
+        assert_ids_consistent(&universe, &removed);
+    }
+
+    // Ids that are already gone, or never existed
+    assert!(!universe.despawn_by_id(57));
+    assert!(!universe.despawn_by_id(1000));
+    assert_eq!(universe.get_size(), 94);
+    assert_ids_consistent(&universe, &removed);
+}
+
+#[test]
+fn spawned_particles_get_fresh_ids() {
+    let mut universe = universe(10, 10);
+    assert!(universe.despawn_by_id(99));
+    universe.queue_spawn_particles(5, 200.0, 250.0);
+    universe.update(1e-4).unwrap();
+
+    // Removed ids are never handed out again
+    let particles = universe.get_particles();
+    assert_eq!(particles.len(), 104);
+    let mut ids: Vec<u32> = particles.iter().map(|pi| pi.id).collect();
+    ids.sort();
+    ids.dedup();
+    assert_eq!(ids.len(), 104);
+    assert!(particles[99..].iter().all(|pi| pi.id >= 100));
+    assert_ids_consistent(&universe, &[99]);
+
+    let id = particles[101].id;
+    assert!(universe.despawn_by_id(id));
+    assert_ids_consistent(&universe, &[99, id]);
+}
+
+// A row of particles with speed and density increasing with their index
+fn row(n: usize) -> Vec<Particle> {
+    (0..n).map(|i| {
+        let mut pi = Particle::new(i as u32, Vector2f::new(10.0 * i as f32, 5.0), 100.0);
+        pi.vel = Vector2f::new(0.0, i as f32);
+        pi.rho = 1.0 + i as f32;
+        pi
+    }).collect()
+}
+
+fn selected(removal: Removal, particles: &[Particle]) -> HashSet<usize> {
+    let indices = removal.select(particles).unwrap();
+    let set: HashSet<usize> = indices.iter().cloned().collect();
+    assert_eq!(set.len(), indices.len(), "{:?} picked a particle twice", removal);
+    set
+}
+
+#[test]
+fn fastest_removes_exactly_count() {
+    let particles = row(20);
+    for &count in [0, 1, 5, 19, 20].iter() {
+        let expected: HashSet<usize> = (20 - count..20).collect();
+        assert_eq!(selected(Removal::Fastest(count), &particles), expected, "count {}", count);
+    }
+}
+
+#[test]
+fn exploded_particles_are_the_fastest() {
+    let mut particles = row(20);
+    particles[3].vel = Vector2f::new(f32::NAN, 0.0);
+    particles[8].vel = Vector2f::new(0.0, f32::NAN);
+    let expected: HashSet<usize> = [3, 8, 19].iter().cloned().collect();
+    assert_eq!(selected(Removal::Fastest(3), &particles), expected);
+}
+
+#[test]
+fn oldest_and_densest() {
+    let mut particles = row(20);
+    particles.reverse();
+    let oldest: HashSet<usize> = [19, 18, 17, 16].iter().cloned().collect();
+    assert_eq!(selected(Removal::Oldest(4), &particles), oldest);
+
+    // Index 0 holds the particle with the highest density after reversing
+    let densest: HashSet<usize> = [0, 1].iter().cloned().collect();
+    assert_eq!(selected(Removal::Densest(2), &particles), densest);
+}
+
+#[test]
+fn random_removal_is_seeded() {
+    let particles = row(50);
+    let a = selected(Removal::Random(10, 7), &particles);
+    assert_eq!(a.len(), 10);
+    assert_eq!(a, selected(Removal::Random(10, 7), &particles));
+    assert_ne!(a, selected(Removal::Random(10, 8), &particles));
+    assert!(selected(Removal::Random(0, 7), &particles).is_empty());
+    assert_eq!(selected(Removal::Random(50, 7), &particles).len(), 50);
+}
+
+#[test]
+fn regions_include_their_boundary() {
+    let particles = row(20);
+
+    // Corners in either order, with x = 30 and x = 60 on the edges
+    let rect = Removal::InRect(Vector2f::new(60.0, 0.0), Vector2f::new(30.0, 5.0));
+    assert_eq!(selected(rect, &particles), (3..7).collect());
+    let rect = Removal::InRect(Vector2f::new(0.0, 6.0), Vector2f::new(200.0, 10.0));
+    assert!(selected(rect, &particles).is_empty());
+
+    let circle = Removal::InCircle(Vector2f::new(100.0, 5.0), 20.0);
+    assert_eq!(selected(circle, &particles), (8..13).collect());
+}
+
+#[test]
+fn too_many_removals_are_rejected() {
+    let particles = row(20);
+    let removals = [Removal::Fastest(21), Removal::Oldest(21), Removal::Densest(21), Removal::Random(21, 3)];
+    for removal in removals.iter() {
+        match removal.select(&particles) {
+            Err(SpherroError::NotEnoughParticles { requested: 21, available: 20 }) => (),
+            other => panic!("{:?} gave {:?}", removal, other),
+        }
+    }
+
+    // And nothing is removed from the universe. The web app tells this error
+    // apart from fatal ones by its kind
+    let mut universe = universe(4, 5);
+    assert_eq!(universe.despawn(&Removal::Fastest(21)).unwrap_err().kind(), "NotEnoughParticles");
+    assert_eq!(universe.get_size(), 20);
+    assert_eq!(universe.despawn(&Removal::Fastest(20)).unwrap(), 20);
+    assert_eq!(universe.get_size(), 0);
+}
+
+#[test]
+fn despawn_keeps_the_index_map() {
+    let mut universe = universe(10, 10);
+    let spacing = rest_spacing();
+
+    // The bottom two rows
+    let rect = Removal::InRect(Vector2f::new(0.0, 0.0), Vector2f::new(10.0 * spacing, 2.0 * spacing));
+    assert_eq!(universe.despawn(&rect).unwrap(), 20);
+    let removed: Vec<u32> = (0..20).collect();
+    assert_ids_consistent(&universe, &removed);
+
+    assert_eq!(universe.despawn(&Removal::Oldest(5)).unwrap(), 5);
+    let removed: Vec<u32> = (0..25).collect();
+    assert_ids_consistent(&universe, &removed);
+    assert_eq!(universe.get_size(), 75);
+}
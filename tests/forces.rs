@@ -0,0 +1,69 @@
+extern crate spherro;
+
+use spherro::{Universe, Force, ForceKind, Falloff};
+use spherro::util::Vector2f;
+use spherro::scenarios::rest_spacing;
+
+mod common;
+use common::block;
+
+const KINDS: [ForceKind; 5] = [
+    ForceKind::Radial, ForceKind::Jet, ForceKind::Vortex, ForceKind::Drag, ForceKind::Uniform,
+];
+
+const FALLOFFS: [Falloff; 4] = [
+    Falloff::Constant, Falloff::Linear, Falloff::InverseSquare, Falloff::Smooth,
+];
+
+fn force(x: f32, y: f32, power: f32, kind: ForceKind, falloff: Falloff) -> Force {
+    Force{
+        kind,
+        dir_x: 1.0,
+        dir_y: 0.5,
+        spread: 0.3,
+        ..Force::radial(x, y, power, 50.0, falloff)
+    }
+}
+
+fn is_finite(v: Vector2f) -> bool {
+    v.x.is_finite() && v.y.is_finite()
+}
+
+#[test]
+fn acceleration_is_finite_at_the_center() {
+    let center = Vector2f::new(100.0, 100.0);
+    for &kind in KINDS.iter() {
+        for &falloff in FALLOFFS.iter() {
+            let f = force(center.x, center.y, 2e6, kind, falloff);
+            for &vel in [Vector2f::new(0.0, 0.0), Vector2f::new(30.0, -20.0)].iter() {
+                let a = f.acceleration(center, vel);
+                assert!(is_finite(a), "{:?} {:?} {:?}", kind, falloff, a);
+            }
+
+            // And just off it
+            let a = f.acceleration(center + Vector2f::new(1e-6, 0.0), Vector2f::new(0.0, 0.0));
+            assert!(is_finite(a), "{:?} {:?} {:?}", kind, falloff, a);
+        }
+    }
+}
+
+#[test]
+fn particles_at_the_center_stay_finite() {
+    // A force centered exactly on a particle of a block for every
+    // combination. The inverse square falloff is capped at the center, but is
+    // still steep enough to trip the stability monitor with a large power
+    let particles = block(0.0, 0.0, 40, 30);
+    let mut universe = Universe::from_particles(40.0 * rest_spacing(), 30.0 * rest_spacing(), particles).unwrap();
+    universe.set_gravity(0.0, 0.0);
+    for (i, &kind) in KINDS.iter().enumerate() {
+        for (j, &falloff) in FALLOFFS.iter().enumerate() {
+            let pos = universe.get_particles()[(6 * j + 5) * 40 + 7 * i + 5].pos;
+            universe.add_force(force(pos.x, pos.y, 1.0, kind, falloff));
+        }
+    }
+    universe.update(1e-3).unwrap();
+
+    for pi in universe.get_particles().iter() {
+        assert!(is_finite(pi.pos) && is_finite(pi.vel), "{:?}", pi);
+    }
+}
@@ -0,0 +1,84 @@
+extern crate spherro;
+
+use spherro::{Universe, Particle, RheologyPreset};
+use spherro::util::Vector2f;
+
+// A little looser than the settled spacing, so that the heap doesn't start
+// out compressed
+const SPACING: f32 = 17.5;
+
+// A heap with 60 degree sides in the middle of a 1000 wide tank, for phase
+// 0 set up by `setup`
+fn heap<F>(setup: F) -> Universe where F: Fn(&mut Universe) {
+    let mut particles = Vec::new();
+    for j in 0..14 {
+        let half = 14 - j;
+        for i in -half..half {
+            let pos = Vector2f::new(500.0 + (i as f32 + 0.5) * SPACING, (j as f32 + 0.5) * SPACING);
+            particles.push(Particle::new(particles.len() as u32, pos, Universe::default_particle_mass()));
+        }
+    }
+
+    let mut universe = Universe::from_particles(1000.0, 500.0, particles).unwrap();
+    setup(&mut universe);
+    universe
+}
+
+// Angle of the heap's sides in degrees after it has slumped for 2s, from its
+// height over its half width. The few particles furthest out are left out
+fn repose_angle(mut universe: Universe) -> f32 {
+    for _ in 0..400 {
+        universe.update(0.005).unwrap();
+    }
+
+    let particles = universe.get_particles();
+    let height = particles.iter()
+        .filter(|pi| (pi.pos.x - 500.0).abs() < 2.0 * SPACING)
+        .map(|pi| pi.pos.y)
+        .fold(0.0, f32::max);
+
+    let mut widths: Vec<f32> = particles.iter().map(|pi| (pi.pos.x - 500.0).abs()).collect();
+    widths.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    let width = widths[(0.97 * widths.len() as f32) as usize];
+
+    (height / width).atan().to_degrees()
+}
+
+#[test]
+fn sand_piles_up() {
+    let water = repose_angle(heap(|_| ()));
+    let sand = repose_angle(heap(|u| u.set_rheology_preset(0, RheologyPreset::Sand)));
+
+    // The heap is shallower than the 30 degree friction angle, see
+    // `Rheology::DruckerPrager`. With this heap its slope comes out at about
+    // 0.45 tan(phi), measured at friction angles of 20, 30 and 40 degrees
+    // (9.5, 15.2 and 20.2 degrees), so about 14.6 degrees for sand. The water
+    // spreads over the floor
+    let expected = (0.45 * 30f32.to_radians().tan()).atan().to_degrees();
+    assert!(water < 8.0, "{}", water);
+    assert!((sand - expected).abs() < 2.0, "{} vs {}", sand, expected);
+}
+
+#[test]
+fn more_friction_piles_up_steeper() {
+    let angles: Vec<f32> = [20.0, 30.0, 40.0].iter().map(|&phi| {
+        repose_angle(heap(|u| u.set_drucker_prager(0, phi, 0.0)))
+    }).collect();
+
+    assert!(angles[1] > angles[0] + 3.0 && angles[2] > angles[1] + 3.0, "{:?}", angles);
+}
+
+#[test]
+fn only_granular_phases_carry_stress() {
+    // The left half is sand, the right half water
+    let mut universe = heap(|u| u.set_rheology_preset(1, RheologyPreset::Sand));
+    universe.set_phase_in_rect(0.0, 0.0, 500.0, 500.0, 1);
+    for _ in 0..40 {
+        universe.update(0.005).unwrap();
+    }
+
+    let stressed = |pi: &&Particle| pi.stress.iter().any(|&s| s != 0.0);
+    let particles = universe.get_particles();
+    assert!(particles.iter().filter(|pi| pi.phase == 0).all(|pi| !stressed(&pi)));
+    assert!(particles.iter().filter(|pi| pi.phase == 1).filter(stressed).count() > 50);
+}
@@ -0,0 +1,73 @@
+extern crate spherro;
+
+use spherro::{Universe, Config};
+use spherro::util::Vector2f;
+
+// Fits y = a*x + b through the free surface particles and returns atan(a)
+fn surface_angle(universe: &Universe) -> f32 {
+    let surface: Vec<(f32, f32)> = universe.get_particles()
+                                           .iter()
+                                           .filter(|pi| pi.is_surface)
+                                           .map(|pi| (pi.pos.x, pi.pos.y))
+                                           .collect();
+    assert!(surface.len() > 5);
+
+    let n = surface.len() as f32;
+    let mx = surface.iter().map(|p| p.0).sum::<f32>() / n;
+    let my = surface.iter().map(|p| p.1).sum::<f32>() / n;
+    let sxy: f32 = surface.iter().map(|p| (p.0 - mx) * (p.1 - my)).sum();
+    let sxx: f32 = surface.iter().map(|p| (p.0 - mx) * (p.0 - mx)).sum();
+
+    (sxy / sxx).atan()
+}
+
+fn settle_tilted(angle: f32) -> f32 {
+    let config = Config::new(0.5, 0.5, 25, 10);
+    let mut universe = Universe::new(700.0, 700.0, &config).unwrap();
+    universe.set_gravity_angle(angle);
+
+    for _ in 0..800 {
+        universe.update(0.005).unwrap();
+    }
+    assert!(!universe.is_unstable());
+
+    // Average over a few frames to smooth out ripples
+    let mut total = 0.0;
+    for _ in 0..10 {
+        for _ in 0..20 {
+            universe.update(0.005).unwrap();
+        }
+        total += surface_angle(&universe);
+    }
+
+    total / 10.0
+}
+
+#[test]
+fn level_tank_has_level_surface() {
+    let angle = settle_tilted(0.0);
+    assert!(angle.abs() < 0.05, "surface angle {}", angle);
+}
+
+#[test]
+fn tilted_tank_surface_follows_tilt() {
+    // Gravity pointing 0.2 radians to the right of straight down is the
+    // same as tilting the tank 0.2 radians clockwise, so the free surface
+    // should rise towards the right wall at 0.2 radians
+    let theta = 0.2;
+    let angle = settle_tilted(theta);
+    assert!((angle - theta).abs() < 0.05, "surface angle {}, expected {}", angle, theta);
+}
+
+#[test]
+fn universes_move_between_threads() {
+    let config = Config::new(0.4, 0.8, 20, 10);
+    let mut universe = Universe::new(500.0, 500.0, &config).unwrap();
+    universe.set_body_force(|_, t| Vector2f::new(100.0 * t, 0.0)).unwrap();
+
+    let universe = std::thread::spawn(move || {
+        universe.update(0.005).unwrap();
+        universe
+    }).join().unwrap();
+    assert_eq!(universe.get_time(), 0.005);
+}
@@ -0,0 +1,117 @@
+extern crate spherro;
+
+use spherro::{Universe, Wall};
+use spherro::util::Vector2f;
+use spherro::scenarios::rest_spacing;
+
+mod common;
+use common::block;
+
+
+fn heat(universe: &Universe) -> f32 {
+    universe.get_particles().iter().map(|pi| pi.mass * pi.temperature).sum()
+}
+
+#[test]
+fn conduction_spreads_heat_without_creating_it() {
+    // The left half starts hot
+    let (nx, ny) = (20, 10);
+    let mut particles = block(0.0, 0.0, nx, ny);
+    for pi in particles.iter_mut() {
+        pi.temperature = if pi.pos.x < 0.5 * nx as f32 * rest_spacing() { 1.0 } else { 0.0 };
+    }
+
+    let mut universe = Universe::from_particles(nx as f32 * rest_spacing(), ny as f32 * rest_spacing(), particles).unwrap();
+    universe.set_periodic(true, true);
+    universe.set_gravity(0.0, 0.0);
+    universe.set_conductivity(5.0);
+
+    let total = heat(&universe);
+    let spread = |universe: &Universe| {
+        let t = universe.get_particles().iter().map(|pi| pi.temperature);
+        t.clone().fold(f32::MIN, f32::max) - t.fold(f32::MAX, f32::min)
+    };
+
+    let mut prev = spread(&universe);
+    for _ in 0..10 {
+        for _ in 0..20 {
+            universe.update(0.002).unwrap();
+        }
+
+        assert!((heat(&universe) - total).abs() < 1e-3 * total);
+
+        let s = spread(&universe);
+        assert!(s < prev, "temperature spread went from {} to {}", prev, s);
+        prev = s;
+    }
+}
+
+// Average height of a hot blob of fluid, released in the lower middle of a
+// tank at rest, after `time`
+fn hot_blob_height(beta: f32, time: f32) -> (f32, f32) {
+    let (nx, ny) = (20, 14);
+    let center = Vector2f::new(0.5 * nx as f32 * rest_spacing(), 3.5 * rest_spacing());
+    let mut particles = block(0.0, 0.0, nx, ny);
+    for pi in particles.iter_mut() {
+        let d = pi.pos - center;
+        if d.x.hypot(d.y) < 3.0 * rest_spacing() {
+            pi.temperature = 1.0;
+        }
+    }
+
+    let height = |universe: &Universe| {
+        let hot: Vec<f32> = universe.get_particles().iter()
+                                    .filter(|pi| pi.temperature > 0.5)
+                                    .map(|pi| pi.pos.y)
+                                    .collect();
+        hot.iter().sum::<f32>() / hot.len() as f32
+    };
+
+    let mut universe = Universe::from_particles(nx as f32 * rest_spacing(), 2.0 * ny as f32 * rest_spacing(), particles).unwrap();
+    universe.set_thermal_expansion(beta);
+    let before = height(&universe);
+    while universe.get_time() < time {
+        universe.update(0.002).unwrap();
+    }
+
+    (before, height(&universe))
+}
+
+#[test]
+fn hot_fluid_rises() {
+    let (before, after) = hot_blob_height(0.3, 1.0);
+    assert!(after - before > 2.0 * rest_spacing(), "hot fluid went from {} to {}", before, after);
+
+    // Without buoyancy it stays put
+    let (before, after) = hot_blob_height(0.0, 1.0);
+    assert!((after - before).abs() < rest_spacing(), "fluid went from {} to {}", before, after);
+}
+
+#[test]
+fn heat_wall_warms_the_fluid_next_to_it() {
+    let (nx, ny) = (20, 10);
+    let mut universe = Universe::from_particles(nx as f32 * rest_spacing(), 2.0 * ny as f32 * rest_spacing(), block(0.0, 0.0, nx, ny)).unwrap();
+    universe.add_heat_wall(Wall::Bottom, 1.0, 20.0);
+    universe.add_heat_wall(Wall::Left, -1.0, 20.0);
+    for _ in 0..100 {
+        universe.update(0.002).unwrap();
+    }
+
+    // No conduction, so the fluid away from the walls is untouched. Particles
+    // kicked off the right wall can carry heat up along it
+    let width = nx as f32 * rest_spacing();
+    for pi in universe.get_particles().iter() {
+        if pi.pos.x > 0.5 * width && pi.pos.x < width - 70.0 && pi.pos.y > 0.5 * ny as f32 * rest_spacing() {
+            assert_eq!(pi.temperature, 0.0, "{:?}", pi);
+        }
+    }
+
+    let particles = universe.get_particles();
+    let floor = particles.iter().filter(|pi| pi.pos.y < 0.5 * rest_spacing() && pi.pos.x > 0.5 * nx as f32 * rest_spacing());
+    assert!(floor.clone().count() > 0);
+    assert!(floor.clone().all(|pi| pi.temperature > 0.5), "{:?}", floor.map(|pi| pi.temperature).collect::<Vec<_>>());
+
+    let wall = particles.iter().filter(|pi| pi.pos.x < 0.5 * rest_spacing() && pi.pos.y > 0.5 * ny as f32 * rest_spacing());
+    assert!(wall.clone().count() > 0);
+    assert!(wall.clone().all(|pi| pi.temperature < -0.5), "{:?}", wall.map(|pi| pi.temperature).collect::<Vec<_>>());
+}
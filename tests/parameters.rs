@@ -0,0 +1,117 @@
+extern crate spherro;
+
+use spherro::{Universe, Config, Particle, Recording, Replayer};
+use spherro::util::Vector2f;
+
+fn column() -> Universe {
+    let config = Config::new(0.4, 0.8, 30, 20);
+    Universe::new(600.0, 600.0, &config).unwrap()
+}
+
+// Average density error over the second half of `steps`
+fn settle(universe: &mut Universe, steps: usize) -> f32 {
+    let mut error = 0.0;
+    for i in 0..steps {
+        universe.update(0.005).unwrap();
+        if i >= steps / 2 {
+            error += universe.get_stats().density_error_avg;
+        }
+    }
+
+    error / (steps - steps / 2) as f32
+}
+
+#[test]
+fn defaults_match_the_constants() {
+    let universe = column();
+    assert_eq!(universe.get_particle_mass(), Universe::default_particle_mass());
+    assert_eq!(universe.get_sound_speed(), Universe::default_sound_speed());
+    assert_eq!(universe.get_smoothing_length(), Universe::default_smoothing_length());
+    assert_eq!(universe.get_stiffness(), 10.0);
+}
+
+#[test]
+fn stiffer_fluid_compresses_less() {
+    let soft = settle(&mut column(), 400);
+    let mut stiff = column();
+    stiff.set_stiffness(40.0).unwrap();
+    let c = stiff.get_sound_speed();
+    let stiff = settle(&mut stiff, 400);
+
+    assert!((c / Universe::default_sound_speed() - 2.0).abs() < 1e-4, "{}", c);
+    assert!(stiff < soft - 0.05, "{} {}", soft, stiff);
+}
+
+#[test]
+fn particle_mass_scales_every_particle() {
+    let particles = (0..10).map(|i| {
+        let mass = if i == 0 { 200.0 } else { 100.0 };
+        Particle::new(i, Vector2f::new(100.0 + 16.0 * i as f32, 20.0), mass)
+    }).collect();
+    let mut universe = Universe::from_particles(600.0, 600.0, particles).unwrap();
+    universe.set_particle_mass(50.0).unwrap();
+
+    let particles = universe.get_particles();
+    assert_eq!(particles[0].mass, 100.0);
+    assert!(particles[1..].iter().all(|pi| pi.mass == 50.0));
+
+    universe.queue_spawn_particles(3, 300.0, 500.0);
+    universe.update(0.005).unwrap();
+    assert!(universe.get_particles().iter().rev().take(3).all(|pi| pi.mass == 50.0));
+}
+
+#[test]
+fn settles_with_other_smoothing_lengths() {
+    let top = |u: &Universe| u.get_particles().iter().map(|pi| pi.pos.y).fold(0.0, f32::max);
+    let mut default = column();
+    settle(&mut default, 400);
+
+    for &h in [30.0, 40.0].iter() {
+        let mut universe = column();
+        universe.set_smoothing_length(h).unwrap();
+        let error = settle(&mut universe, 400);
+        assert!(error < 0.5, "h={} {}", h, error);
+        assert!((top(&universe) - top(&default)).abs() < 50.0, "h={} {} {}", h, top(&universe), top(&default));
+    }
+}
+
+#[test]
+fn bad_parameters_are_rejected() {
+    let mut universe = column();
+    assert!(universe.set_smoothing_length(0.0).is_err());
+    assert!(universe.set_particle_mass(-1.0).is_err());
+    assert!(universe.set_stiffness(f32::NAN).is_err());
+    assert!(universe.set_stiffness(f32::INFINITY).is_err());
+    assert_eq!(universe.get_smoothing_length(), Universe::default_smoothing_length());
+    assert_eq!(universe.get_particle_mass(), 100.0);
+    assert_eq!(universe.get_stiffness(), 10.0);
+}
+
+#[test]
+fn parameters_are_replayed() {
+    let mut universe = column();
+    universe.start_recording().unwrap();
+    for i in 0..60 {
+        match i {
+            10 => universe.set_smoothing_length(30.0).unwrap(),
+            20 => universe.set_particle_mass(80.0).unwrap(),
+            30 => universe.set_stiffness(20.0).unwrap(),
+            _ => (),
+        }
+        universe.update(0.005).unwrap();
+    }
+
+    let text = universe.stop_recording().unwrap();
+    let recording = Recording::parse(&text).unwrap();
+    assert_eq!(recording.to_text(), text);
+    let mut replayed = recording.universe().unwrap();
+    Replayer::new(recording).finish(&mut replayed).unwrap();
+
+    assert_eq!(replayed.get_smoothing_length(), 30.0);
+    assert_eq!(replayed.get_particle_mass(), 80.0);
+    assert_eq!(replayed.get_stiffness(), 20.0);
+    for (a, b) in universe.get_particles().iter().zip(replayed.get_particles().iter()) {
+        assert_eq!(a.pos, b.pos);
+        assert_eq!(a.mass, b.mass);
+    }
+}
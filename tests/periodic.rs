@@ -0,0 +1,73 @@
+extern crate spherro;
+
+use spherro::Universe;
+use spherro::util::Vector2f;
+use spherro::scenarios::rest_spacing;
+
+mod common;
+use common::moving_block;
+
+// Densities of an nx by ny lattice that wraps around both axes
+fn lattice_densities(nx: usize, ny: usize) -> Vec<f32> {
+    let mut universe = Universe::from_particles(nx as f32 * rest_spacing(), ny as f32 * rest_spacing(),
+                                                moving_block(0.0, 0.0, nx, ny, |_| Vector2f::new(0.0, 0.0))).unwrap();
+    universe.set_periodic(true, true);
+    universe.set_gravity(0.0, 0.0);
+    universe.update(1e-4).unwrap();
+    universe.get_particles().iter().map(|pi| pi.rho).collect()
+}
+
+#[test]
+fn periodic_lattice_has_no_edges() {
+    // Every particle of a lattice that wraps around both axes sees the same
+    // neighbourhood, so the densities only match if neighbours are found
+    // across the seams at their closest images
+    let (nx, ny) = (12, 10);
+    let mut universe = Universe::from_particles(nx as f32 * rest_spacing(), ny as f32 * rest_spacing(),
+                                                moving_block(0.0, 0.0, nx, ny, |_| Vector2f::new(0.0, 0.0))).unwrap();
+    universe.set_periodic(true, true);
+    universe.set_gravity(0.0, 0.0);
+    universe.update(1e-4).unwrap();
+
+    let particles = universe.get_particles();
+    let rho0 = particles[0].rho;
+    for pi in particles.iter() {
+        assert!((pi.rho - rho0).abs() < 1e-3 * rho0, "density {} at {:?}, expected {}", pi.rho, pi.pos, rho0);
+        assert!(!pi.is_surface);
+    }
+}
+
+#[test]
+fn short_periodic_axis_counts_neighbours_once() {
+    // 9 and 10 rows are only a little more than twice the smoothing radius,
+    // so the search ranges on either side of the seam share cells
+    let reference = lattice_densities(16, 16)[0];
+    for &ny in [9, 10].iter() {
+        for &rho in lattice_densities(16, ny).iter() {
+            assert!((rho - reference).abs() < 1e-5 * reference, "{} rows: density {}, expected {}", ny, rho, reference);
+        }
+    }
+}
+
+#[test]
+fn particles_wrap_around_periodic_axis() {
+    let (nx, ny) = (12, 6);
+    let (width, height) = (nx as f32 * rest_spacing(), 700.0);
+    let speed = 400.0;
+    let mut universe = Universe::from_particles(width, height, moving_block(0.0, 0.0, nx, ny, |_| Vector2f::new(speed, 0.0))).unwrap();
+    universe.set_periodic(true, false);
+
+    let mass: f32 = universe.get_particles().iter().map(|pi| pi.mass).sum();
+    for _ in 0..500 {
+        universe.update(0.002).unwrap();
+        for pi in universe.get_particles().iter() {
+            assert!(pi.pos.x >= 0.0 && pi.pos.x < width, "particle {} left the domain at {:?}", pi.id, pi.pos);
+        }
+    }
+
+    // One second in, the fluid has gone around the channel about two times
+    // without hitting anything that slows it down along x
+    let particles = universe.get_particles();
+    let p: f32 = particles.iter().map(|pi| pi.mass * pi.vel.x).sum();
+    assert!((p / mass - speed).abs() < 0.01 * speed, "average speed {}, started at {}", p / mass, speed);
+}
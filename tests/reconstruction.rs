@@ -0,0 +1,175 @@
+extern crate spherro;
+
+use std::collections::HashMap;
+use spherro::{Universe, Particle, SurfaceReconstructor, FieldKernel};
+use spherro::util::Vector2f;
+use spherro::scenarios::rest_spacing;
+
+mod common;
+use common::block;
+
+
+fn segments(reconstructor: &SurfaceReconstructor) -> Vec<(Vector2f, Vector2f)> {
+    reconstructor.get_segments()
+                 .chunks(4)
+                 .map(|s| (Vector2f::new(s[0], s[1]), Vector2f::new(s[2], s[3])))
+                 .collect()
+}
+
+// Every end point has to be the start of exactly one other segment
+fn is_closed(segments: &[(Vector2f, Vector2f)]) -> bool {
+    let key = |p: Vector2f| (p.x.to_bits(), p.y.to_bits());
+    let mut starts: HashMap<(u32, u32), i32> = HashMap::new();
+    for &(a, b) in segments.iter() {
+        *starts.entry(key(a)).or_insert(0) += 1;
+        *starts.entry(key(b)).or_insert(0) -= 1;
+    }
+
+    starts.values().all(|&n| n == 0)
+}
+
+// Signed area enclosed by the segments, positive counter-clockwise
+fn contour_area(segments: &[(Vector2f, Vector2f)]) -> f32 {
+    segments.iter().map(|&(a, b)| 0.5 * (a.x * b.y - b.x * a.y)).sum()
+}
+
+fn fill_area(reconstructor: &SurfaceReconstructor) -> Vec<f32> {
+    reconstructor.get_triangles()
+                 .chunks(6)
+                 .map(|t| 0.5 * ((t[2] - t[0]) * (t[5] - t[1]) - (t[4] - t[0]) * (t[3] - t[1])))
+                 .collect()
+}
+
+#[test]
+fn block_has_a_closed_contour_around_it() {
+    let (nx, ny) = (16, 12);
+    let universe = Universe::from_particles(600.0, 600.0, block(150.0, 150.0, nx, ny)).unwrap();
+    let area = (nx * ny) as f32 * rest_spacing() * rest_spacing();
+
+    for &kernel in [FieldKernel::CubicSpline, FieldKernel::Poly6].iter() {
+        for &anisotropic in [false, true].iter() {
+            let mut reconstructor = SurfaceReconstructor::new(5.0, 0.5).unwrap();
+            reconstructor.set_kernel(kernel);
+            reconstructor.set_anisotropic(anisotropic);
+            reconstructor.update(&universe);
+
+            let segments = segments(&reconstructor);
+            assert!(!segments.is_empty());
+            assert!(is_closed(&segments), "{:?} anisotropic {}", kernel, anisotropic);
+
+            let enclosed = contour_area(&segments);
+            assert!((enclosed - area).abs() < 0.05 * area, "{:?} anisotropic {}: {} vs {}", kernel, anisotropic, enclosed, area);
+
+            // The fill covers exactly what the contour encloses
+            let triangles = fill_area(&reconstructor);
+            assert!(triangles.iter().all(|&a| a >= 0.0));
+            let filled: f32 = triangles.iter().sum();
+            assert!((filled - enclosed).abs() < 1e-3 * area, "filled {}, enclosed {}", filled, enclosed);
+        }
+    }
+}
+
+#[test]
+fn contour_follows_the_universe_parameters() {
+    let (nx, ny) = (16, 12);
+    let area = (nx * ny) as f32 * rest_spacing() * rest_spacing();
+
+    for &(mass, h) in [(25.0, 35.0), (100.0, 25.0), (400.0, 50.0)].iter() {
+        let mut universe = Universe::from_particles(600.0, 600.0, block(150.0, 150.0, nx, ny)).unwrap();
+        universe.set_particle_mass(mass).unwrap();
+        universe.set_smoothing_length(h).unwrap();
+
+        let mut reconstructor = SurfaceReconstructor::new(5.0, 0.5).unwrap();
+        reconstructor.update(&universe);
+
+        let segments = segments(&reconstructor);
+        assert!(!segments.is_empty(), "mass {} h {}", mass, h);
+        assert!(is_closed(&segments), "mass {} h {}", mass, h);
+
+        // Wider kernels round off more of the corners
+        let enclosed = contour_area(&segments);
+        assert!((enclosed - area).abs() < 0.1 * area, "mass {} h {}: {} vs {}", mass, h, enclosed, area);
+    }
+}
+
+#[test]
+fn iso_value_moves_the_surface() {
+    let universe = Universe::from_particles(600.0, 600.0, block(150.0, 150.0, 16, 12)).unwrap();
+    let mut reconstructor = SurfaceReconstructor::new(5.0, 0.2).unwrap();
+    reconstructor.update(&universe);
+    let loose = contour_area(&segments(&reconstructor));
+
+    reconstructor.set_iso(0.8);
+    reconstructor.update(&universe);
+    let tight = contour_area(&segments(&reconstructor));
+
+    assert!(tight < loose, "{} at 0.8, {} at 0.2", tight, loose);
+}
+
+#[test]
+fn periodic_field_wraps_around() {
+    // The same block, once in the middle and once across the seam. With
+    // 10 unit cells, the shift is a whole number of cells
+    let (nx, ny) = (12, 8);
+    let width = 400.0;
+    let shift = 250.0;
+    let middle = block(100.0, 100.0, nx, ny);
+    let mut across = middle.clone();
+    for pi in across.iter_mut() {
+        pi.pos.x = (pi.pos.x + shift) % width;
+    }
+
+    let field = |particles: Vec<Particle>| {
+        let mut universe = Universe::from_particles(width, 400.0, particles).unwrap();
+        universe.set_periodic(true, false);
+        let mut reconstructor = SurfaceReconstructor::new(10.0, 0.5).unwrap();
+        reconstructor.update(&universe);
+        (reconstructor.get_field().to_vec(), reconstructor.field_cols())
+    };
+
+    let (a, cols) = field(middle);
+    let (b, _) = field(across);
+    let cells = cols - 1;
+    let offset = (shift / 10.0) as usize;
+    for (n, &value) in a.iter().enumerate() {
+        let (x, y) = (n % cols, n / cols);
+        let wrapped = (x + offset) % cells;
+        assert!((b[y * cols + wrapped] - value).abs() < 1e-4, "node ({}, {})", x, y);
+    }
+}
+
+#[test]
+fn resolution_must_be_positive() {
+    assert!(SurfaceReconstructor::new(0.0, 0.5).is_err());
+    assert!(SurfaceReconstructor::new(f32::NAN, 0.5).is_err());
+
+    let mut reconstructor = SurfaceReconstructor::new(5.0, 0.5).unwrap();
+    assert!(reconstructor.set_resolution(-1.0).is_err());
+}
+
+#[test]
+fn contours_stay_closed_in_a_splashing_tank() {
+    // Lots of droplets and thin sheets, and so lots of saddle cells
+    let mut universe = Universe::from_particles(700.0, 700.0, block(0.0, 0.0, 20, 20)).unwrap();
+    for _ in 0..150 {
+        universe.update(0.004).unwrap();
+    }
+
+    let mut reconstructor = SurfaceReconstructor::new(4.0, 0.5).unwrap();
+    for &anisotropic in [false, true].iter() {
+        reconstructor.set_anisotropic(anisotropic);
+        reconstructor.update(&universe);
+
+        // Contours that run into the walls are left open there, so only the
+        // end points inside the tank have to match up
+        let key = |p: Vector2f| (p.x.to_bits(), p.y.to_bits());
+        let inner = |p: Vector2f| p.x > 0.0 && p.x < 700.0 && p.y > 0.0 && p.y < 700.0;
+        let mut starts: HashMap<(u32, u32), i32> = HashMap::new();
+        for &(a, b) in segments(&reconstructor).iter() {
+            if inner(a) { *starts.entry(key(a)).or_insert(0) += 1; }
+            if inner(b) { *starts.entry(key(b)).or_insert(0) -= 1; }
+        }
+        assert!(starts.values().all(|&n| n == 0), "anisotropic {}", anisotropic);
+        assert!(fill_area(&reconstructor).iter().all(|&a| a >= 0.0));
+    }
+}
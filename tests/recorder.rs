@@ -0,0 +1,238 @@
+extern crate spherro;
+
+use spherro::{Universe, Config, Force, Falloff, Recording, Replayer, SpherroError, Input, Removal, Rheology,
+              Viscosity};
+use spherro::util::Vector2f;
+
+fn recorded_universe() -> Universe {
+    let config = Config::new(0.4, 0.8, 20, 10);
+    let mut universe = Universe::new(500.0, 500.0, &config).unwrap();
+    universe.start_recording().unwrap();
+    universe
+}
+
+// Something like the browser does: drags a force around, spawns and
+// despawns particles, and changes some settings along the way
+fn play(universe: &mut Universe) {
+    universe.set_seed(7);
+    for i in 0..120 {
+        universe.clear_forces();
+        if i % 40 < 20 {
+            let x = 100.0 + 5.0 * i as f32;
+            universe.add_force(Force::radial(x, 100.0, 2e8, 100.0, Falloff::Smooth));
+        }
+        if i % 10 == 0 {
+            universe.queue_spawn_particles(5, 250.0, 450.0);
+        }
+        if i == 60 {
+            universe.queue_despawn_particles(3);
+            universe.set_gravity_angle(0.3);
+            universe.add_disc_body(350.0, 300.0, 30.0, 0.5).unwrap();
+        }
+
+        universe.update(if i < 100 { 0.005 } else { 0.002 }).unwrap();
+    }
+}
+
+#[test]
+fn replays_the_same_session() {
+    let mut universe = recorded_universe();
+    play(&mut universe);
+    let text = universe.get_recording().unwrap();
+
+    let recording = Recording::parse(&text).unwrap();
+    assert_eq!(recording.steps(), 120);
+    let mut replayed = recording.universe().unwrap();
+    Replayer::new(recording).finish(&mut replayed).unwrap();
+
+    assert_eq!(replayed.get_size(), universe.get_size());
+    for (a, b) in universe.get_particles().iter().zip(replayed.get_particles().iter()) {
+        assert_eq!(a.id, b.id);
+        assert_eq!(a.pos, b.pos);
+        assert_eq!(a.vel, b.vel);
+    }
+}
+
+#[test]
+fn dropped_despawns_dont_stop_a_replay() {
+    // The browser carries on after these, since the step itself went through
+    let mut universe = recorded_universe();
+    for i in 0..20 {
+        if i == 5 {
+            universe.queue_despawn_particles(10000);
+        }
+        let result = universe.update(0.005);
+        assert_eq!(result.is_err(), i == 5);
+    }
+
+    let recording = Recording::parse(&universe.get_recording().unwrap()).unwrap();
+    let mut replayed = recording.universe().unwrap();
+    let mut replayer = Replayer::new(recording);
+    replayer.finish(&mut replayed).unwrap();
+
+    assert_eq!(replayer.step(), 20);
+    assert_eq!(replayer.dropped(), &[(6, SpherroError::NotEnoughParticles { requested: 10000, available: 200 })][..]);
+    for (a, b) in universe.get_particles().iter().zip(replayed.get_particles().iter()) {
+        assert_eq!(a.pos, b.pos);
+    }
+}
+
+#[test]
+fn recordings_round_trip() {
+    let mut universe = recorded_universe();
+    play(&mut universe);
+    universe.set_body_force_grid(1, 1, vec![0.1, 1e-7]).unwrap();
+    universe.set_stability_monitor(false);
+    for _ in 0..20 {
+        universe.update(0.002).unwrap();
+    }
+
+    let text = universe.stop_recording().unwrap();
+    assert!(!universe.is_recording());
+    assert_eq!(Recording::parse(&text).unwrap().to_text(), text);
+
+    // Runs of updates with the same dt share a line
+    assert_eq!(text.lines().filter(|l| l.contains(" update ")).count(), 120 + 1);
+    assert_eq!(text.lines().last(), Some("120 update 0.002 20"));
+}
+
+#[test]
+fn native_calls_are_replayed() {
+    let mut universe = recorded_universe();
+    universe.set_viscosity_model(Viscosity::Monaghan { alpha: 0.3, beta: 0.1 });
+    universe.set_rheology(1, Rheology::Cross { nu_0: 900.0, nu_inf: 20.0, lambda: 0.5, n: 0.8 });
+    universe.set_phase_in_rect(0.0, 0.0, 250.0, 500.0, 1);
+    for i in 0..40 {
+        if i == 10 {
+            universe.despawn(&Removal::Random(4, 1 << 40)).unwrap();
+            universe.despawn(&Removal::InCircle(Vector2f::new(100.0, 50.0), 30.0)).unwrap();
+        }
+        universe.update(0.005).unwrap();
+    }
+
+    let text = universe.stop_recording().unwrap();
+    let recording = Recording::parse(&text).unwrap();
+    assert_eq!(recording.to_text(), text);
+    let mut replayed = recording.universe().unwrap();
+    Replayer::new(recording).finish(&mut replayed).unwrap();
+
+    assert_eq!(replayed.get_rheology(1), universe.get_rheology(1));
+    assert_eq!(replayed.get_viscosity_model(), universe.get_viscosity_model());
+    assert_eq!(replayed.get_size(), universe.get_size());
+    for (a, b) in universe.get_particles().iter().zip(replayed.get_particles().iter()) {
+        assert_eq!(a.id, b.id);
+        assert_eq!(a.pos, b.pos);
+    }
+}
+
+#[test]
+fn native_calls_round_trip() {
+    let mut universe = recorded_universe();
+    let removals = [
+        Removal::Fastest(3), Removal::Oldest(2), Removal::Random(1, u64::MAX), Removal::Densest(1),
+        Removal::InRect(Vector2f::new(0.0, 0.0), Vector2f::new(0.5, 0.25)),
+        Removal::InCircle(Vector2f::new(-1.0, 1e-7), 2.5),
+    ];
+    for removal in removals.iter() {
+        universe.despawn(removal).unwrap();
+    }
+
+    let rheologies = [
+        Rheology::Newtonian, Rheology::PowerLaw { k: 500.0, n: 1.6 },
+        Rheology::Cross { nu_0: 1.0, nu_inf: 2.0, lambda: 3.0, n: 4.0 },
+        Rheology::Carreau { nu_0: 5.0, nu_inf: 6.0, lambda: 7.0, n: 8.0 },
+        Rheology::Bingham { nu_plastic: 9.0, yield_stress: 10.0 },
+        Rheology::DruckerPrager { friction_angle: 0.1, cohesion: 0.2 },
+    ];
+    for (phase, &rheology) in rheologies.iter().enumerate() {
+        universe.set_rheology(phase as u32, rheology);
+    }
+
+    for &viscosity in [Viscosity::Laplacian(0.5), Viscosity::Morris(30.0),
+                       Viscosity::Monaghan { alpha: 0.1, beta: 0.2 }].iter() {
+        universe.set_viscosity_model(viscosity);
+    }
+    universe.clear_colors();
+    universe.debug_single_particle();
+    universe.debug_first_force();
+
+    let recorded = universe.recording().unwrap().inputs().to_vec();
+    assert_eq!(recorded.len(), removals.len() + rheologies.len() + 3 + 3);
+
+    // Debug prints every field, and f32s as the shortest string that reads
+    // back the same
+    let text = universe.stop_recording().unwrap();
+    let recording = Recording::parse(&text).unwrap();
+    assert_eq!(recording.to_text(), text);
+    assert_eq!(format!("{:?}", recording.inputs()), format!("{:?}", recorded));
+}
+
+#[test]
+fn setters_are_recorded_once() {
+    let mut universe = recorded_universe();
+    universe.set_drucker_prager(0, 30.0, 0.0);
+    universe.set_power_law(1, 10.0, 0.5);
+    universe.set_artificial_viscosity(0.2, 0.0);
+
+    let inputs = universe.recording().unwrap().inputs();
+    assert_eq!(inputs.len(), 3);
+    assert!(!inputs.iter().any(|(_, input)| matches!(input, Input::SetRheology(..) | Input::SetViscosityModel(_))),
+            "{:?}", inputs);
+}
+
+#[test]
+fn failed_calls_are_not_recorded() {
+    let mut universe = recorded_universe();
+    assert!(universe.set_obstacle_target(3, 0.0, 0.0, 0.0).is_err());
+    assert!(universe.update(-1.0).is_err());
+    assert!(universe.despawn(&Removal::Fastest(1000)).is_err());
+    assert!(universe.add_disc_body(100.0, 100.0, 20.0, 0.0).is_err());
+    assert_eq!(universe.recording().unwrap().inputs().len(), 0);
+}
+
+#[test]
+fn body_force_functions_cant_be_recorded() {
+    let mut universe = recorded_universe();
+    let error = universe.set_body_force(|_, _| Vector2f::new(0.0, 1.0));
+    assert!(matches!(error, Err(SpherroError::CannotRecord(_))), "{:?}", error);
+
+    // The recording still describes the universe
+    universe.update(0.005).unwrap();
+    let recording = Recording::parse(&universe.stop_recording().unwrap()).unwrap();
+    let mut replayed = recording.universe().unwrap();
+    Replayer::new(recording).finish(&mut replayed).unwrap();
+    assert_eq!(replayed.get_particles()[0].pos, universe.get_particles()[0].pos);
+
+    assert!(universe.set_body_force(|_, _| Vector2f::new(0.0, 1.0)).is_ok());
+}
+
+#[test]
+fn only_untouched_universes_can_be_recorded() {
+    let config = Config::new(0.4, 0.8, 20, 10);
+    let mut universe = Universe::new(500.0, 500.0, &config).unwrap();
+    universe.set_gravity(0.0, -100.0);
+    assert!(universe.start_recording().is_err());
+
+    let mut universe = Universe::new(500.0, 500.0, &config).unwrap();
+    universe.set_body_force(|_, _| Vector2f::new(0.0, 1.0)).unwrap();
+    assert!(universe.start_recording().is_err());
+
+    let mut universe = Universe::from_particles(500.0, 500.0, Vec::new()).unwrap();
+    assert!(universe.start_recording().is_err());
+}
+
+#[test]
+fn bad_recordings_are_rejected() {
+    let header = "spherro-recording 1 500 500 0.4 0.8 20 10\n";
+    let line = |text: &str| match Recording::parse(text) {
+        Err(SpherroError::InvalidRecording { line, .. }) => line,
+        other => panic!("{:?}", other),
+    };
+
+    assert_eq!(line("hello\n"), 1);
+    assert_eq!(line("spherro-recording 2 500 500 0.4 0.8 20 10\n"), 1);
+    assert_eq!(line(&format!("{}0 update 0.005 2\n0 explode\n", header)), 3);
+    assert_eq!(line(&format!("{}0 update 0.005 2\n1 clear_forces\n", header)), 3);
+    assert_eq!(line(&format!("{}0 set_gravity 1\n", header)), 2);
+    assert_eq!(line(&format!("{}0 set_xsph 0.1 0.2\n", header)), 2);
+}
@@ -0,0 +1,108 @@
+extern crate spherro;
+
+use spherro::{Universe, Particle, Rheology, RheologyPreset};
+use spherro::util::Vector2f;
+use spherro::scenarios::{Scenario, Poiseuille, rest_spacing};
+
+mod common;
+use common::universe_with;
+
+
+// Particles away from the surface of the block
+fn interior(universe: &Universe) -> Vec<&Particle> {
+    universe.get_particles().iter().filter(|pi| {
+        pi.pos.x > 250.0 && pi.pos.x < 450.0 && pi.pos.y > 250.0 && pi.pos.y < 450.0
+    }).collect()
+}
+
+#[test]
+fn strain_rate_is_measured() {
+    // Simple shear has a strain rate equal to its shear rate
+    let universe = universe_with(|p| Vector2f::new(3.0 * (p.y - 350.0), 0.0));
+    for pi in interior(&universe) {
+        assert!((pi.strain_rate - 3.0).abs() < 0.15, "{}", pi.strain_rate);
+    }
+
+    // Solid rotation doesn't strain the fluid
+    let universe = universe_with(|p| 3.0 * Vector2f::new(-(p.y - 350.0), p.x - 350.0));
+    for pi in interior(&universe) {
+        assert!(pi.strain_rate.abs() < 0.15, "{}", pi.strain_rate);
+    }
+}
+
+#[test]
+fn viscosities_follow_their_laws() {
+    let max = 1e5;
+    assert_eq!(Rheology::Newtonian.viscosity(1.0, max), None);
+
+    let thinning = Rheology::PowerLaw { k: 1000.0, n: 0.5 };
+    assert!((thinning.viscosity(4.0, max).unwrap() - 500.0).abs() < 1e-2);
+    assert_eq!(thinning.viscosity(0.0, max), Some(max));
+
+    let thickening = Rheology::PowerLaw { k: 1000.0, n: 2.0 };
+    assert!(thickening.viscosity(10.0, max) > thickening.viscosity(1.0, max));
+
+    for &rheology in [Rheology::Cross { nu_0: 5000.0, nu_inf: 100.0, lambda: 1.0, n: 1.0 },
+                      Rheology::Carreau { nu_0: 5000.0, nu_inf: 100.0, lambda: 1.0, n: 0.5 }].iter() {
+        assert!((rheology.viscosity(0.0, max).unwrap() - 5000.0).abs() < 1e-2);
+        assert!((rheology.viscosity(1e8, max).unwrap() - 100.0).abs() < 1.0);
+    }
+
+    let bingham = Rheology::Bingham { nu_plastic: 100.0, yield_stress: 1000.0 };
+    assert!((bingham.viscosity(10.0, max).unwrap() - 200.0).abs() < 1e-2);
+    assert_eq!(bingham.viscosity(0.0, max), Some(max));
+}
+
+#[test]
+fn shear_thinning_flattens_channel_flow() {
+    // Velocity a fifth of the way across the channel, relative to the center
+    let shape = |n: f32| {
+        let scenario = Poiseuille::default();
+        let mut universe = scenario.build().unwrap();
+        universe.set_power_law(0, 7000.0 * 30.0f32.powf(1.0 - n), n);
+        universe.set_body_force(|_, _| Vector2f::new(2000.0, 0.0)).unwrap();
+        while universe.get_time() < scenario.duration {
+            universe.update(scenario.dt).unwrap();
+        }
+
+        let profile = scenario.profile(&universe, &[0.2, 0.5]);
+        profile[0] / profile[1]
+    };
+
+    // The plates pin the fluid next to them, which makes the differences
+    // much smaller than the analytic ones(0.78, 0.64 and 0.56)
+    let (thinning, newtonian, thickening) = (shape(0.5), shape(1.0), shape(1.6));
+    assert!(thinning > newtonian + 0.005 && newtonian > thickening + 0.01,
+            "{} {} {}", thinning, newtonian, thickening);
+}
+
+#[test]
+fn phases_have_their_own_rheology() {
+    // Two columns far apart, water on the left and ketchup on the right
+    let mut particles = Vec::new();
+    for &x in [400.0, 1500.0].iter() {
+        for j in 0..12 {
+            for i in 0..8 {
+                let pos = Vector2f::new(x + (i as f32 + 0.5) * rest_spacing(), (j as f32 + 0.5) * rest_spacing());
+                particles.push(Particle::new(particles.len() as u32, pos, Universe::default_particle_mass()));
+            }
+        }
+    }
+
+    let mut universe = Universe::from_particles(2000.0, 500.0, particles).unwrap();
+    assert_eq!(universe.set_phase_in_rect(1400.0, 0.0, 2000.0, 500.0, 1), 8 * 12);
+    universe.set_rheology_preset(1, RheologyPreset::Ketchup);
+    assert_eq!(universe.get_rheology(0), Rheology::Newtonian);
+
+    for _ in 0..150 {
+        universe.update(0.002).unwrap();
+    }
+
+    let width = |phase: u32| {
+        let xs: Vec<f32> = universe.get_particles().iter().filter(|pi| pi.phase == phase).map(|pi| pi.pos.x).collect();
+        xs.iter().cloned().fold(f32::MIN, f32::max) - xs.iter().cloned().fold(f32::MAX, f32::min)
+    };
+
+    // Both started 130 wide
+    assert!(width(1) < 0.7 * width(0), "{} {}", width(0), width(1));
+}
@@ -0,0 +1,128 @@
+extern crate spherro;
+
+use spherro::{RASTER_STRIDE, SmoothingKernel};
+use spherro::util::Vector2f;
+
+mod common;
+use common::universe_with;
+
+const H: f32 = 35.0;
+
+#[test]
+fn uniform_flow_is_reproduced() {
+    let universe = universe_with(|_| Vector2f::new(120.0, -40.0));
+
+    // Including near the surface, where the kernel is only partly covered.
+    // The surface particles have been pushed out a little by the first step
+    for &(x, y) in [(350.0, 350.0), (160.0, 350.0), (350.0, 540.0)].iter() {
+        let s = universe.sample_at(Vector2f::new(x, y)).unwrap();
+        assert!((s.vx - 120.0).abs() < 0.5 && (s.vy + 40.0).abs() < 0.5, "{:?}", s);
+        assert!(s.vorticity.abs() < 0.05, "{:?}", s);
+        assert!(s.density > 0.0);
+    }
+}
+
+#[test]
+fn solid_rotation_has_twice_its_angular_velocity_as_vorticity() {
+    let center = Vector2f::new(350.0, 350.0);
+    let omega = 2.0;
+    let universe = universe_with(|p| omega * Vector2f::new(-(p.y - center.y), p.x - center.x));
+
+    for &(x, y) in [(350.0, 350.0), (300.0, 400.0), (420.0, 330.0)].iter() {
+        let pos = Vector2f::new(x, y);
+        let s = universe.sample_at(pos).unwrap();
+        let expected = omega * Vector2f::new(-(y - center.y), x - center.x);
+        assert!((s.velocity() - expected).x.hypot((s.velocity() - expected).y) < 0.02 * omega * H, "{:?}", s);
+        assert!((s.vorticity - 2.0 * omega).abs() < 0.1 * omega, "{:?}", s);
+    }
+}
+
+#[test]
+fn samples_are_weighted_with_the_solver_kernel() {
+    // Shepard weights with the Wendland C2 kernel, its normalization cancels
+    let universe = universe_with(|p| Vector2f::new(0.0, (p.x - 350.0).powi(2) / 100.0));
+    let shepard = |pos: Vector2f| {
+        let (mut weight, mut vy) = (0.0, 0.0);
+        for pj in universe.get_particles().iter() {
+            let q = (pj.pos - pos).x.hypot((pj.pos - pos).y) / H;
+            if q < 2.0 {
+                let w = (pj.mass / pj.rho) * (1.0 - q / 2.0).powi(4) * (2.0 * q + 1.0);
+                weight += w;
+                vy += w * pj.vel.y;
+            }
+        }
+        vy / weight
+    };
+
+    let mut wendland = universe_with(|p| Vector2f::new(0.0, (p.x - 350.0).powi(2) / 100.0));
+    wendland.set_smoothing_kernel(SmoothingKernel::WendlandC2);
+    for &(x, y) in [(350.0, 350.0), (170.0, 300.0), (540.0, 520.0)].iter() {
+        let pos = Vector2f::new(x, y);
+        let expected = shepard(pos);
+        let s = wendland.sample_at(pos).unwrap();
+        assert!((s.vy - expected).abs() < 1e-3 * expected.abs().max(1.0), "{} vs {}", s.vy, expected);
+
+        // The cubic spline spreads the field out differently
+        let s = universe.sample_at(pos).unwrap();
+        assert!((s.vy - expected).abs() > 5e-3 * expected.abs().max(1.0), "{} vs {}", s.vy, expected);
+
+        let raster = wendland.rasterize(x, y, x, y, 1, 1).unwrap();
+        assert!((raster[1] - expected).abs() < 1e-3 * expected.abs().max(1.0), "{} vs {}", raster[1], expected);
+    }
+}
+
+#[test]
+fn nothing_is_sampled_away_from_the_fluid() {
+    let universe = universe_with(|_| Vector2f::new(0.0, 0.0));
+    assert!(universe.sample_at(Vector2f::new(50.0, 50.0)).is_none());
+    assert!(universe.sample_point(650.0, 650.0).is_none());
+}
+
+#[test]
+fn batches_are_sampled_in_order() {
+    let universe = universe_with(|p| Vector2f::new(p.y, -p.x) * 0.5);
+    let points = [Vector2f::new(350.0, 350.0), Vector2f::new(50.0, 50.0), Vector2f::new(300.0, 420.0)];
+
+    let samples = universe.sample_many(&points);
+    assert_eq!(samples.len(), points.len());
+    assert!(samples[1].is_none());
+    for &i in [0, 2].iter() {
+        let (a, b) = (samples[i].unwrap(), universe.sample_point(points[i].x, points[i].y).unwrap());
+        assert_eq!((a.vx, a.vy, a.density, a.pressure, a.vorticity), (b.vx, b.vy, b.density, b.pressure, b.vorticity));
+    }
+    assert!(universe.sample_many(&[]).is_empty());
+}
+
+#[test]
+fn raster_matches_point_samples() {
+    let universe = universe_with(|p| Vector2f::new(p.y, -p.x) * 0.5);
+    let (cols, rows) = (8, 6);
+    let raster = universe.rasterize_with_vorticity(100.0, 100.0, 600.0, 500.0, cols, rows).unwrap();
+    assert_eq!(raster.len(), cols * rows * RASTER_STRIDE);
+
+    for y in 0..rows {
+        for x in 0..cols {
+            let pos = Vector2f::new(100.0 + 500.0 * x as f32 / 7.0, 100.0 + 400.0 * y as f32 / 5.0);
+            let node = &raster[(y * cols + x) * RASTER_STRIDE..][..RASTER_STRIDE];
+            match universe.sample_at(pos) {
+                Some(s) => {
+                    let expected = [s.vx, s.vy, s.density, s.pressure, s.vorticity];
+                    for (a, b) in node.iter().zip(expected.iter()) {
+                        assert!((a - b).abs() <= 1e-3 * b.abs().max(1e-3), "{:?} vs {:?}", node, expected);
+                    }
+                },
+                None => assert!(node.iter().all(|v| v.is_nan())),
+            }
+        }
+    }
+
+    // The corners are outside the fluid
+    assert!(raster[0].is_nan());
+
+    // Without vorticity, that column is left empty
+    let raster = universe.rasterize(100.0, 100.0, 600.0, 500.0, cols, rows).unwrap();
+    let middle = (3 * cols + 4) * RASTER_STRIDE;
+    assert!(raster[middle].is_finite() && raster[middle + 4].is_nan());
+
+    assert!(universe.rasterize(0.0, 0.0, 1.0, 1.0, 0, 4).is_err());
+}
@@ -0,0 +1,46 @@
+extern crate spherro;
+
+use spherro::scenarios::{self, Scenario, DamBreak, HydrostaticColumn, LidDrivenCavity, TaylorGreen,
+                         Poiseuille, Couette};
+
+#[test]
+fn dam_break_is_close_to_measurements() {
+    let report = DamBreak::default().run().unwrap();
+    assert_eq!(report.samples, DamBreak::MEASURED.len());
+    assert!(report.l2 < 0.15, "{}", report);
+}
+
+#[test]
+fn hydrostatic_column_is_close_to_equilibrium() {
+    let report = HydrostaticColumn::default().run().unwrap();
+    assert!(report.l2 < 0.3, "{}", report);
+    assert!(report.reference.starts_with("solver equilibrium"), "{}", report);
+}
+
+#[test]
+fn couette_is_close_to_analytic() {
+    let report = Couette::default().run().unwrap();
+    assert!(report.l2 < 0.1, "{}", report);
+}
+
+#[test]
+fn every_scenario_reports_finite_norms() {
+    // Just long enough to exercise the runners
+    let short: Vec<Box<dyn Scenario>> = vec![
+        Box::new(HydrostaticColumn{ settle_time: 0.1, ..HydrostaticColumn::default() }),
+        Box::new(LidDrivenCavity{ duration: 0.1, ..LidDrivenCavity::default() }),
+        Box::new(TaylorGreen{ duration: 0.1, ..TaylorGreen::default() }),
+        Box::new(Poiseuille{ duration: 0.1, ..Poiseuille::default() }),
+    ];
+
+    for scenario in short.iter() {
+        let report = scenario.run().unwrap();
+        assert_eq!(report.name, scenario.name());
+        assert!(report.samples > 0);
+        assert!(!report.reference.is_empty());
+        assert!(report.l1.is_finite() && report.l2.is_finite() && report.linf.is_finite(), "{}", report);
+        assert!(report.l1 <= report.l2 && report.l2 <= report.linf, "{}", report);
+    }
+
+    assert_eq!(scenarios::all().len(), 6);
+}
@@ -0,0 +1,77 @@
+extern crate spherro;
+
+use spherro::{Universe, Config, SmoothingKernel, PressureSolver};
+
+fn column<F>(setup: F) -> Universe where F: Fn(&mut Universe) {
+    let config = Config::new(0.4, 0.8, 30, 20);
+    let mut universe = Universe::new(600.0, 600.0, &config).unwrap();
+    setup(&mut universe);
+    universe
+}
+
+// Average over the second half of `steps` of the pressure iterations and the
+// average density error
+fn settle(universe: &mut Universe, steps: usize) -> (f32, f32) {
+    let (mut iterations, mut error) = (0, 0.0);
+    for i in 0..steps {
+        universe.update(0.005).unwrap();
+        if i >= steps / 2 {
+            let stats = universe.get_stats();
+            iterations += stats.pressure_iterations;
+            error += stats.density_error_avg;
+        }
+    }
+
+    let n = (steps - steps / 2) as f32;
+    (iterations as f32 / n, error / n)
+}
+
+#[test]
+fn more_iterations_compress_less() {
+    let (_, error4) = settle(&mut column(|_| ()), 400);
+    let (iterations, error8) = settle(&mut column(|u| u.set_pressure_iterations(8).unwrap()), 400);
+
+    assert_eq!(iterations, 8.0);
+    assert!(error8 < error4 - 0.05, "{} {}", error4, error8);
+}
+
+#[test]
+fn adaptive_solver_stops_at_the_tolerance() {
+    let adaptive = |tolerance: f32| column(|u| {
+        u.set_pressure_solver(PressureSolver::Adaptive);
+        u.set_pressure_iterations(8).unwrap();
+        u.set_pressure_tolerance(tolerance).unwrap();
+    });
+
+    // Nothing is ever compressed this little, so it always takes the limit
+    let (iterations, _) = settle(&mut adaptive(0.01), 400);
+    assert_eq!(iterations, 8.0);
+
+    // Looser than this, a single iteration per step lets the column blow up
+    let (iterations, error) = settle(&mut adaptive(0.25), 400);
+    assert!(iterations < 7.0, "{}", iterations);
+    assert!((error - 0.25).abs() < 0.05, "{}", error);
+}
+
+#[test]
+fn wendland_kernel_settles_like_the_cubic_spline() {
+    let mut cubic = column(|_| ());
+    let mut wendland = column(|u| u.set_smoothing_kernel(SmoothingKernel::WendlandC2));
+    let (_, cubic_error) = settle(&mut cubic, 400);
+    let (_, wendland_error) = settle(&mut wendland, 400);
+
+    assert_eq!(wendland.get_smoothing_kernel(), SmoothingKernel::WendlandC2);
+    assert!((cubic_error - wendland_error).abs() < 0.05, "{} {}", cubic_error, wendland_error);
+    let top = |u: &Universe| u.get_particles().iter().map(|pi| pi.pos.y).fold(0.0, f32::max);
+    assert!((top(&cubic) - top(&wendland)).abs() < 30.0, "{} {}", top(&cubic), top(&wendland));
+}
+
+#[test]
+fn bad_solver_settings_are_rejected() {
+    let mut universe = column(|_| ());
+    assert!(universe.set_pressure_iterations(0).is_err());
+    assert!(universe.set_pressure_tolerance(0.0).is_err());
+    assert!(universe.set_pressure_tolerance(f32::NAN).is_err());
+    assert_eq!(universe.get_pressure_iterations(), 4);
+    assert_eq!(universe.get_pressure_tolerance(), 0.25);
+}
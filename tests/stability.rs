@@ -0,0 +1,113 @@
+extern crate spherro;
+
+use spherro::{Universe, StabilityCheck, Recovery, SpherroError};
+use spherro::util::Vector2f;
+use spherro::scenarios::rest_spacing;
+
+mod common;
+use common::moving_block;
+
+// A lattice drifting at `speed` through a box that is periodic on both
+// axes. With H = 35 it crosses the smoothing radius in a step of 35 / speed
+fn drifting(speed: f32) -> Universe {
+    let n = 16;
+    let size = n as f32 * rest_spacing();
+    let particles = moving_block(0.0, 0.0, n, n, |_| Vector2f::new(speed, 0.0));
+    let mut universe = Universe::from_particles(size, size, particles).unwrap();
+    universe.set_periodic(true, true);
+    universe.set_gravity(0.0, 0.0);
+    universe.set_viscosity(0.0);
+    universe
+}
+
+fn recoveries(universe: &Universe) -> Vec<Recovery> {
+    universe.get_trips().iter().map(|t| t.recovery).collect()
+}
+
+#[test]
+fn monitor_is_off_by_default() {
+    let mut universe = drifting(1000.0);
+    universe.update(0.05).unwrap();
+    assert_eq!(universe.get_stats().substeps, 1);
+    assert!(universe.get_trips().is_empty());
+}
+
+#[test]
+fn fast_steps_are_split() {
+    // A CFL number of 1.4 at dt = 0.05, 0.7 after halving it
+    let mut universe = drifting(1000.0);
+    universe.set_stability_monitor(true);
+    universe.update(0.05).unwrap();
+
+    assert_eq!(universe.get_stats().substeps, 2);
+    assert_eq!(recoveries(&universe), vec![Recovery::Substep]);
+    let trip = universe.get_trips()[0];
+    assert_eq!(trip.check, StabilityCheck::Speed);
+    assert_eq!((trip.time, trip.dt), (0.0, 0.05));
+    assert!((universe.get_time() - 0.05).abs() < 1e-6);
+
+    // The rolled back attempt leaves no trace: the result is the same as
+    // taking the two half steps directly
+    let mut halves = drifting(1000.0);
+    halves.update(0.025).unwrap();
+    halves.update(0.025).unwrap();
+    for (a, b) in universe.get_particles().iter().zip(halves.get_particles().iter()) {
+        assert_eq!((a.pos, a.vel), (b.pos, b.vel));
+    }
+}
+
+#[test]
+fn damping_is_the_last_resort() {
+    // Without retries the step goes straight to damping, which halves the
+    // speed and brings it back under the limit
+    let mut universe = drifting(1000.0);
+    universe.set_stability_monitor(true);
+    universe.set_stability_recovery(0, 0.5);
+    universe.update(0.05).unwrap();
+
+    assert_eq!(universe.get_stats().substeps, 1);
+    assert_eq!(recoveries(&universe), vec![Recovery::Damp]);
+    for pi in universe.get_particles().iter() {
+        assert!((pi.vel.x - 500.0).abs() < 1.0, "{:?}", pi.vel);
+    }
+}
+
+#[test]
+fn steps_that_cant_be_saved_are_rolled_back() {
+    let mut universe = drifting(100.0);
+    universe.set_stability_monitor(true);
+    universe.update(0.01).unwrap();
+    let before: Vec<_> = universe.get_particles().iter().map(|pi| (pi.pos, pi.vel)).collect();
+
+    universe.set_body_force(|_, _| Vector2f::new(f32::NAN, 0.0)).unwrap();
+    match universe.update(0.01) {
+        Err(SpherroError::Unstable { .. }) => (),
+        other => panic!("{:?}", other),
+    }
+
+    assert_eq!(recoveries(&universe), vec![
+        Recovery::Substep, Recovery::Substep, Recovery::Substep, Recovery::Damp, Recovery::Failed,
+    ]);
+    assert!(universe.get_trips().iter().all(|t| t.check == StabilityCheck::NonFinite));
+    assert!((universe.get_time() - 0.01).abs() < 1e-6);
+    let after: Vec<_> = universe.get_particles().iter().map(|pi| (pi.pos, pi.vel)).collect();
+    assert_eq!(before, after);
+}
+
+#[test]
+fn old_trips_are_dropped() {
+    let mut universe = drifting(1000.0);
+    universe.set_stability_monitor(true);
+    for _ in 0..70 {
+        universe.update(0.05).unwrap();
+    }
+
+    // At least one trip per update, and only the last 64 are kept, oldest first
+    let trips: Vec<f32> = universe.get_trips().iter().map(|t| t.time).collect();
+    assert_eq!(trips.len(), 64);
+    assert!(trips.windows(2).all(|w| w[0] <= w[1]));
+    assert!((trips[63] - 69.0 * 0.05).abs() < 1e-3, "{}", trips[63]);
+
+    assert_eq!(universe.pop_trip().unwrap().time, trips[0]);
+    assert_eq!(universe.get_trips().len(), 63);
+}
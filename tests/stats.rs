@@ -0,0 +1,54 @@
+extern crate spherro;
+
+use spherro::{Universe, Config, StepStats};
+
+// The fields of a flat JSON object, in order, with their raw values
+fn fields(json: &str) -> Vec<(String, String)> {
+    assert!(json.starts_with('{') && json.ends_with('}'), "{}", json);
+    assert!(!json.contains('\n'), "{}", json);
+    json[1..json.len() - 1].split(',').map(|field| {
+        let (key, value) = field.split_once(':').unwrap();
+        assert!(key.starts_with('"') && key.ends_with('"'), "{}", key);
+        (key.trim_matches('"').to_string(), value.to_string())
+    }).collect()
+}
+
+#[test]
+fn json_has_every_field_once() {
+    let config = Config::new(0.4, 0.8, 30, 20);
+    let mut universe = Universe::new(600.0, 600.0, &config).unwrap();
+    universe.update(0.005).unwrap();
+    let stats = universe.get_stats();
+
+    let fields = fields(&stats.to_json());
+    assert_eq!(fields.len(), 23);
+    assert_eq!(fields[0].0, "time");
+    assert_eq!(fields[22].0, "total_ms");
+    for (i, (key, value)) in fields.iter().enumerate() {
+        assert!(fields[..i].iter().all(|(k, _)| k != key), "{} twice", key);
+        let expected = stats.get(key).unwrap_or_else(|| panic!("no field {}", key));
+        let value: f32 = value.parse().unwrap_or_else(|_| panic!("{}: {}", key, value));
+        assert_eq!(value, expected, "{}", key);
+    }
+    assert_eq!(stats.get("particles"), Some(600.0));
+    assert_eq!(stats.get("nonsense"), None);
+}
+
+#[test]
+fn json_writes_nan_and_infinity_as_null() {
+    let stats = StepStats {
+        max_speed: f32::NAN,
+        kinetic_energy: f32::INFINITY,
+        potential_energy: -1.5,
+        ..StepStats::default()
+    };
+
+    let json = stats.to_json();
+    let fields = fields(&json);
+    let value = |name: &str| fields.iter().find(|(k, _)| k == name).unwrap().1.clone();
+    assert_eq!(value("max_speed"), "null");
+    assert_eq!(value("kinetic_energy"), "null");
+    assert_eq!(value("potential_energy"), "-1.5");
+    assert_eq!(value("substeps"), "0");
+    assert!(!json.contains("NaN") && !json.contains("inf"), "{}", json);
+}
@@ -0,0 +1,95 @@
+extern crate spherro;
+
+use spherro::{Universe, SurfaceClassifier};
+use spherro::scenarios::rest_spacing;
+
+mod common;
+use common::block;
+
+const CLASSIFIERS: [SurfaceClassifier; 3] = [
+    SurfaceClassifier::ColorField, SurfaceClassifier::NeighbourCount, SurfaceClassifier::Covariance,
+];
+
+// An n by n block with its lower left corner at (x0, y0) in a 700x700 tank,
+// classified after a tiny step with a smoothing length of h. Returns the
+// surface flags by lattice index
+fn classify(classifier: SurfaceClassifier, x0: f32, y0: f32, n: usize, h: f32) -> (Universe, Vec<Vec<bool>>) {
+    let mut universe = Universe::from_particles(700.0, 700.0, block(x0, y0, n, n)).unwrap();
+    universe.set_gravity(0.0, 0.0);
+    universe.set_smoothing_length(h).unwrap();
+    universe.set_surface_classifier(classifier);
+    universe.update(1e-6).unwrap();
+
+    let mut flags = vec![vec![false; n]; n];
+    for pi in universe.get_particles().iter() {
+        let i = ((pi.pos.x - x0) / rest_spacing()) as usize;
+        let j = ((pi.pos.y - y0) / rest_spacing()) as usize;
+        flags[j][i] = pi.is_surface;
+    }
+
+    (universe, flags)
+}
+
+// Checks that the edges of an n by n block are flagged, and that the
+// particles at least `depth` spacings in aren't
+fn assert_outlined(classifier: SurfaceClassifier, flags: &[Vec<bool>], depth: usize, h: f32) {
+    let n = flags.len();
+    for (j, row) in flags.iter().enumerate() {
+        for (i, &flag) in row.iter().enumerate() {
+            let edge = i == 0 || j == 0 || i == n - 1 || j == n - 1;
+            let inner = i >= depth && j >= depth && i < n - depth && j < n - depth;
+            if edge {
+                assert!(flag, "{:?} at h={} missed ({}, {})", classifier, h, i, j);
+            } else if inner {
+                assert!(!flag, "{:?} at h={} flagged ({}, {})", classifier, h, i, j);
+            }
+        }
+    }
+}
+
+#[test]
+fn block_is_outlined_by_every_classifier() {
+    for &classifier in CLASSIFIERS.iter() {
+        let (_, flags) = classify(classifier, 220.0, 220.0, 16, 35.0);
+        assert_outlined(classifier, &flags, 2, 35.0);
+    }
+}
+
+#[test]
+fn classifiers_follow_the_smoothing_length() {
+    // Particles within the support radius of the surface feel it, so those
+    // are left out
+    for &h in [25.0, 50.0].iter() {
+        let depth = (2.0 * h / rest_spacing()).ceil() as usize;
+        for &classifier in CLASSIFIERS.iter() {
+            let (_, flags) = classify(classifier, 170.0, 170.0, 20, h);
+            assert_outlined(classifier, &flags, depth, h);
+        }
+    }
+}
+
+#[test]
+fn walls_are_not_free_surface() {
+    // The block sits in the lower left corner, so only its top and right
+    // sides are free
+    let n = 16;
+    for &classifier in CLASSIFIERS.iter() {
+        let (_, flags) = classify(classifier, 0.0, 0.0, n, 35.0);
+        let floor = flags[0][..n - 3].iter().any(|&flag| flag);
+        let wall = flags[..n - 3].iter().any(|row| row[0]);
+        assert!(!floor && !wall, "{:?} flagged a wall", classifier);
+
+        let top = flags[n - 1][3..].iter().all(|&flag| flag);
+        let side = flags[3..].iter().all(|row| row[n - 1]);
+        assert!(top && side, "{:?} missed the free surface", classifier);
+    }
+}
+
+#[test]
+fn surface_length_is_the_block_perimeter() {
+    let n = 16;
+    let (universe, _) = classify(SurfaceClassifier::ColorField, 220.0, 220.0, n, 35.0);
+    let perimeter = 4.0 * n as f32 * rest_spacing();
+    let length = universe.get_surface_length();
+    assert!((length - perimeter).abs() < 0.05 * perimeter, "{} {}", length, perimeter);
+}
@@ -0,0 +1,110 @@
+extern crate spherro;
+
+use spherro::Universe;
+use spherro::util::Vector2f;
+use spherro::scenarios::rest_spacing;
+
+mod common;
+use common::block;
+
+// An n by n block with its lower left corner at (x0, y0) in a 700x700 tank
+// with no gravity
+fn still_block(x0: f32, y0: f32, n: usize) -> Universe {
+    let mut universe = Universe::from_particles(700.0, 700.0, block(x0, y0, n, n)).unwrap();
+    universe.set_gravity(0.0, 0.0);
+    universe
+}
+
+fn centroid(universe: &Universe) -> Vector2f {
+    let particles = universe.get_particles();
+    particles.iter().fold(Vector2f::new(0.0, 0.0), |c, pi| c + pi.pos) / particles.len() as f32
+}
+
+// RMS distance of the particles from their centroid
+fn spread(universe: &Universe) -> f32 {
+    let c = centroid(universe);
+    let particles = universe.get_particles();
+    let sum: f32 = particles.iter().map(|pi| {
+        let d = pi.pos - c;
+        d.x * d.x + d.y * d.y
+    }).sum();
+
+    (sum / particles.len() as f32).sqrt()
+}
+
+#[test]
+fn normals_point_into_the_fluid() {
+    let (x0, n) = (220.0, 16);
+    let mut universe = still_block(x0, x0, n);
+    universe.set_surface_tension(1.0);
+    universe.update(1e-6).unwrap();
+
+    // A flat surface has a normal of about 1.5, scaled by the support radius,
+    // and the normals of the bulk cancel out
+    for pi in universe.get_particles().iter() {
+        let i = ((pi.pos.x - x0) / rest_spacing()) as usize;
+        let j = ((pi.pos.y - x0) / rest_spacing()) as usize;
+        let edge = if j == 0 && i > 4 && i < n - 5 {
+            Some(Vector2f::new(0.0, 1.0))
+        } else if i == n - 1 && j > 4 && j < n - 5 {
+            Some(Vector2f::new(-1.0, 0.0))
+        } else {
+            None
+        };
+
+        match edge {
+            Some(inward) => {
+                let along = pi.normal.x * inward.x + pi.normal.y * inward.y;
+                assert!(along > 1.2 && along < 2.0, "{:?} at {:?}", pi.normal, pi.pos);
+                assert!((pi.normal - along * inward).x.hypot((pi.normal - along * inward).y) < 0.05);
+                assert!(pi.is_surface);
+            },
+            None if i > 4 && i < n - 5 && j > 4 && j < n - 5 => {
+                assert!(pi.normal.x.hypot(pi.normal.y) < 0.01, "{:?} at {:?}", pi.normal, pi.pos);
+                assert!(!pi.is_surface);
+            },
+            None => (),
+        }
+    }
+}
+
+#[test]
+fn surface_tension_holds_a_drop_together() {
+    // Without gravity a free block spreads out. Cohesion pulls neighbours
+    // together and the curvature term evens out the surface, and between
+    // them they keep it in one piece. On their own, cohesion takes about a
+    // quarter off the spread and curvature about 55%, both together 60%
+    let run = |gamma: f32| {
+        let mut universe = still_block(250.0, 250.0, 12);
+        universe.set_surface_tension(gamma);
+        for _ in 0..300 {
+            universe.update(0.002).unwrap();
+        }
+        spread(&universe)
+    };
+
+    let plain = run(0.0);
+    let tense = run(1000.0);
+    assert!(tense < 0.42 * plain, "{} {}", plain, tense);
+}
+
+#[test]
+fn adhesion_pulls_fluid_to_close_walls() {
+    let run = |x0: f32, beta: f32| {
+        let mut universe = still_block(x0, 300.0, 6);
+        universe.set_adhesion(beta);
+        for _ in 0..150 {
+            universe.update(0.002).unwrap();
+        }
+        centroid(&universe)
+    };
+
+    // The first column starts within the support radius of the left wall
+    let plain = run(45.0, 0.0);
+    let adhesive = run(45.0, 1e10);
+    assert!(adhesive.x < plain.x - 10.0, "{:?} {:?}", plain, adhesive);
+    assert!((adhesive.y - plain.y).abs() < 1.0, "{:?} {:?}", plain, adhesive);
+
+    // Away from the walls the adhesion spline is zero
+    assert_eq!(run(300.0, 0.0), run(300.0, 1e10));
+}
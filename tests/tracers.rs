@@ -0,0 +1,166 @@
+extern crate spherro;
+
+use spherro::{Universe, Fetcher, TracerIntegrator};
+use spherro::util::Vector2f;
+use spherro::scenarios::rest_spacing;
+
+mod common;
+use common::moving_block;
+
+// A block of fluid drifting with velocity `vel` through a tank with no
+// gravity or viscosity
+fn drifting_block(vel: Vector2f) -> Universe {
+    let mut universe = Universe::from_particles(1000.0, 1000.0, moving_block(155.0, 155.0, 24, 24, |_| vel)).unwrap();
+    universe.set_gravity(0.0, 0.0);
+    universe.set_viscosity(0.0);
+    universe
+}
+
+// A lattice filling a box that is periodic on both axes, all moving with
+// velocity `vel`. With no free surface the flow stays uniform
+fn uniform_flow(vel: Vector2f) -> Universe {
+    let n = 24;
+    let size = n as f32 * rest_spacing();
+    let mut universe = Universe::from_particles(size, size, moving_block(0.0, 0.0, n, n, |_| vel)).unwrap();
+    universe.set_periodic(true, true);
+    universe.set_gravity(0.0, 0.0);
+    universe.set_viscosity(0.0);
+    universe
+}
+
+#[test]
+fn tracers_drift_with_the_fluid() {
+    let vel = Vector2f::new(200.0, 100.0);
+    for &integrator in [TracerIntegrator::Rk2, TracerIntegrator::Rk4].iter() {
+        let mut universe = uniform_flow(vel);
+        universe.set_tracer_integrator(integrator);
+        universe.add_tracer(100.0, 100.0);
+
+        let dt = 0.002;
+        for _ in 0..250 {
+            universe.update(dt).unwrap();
+        }
+
+        let tracer = &universe.get_tracers()[0];
+        let expected = Vector2f::new(100.0, 100.0) + 0.5 * vel;
+        assert!((tracer.pos.x - expected.x).abs() < 0.5 && (tracer.pos.y - expected.y).abs() < 0.5,
+                "{:?}: {:?}", integrator, tracer.pos);
+        assert!((tracer.age - 0.5).abs() < 1e-3);
+    }
+}
+
+#[test]
+fn tracers_wrap_around_periodic_axes() {
+    let mut universe = uniform_flow(Vector2f::new(400.0, 0.0));
+    universe.set_tracer_history(1000);
+    universe.add_tracer(300.0, 100.0);
+
+    for _ in 0..250 {
+        universe.update(0.002).unwrap();
+    }
+
+    // 200 along x, across the seam at 388.8
+    let tracer = &universe.get_tracers()[0];
+    assert!((tracer.pos.x - (500.0 - 16.2 * 24.0)).abs() < 1.0, "{:?}", tracer.pos);
+
+    // The path line leaves out the segment across the seam
+    let mut fetcher = Fetcher::new();
+    fetcher.fetch_pathlines(&universe);
+    assert_eq!(fetcher.pathline_count(), 250 - 1);
+}
+
+#[test]
+fn tracers_dont_affect_the_fluid() {
+    let vel = Vector2f::new(50.0, -20.0);
+    let mut plain = drifting_block(vel);
+    let mut traced = drifting_block(vel);
+    traced.add_tracer_line(200.0, 300.0, 500.0, 300.0, 20);
+    traced.add_tracer_emitter(300.0, 400.0, 0.01).unwrap();
+
+    for _ in 0..50 {
+        plain.update(0.002).unwrap();
+        traced.update(0.002).unwrap();
+    }
+
+    for (a, b) in plain.get_particles().iter().zip(traced.get_particles().iter()) {
+        assert_eq!(a.pos, b.pos);
+        assert_eq!(a.vel, b.vel);
+    }
+}
+
+#[test]
+fn history_is_bounded() {
+    let mut universe = drifting_block(Vector2f::new(100.0, 0.0));
+    universe.set_tracer_history(10);
+    universe.add_tracer(350.0, 350.0);
+    universe.add_tracer(5.0, 900.0); // outside the fluid
+
+    for _ in 0..50 {
+        universe.update(0.002).unwrap();
+    }
+
+    for tracer in universe.get_tracers().iter() {
+        assert_eq!(tracer.history.len(), 10);
+        assert_eq!(*tracer.history.back().unwrap(), tracer.pos);
+    }
+
+    // Tracers with no fluid around stay put
+    assert_eq!(universe.get_tracers()[1].pos, Vector2f::new(5.0, 900.0));
+
+    let mut fetcher = Fetcher::new();
+    fetcher.fetch_pathlines(&universe);
+    assert_eq!(fetcher.pathline_count(), 2 * 9);
+}
+
+#[test]
+fn emitters_release_periodically() {
+    let mut universe = drifting_block(Vector2f::new(100.0, 0.0));
+    assert!(universe.add_tracer_emitter(300.0, 300.0, 0.0).is_err());
+
+    // Once when added, then every 0.1s
+    let e = universe.add_tracer_line_emitter(200.0, 250.0, 200.0, 450.0, 3, 0.1).unwrap();
+    for _ in 0..250 {
+        universe.update(0.002).unwrap();
+    }
+
+    let tracers = universe.get_tracers();
+    assert!(tracers.len() == 3 * 6 || tracers.len() == 3 * 5, "{}", tracers.len());
+    assert!(tracers.iter().all(|t| t.source.map(|(i, _)| i) == Some(e)));
+
+    // Every point makes a streakline joining its tracers
+    let mut fetcher = Fetcher::new();
+    fetcher.fetch_streaklines(&universe);
+    assert_eq!(fetcher.streakline_count(), tracers.len() - 3);
+
+    universe.set_tracer_lifetime(0.25);
+    universe.update(0.002).unwrap();
+    assert!(universe.get_tracers().iter().all(|t| t.age <= 0.25));
+    assert!(universe.get_tracer_count() <= 3 * 3);
+
+    universe.clear_tracers();
+    for _ in 0..100 {
+        universe.update(0.002).unwrap();
+    }
+    assert_eq!(universe.get_tracer_count(), 0);
+}
+
+#[test]
+fn short_periods_release_a_bounded_number() {
+    let mut universe = Universe::from_particles(500.0, 500.0, Vec::new()).unwrap();
+    universe.update(2.0).unwrap();
+
+    // Added to 2 in f32, this period would leave the time unchanged
+    assert!(universe.add_tracer_emitter(300.0, 300.0, 1e-8).is_err());
+
+    universe.add_tracer_emitter(300.0, 300.0, 1e-3).unwrap();
+    assert_eq!(universe.get_tracer_count(), 1);
+    universe.update(0.005).unwrap();
+    assert_eq!(universe.get_tracer_count(), 1 + 5);
+
+    // A long step releases at most a handful at once
+    universe.update(1.0).unwrap();
+    assert!(universe.get_tracer_count() <= 1 + 5 + 16, "{}", universe.get_tracer_count());
+    universe.update(0.005).unwrap();
+    let count = universe.get_tracer_count();
+    assert!((1 + 5 + 4..=1 + 5 + 16 + 6).contains(&count), "{}", count);
+}
@@ -0,0 +1,92 @@
+extern crate spherro;
+
+use spherro::{Universe, Viscosity, ViscosityModel};
+use spherro::scenarios::{Scenario, Poiseuille};
+
+// Heights across the channel, relative to it. The ones right next to the
+// plates are left out, since the kernel reaches into the plates there
+const HEIGHTS: [f32; 7] = [0.2, 0.3, 0.4, 0.5, 0.6, 0.7, 0.8];
+
+// Kinematic viscosity every model is set up to give, in real units
+const NU: f32 = 7000.0;
+
+// Runs the Poiseuille flow with `viscosity` and `xsph`, and returns the
+// simulated and analytic profiles across the channel
+fn poiseuille(viscosity: Viscosity, xsph: f32) -> (Vec<f32>, Vec<f32>) {
+    let scenario = Poiseuille { viscosity, ..Poiseuille::default() };
+    let mut universe = scenario.build().unwrap();
+    universe.set_xsph(xsph);
+    while universe.get_time() < scenario.duration {
+        universe.update(scenario.dt).unwrap();
+    }
+
+    let (nu, t) = (universe.kinematic_viscosity(), universe.get_time());
+    let simulated = scenario.profile(&universe, &HEIGHTS);
+    let analytic = HEIGHTS.iter().map(|&y| scenario.velocity(y * scenario.height(), nu, t)).collect();
+    (simulated, analytic)
+}
+
+// Checks that the profile is the analytic parabola in shape, to within
+// `tolerance` of the center speed, and that the flow is about as fast. The
+// plates pin the fluid next to them, which slows the whole channel
+// down(see `Poiseuille`), so the speed is only checked loosely
+fn assert_parabolic(simulated: &[f32], analytic: &[f32], tolerance: f32, min_speed: f32) {
+    let center = HEIGHTS.len() / 2;
+    let ratio = simulated[center] / analytic[center];
+    assert!(ratio > min_speed && ratio < 1.2, "{:?} {:?}", simulated, analytic);
+
+    for (u, expected) in simulated.iter().zip(analytic.iter()) {
+        let error = u / simulated[center] - expected / analytic[center];
+        assert!(error.abs() < tolerance, "{:?} {:?}", simulated, analytic);
+    }
+}
+
+#[test]
+fn laplacian_gives_poiseuille_flow() {
+    let (simulated, analytic) = poiseuille(Viscosity::Laplacian(NU / 35.0), 0.0);
+    assert_parabolic(&simulated, &analytic, 0.05, 0.4);
+}
+
+#[test]
+fn morris_gives_poiseuille_flow() {
+    let (simulated, analytic) = poiseuille(Viscosity::Morris(NU), 0.0);
+    assert_parabolic(&simulated, &analytic, 0.05, 0.4);
+}
+
+#[test]
+fn monaghan_gives_poiseuille_flow() {
+    let alpha = NU * 16.0 / (Universe::default_sound_speed() * 35.0);
+    let (simulated, analytic) = poiseuille(Viscosity::Monaghan { alpha, beta: 0.0 }, 0.0);
+
+    // Particles sliding past each other near the plates don't feel it, which
+    // flattens the profile a little
+    assert_parabolic(&simulated, &analytic, 0.08, 0.4);
+}
+
+#[test]
+fn xsph_keeps_poiseuille_flow_parabolic() {
+    // XSPH smooths on top of the viscosity, so the flow is slower than the
+    // viscosity alone would give, but keeps its shape
+    let (plain, _) = poiseuille(Viscosity::Morris(NU), 0.0);
+    let (simulated, analytic) = poiseuille(Viscosity::Morris(NU), 0.05);
+    assert_parabolic(&simulated, &analytic, 0.05, 0.1);
+    assert!(simulated[3] < plain[3]);
+}
+
+#[test]
+fn kinematic_viscosity_is_in_real_units() {
+    let mut universe = Universe::from_particles(100.0, 100.0, Vec::new()).unwrap();
+    for &(model, set) in [
+        (ViscosityModel::Laplacian, Universe::set_kinematic_viscosity as fn(&mut Universe, f32)),
+        (ViscosityModel::Morris, Universe::set_laminar_viscosity),
+    ].iter() {
+        set(&mut universe, NU);
+        assert_eq!(universe.get_viscosity_model(), model);
+        assert!((universe.kinematic_viscosity() - NU).abs() < 1e-2);
+        assert!((universe.get_viscosity() - NU / 35.0).abs() < 1e-3);
+    }
+
+    universe.set_artificial_viscosity(0.1, 0.2);
+    assert_eq!(universe.get_viscosity_model(), ViscosityModel::Monaghan);
+    assert!((universe.kinematic_viscosity() - 0.1 * Universe::default_sound_speed() * 35.0 / 16.0).abs() < 1e-2);
+}
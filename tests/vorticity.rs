@@ -0,0 +1,82 @@
+extern crate spherro;
+
+use std::f32::consts::PI;
+use spherro::Universe;
+use spherro::util::Vector2f;
+use spherro::scenarios::rest_spacing;
+
+mod common;
+use common::moving_block;
+
+// A lattice of n x n particles filling a box that is periodic on both axes,
+// with no gravity, and velocities given by `vel`
+fn periodic_box<F>(n: usize, vel: F) -> Universe where F: Fn(Vector2f, f32) -> Vector2f {
+    let size = n as f32 * rest_spacing();
+    let mut universe = Universe::from_particles(size, size, moving_block(0.0, 0.0, n, n, |pos| vel(pos, size))).unwrap();
+    universe.set_periodic(true, true);
+    universe.set_gravity(0.0, 0.0);
+    universe
+}
+
+// Taylor-Green vortices, one period across the box
+fn taylor_green(pos: Vector2f, size: f32) -> Vector2f {
+    let k = 2.0 * PI / size;
+    100.0 * Vector2f::new((k * pos.x).sin() * (k * pos.y).cos(), -(k * pos.x).cos() * (k * pos.y).sin())
+}
+
+// Projection of the particles' vorticity onto the Taylor-Green mode
+fn mode_amplitude(universe: &Universe, size: f32) -> f32 {
+    let k = 2.0 * PI / size;
+    universe.get_particles().iter().map(|pi| {
+        pi.vorticity * (k * pi.pos.x).sin() * (k * pi.pos.y).sin()
+    }).sum()
+}
+
+#[test]
+fn vorticity_matches_the_velocity_curl() {
+    let n = 32;
+    let mut universe = periodic_box(n, taylor_green);
+    universe.update(1e-6).unwrap();
+
+    // The curl of the Taylor-Green field is 2k * 100 * sin(kx) sin(ky)
+    let size = n as f32 * rest_spacing();
+    let k = 2.0 * PI / size;
+    let mut max_error: f32 = 0.0;
+    for pi in universe.get_particles().iter() {
+        let expected = 2.0 * k * 100.0 * (k * pi.pos.x).sin() * (k * pi.pos.y).sin();
+        max_error = max_error.max((pi.vorticity - expected).abs());
+    }
+
+    assert!(max_error < 0.05 * 2.0 * k * 100.0, "{}", max_error);
+}
+
+#[test]
+fn uniform_flow_has_no_vorticity() {
+    let mut universe = periodic_box(16, |_, _| Vector2f::new(80.0, -30.0));
+    universe.update(1e-6).unwrap();
+
+    assert!(universe.get_particles().iter().all(|pi| pi.vorticity.abs() < 1e-3));
+}
+
+#[test]
+fn confinement_keeps_vortices_spinning() {
+    let n = 24;
+    let size = n as f32 * rest_spacing();
+    let remaining = |strength: f32| {
+        let mut universe = periodic_box(n, taylor_green);
+        universe.set_vorticity_confinement(strength);
+        universe.update(1e-6).unwrap();
+        let start = mode_amplitude(&universe, size);
+
+        for _ in 0..150 {
+            universe.update(0.002).unwrap();
+        }
+        mode_amplitude(&universe, size) / start
+    };
+
+    // The vortices are mostly damped out without it
+    let plain = remaining(0.0);
+    let confined = remaining(100.0);
+    assert!(plain < 0.3, "{}", plain);
+    assert!(confined > 2.0 * plain && confined < 1.2, "{} {}", plain, confined);
+}
@@ -17,6 +17,8 @@ const HEIGHT = 700;
 
 const config = Config.new(0.4, 0.8, 50, 10);
 var universe = Universe.new(WIDTH, HEIGHT, config);
+universe.start_recording();
+universe.set_stability_monitor(true);
 
 const canvas = document.getElementById('spherro-canvas');
 const fpsCounter = new FPSCounter(20);
@@ -26,6 +28,7 @@ const app = new Vue({
     el: '.controls',
     data: {
         fps: 60.0,
+        stats: null,
         isStable: true,
         desiredParticleCount: 500,
         particleCount: 500,
@@ -38,9 +41,6 @@ const app = new Vue({
     methods: {
         onclickReset(event) {
             this.shouldReset = true;
-            if(!this.isStable) {
-                location.reload();
-            }
         }
     }
 });
@@ -55,14 +55,35 @@ const renderLoop = (currentTime) => {
     renderer.draw(universe, currentTime);
 
     for(var i=0; i<2; i++) {
-        universe.update(0.005);
+        try {
+            universe.update(0.005);
+        } catch(e) {
+            if(e.name !== 'NotEnoughParticles') {
+                // The universe can't recover, wait for a reset
+                console.error(e);
+                app.isStable = false;
+                break;
+            }
+
+            // Only the despawn was dropped, the step itself went through
+            console.warn(e.message);
+        }
         if(universe.is_unstable()) {
             app.isStable = false;
         }
+
+        // Steps the stability monitor had to roll back
+        for(var trip = universe.pop_trip(); trip !== undefined; trip = universe.pop_trip()) {
+            console.warn(`Step at t=${trip.time} rolled back: check ${trip.check} on particle ${trip.id}, ` +
+                         `${trip.value} > ${trip.threshold}, recovery ${trip.recovery}`);
+            trip.free();
+        }
     }
 
     if(app.shouldReset) {
         universe = Universe.new(WIDTH, HEIGHT, config);
+        universe.start_recording();
+        universe.set_stability_monitor(true);
 
         app.desiredParticleCount = 500;
         app.isStable = true;
@@ -90,6 +111,10 @@ const renderLoop = (currentTime) => {
     if(nFrames % 20 === 0) {
         const fps = fpsCounter.smoothFPS();
         app.fps = fps.toFixed(1);
+
+        const stats = universe.get_stats();
+        app.stats = JSON.parse(stats.to_json());
+        stats.free();
     }
 
     nFrames += 1;
@@ -97,6 +122,21 @@ const renderLoop = (currentTime) => {
 };
 requestAnimationFrame(renderLoop);
 
+// Saves everything done since the last reset, to be replayed with the
+// `replay` binary
+function saveRecording() {
+    const text = universe.get_recording();
+    if(text === undefined) {
+        return;
+    }
+
+    const link = document.createElement('a');
+    link.href = URL.createObjectURL(new Blob([text], {type: 'text/plain'}));
+    link.download = `spherro-${universe.get_time().toFixed(2)}.rec`;
+    link.click();
+    URL.revokeObjectURL(link.href);
+}
+
 document.addEventListener('keypress', function(e) {
     if(e.key === 'r') {
         app.shouldReset = true;
@@ -104,6 +144,8 @@ document.addEventListener('keypress', function(e) {
         app.desiredParticleCount = Math.min(app.maxParticles, app.desiredParticleCount+5);
     } else if (e.key === 'o') {
         app.desiredParticleCount = Math.max(app.minParticles, app.desiredParticleCount-5);
+    } else if (e.key === 'l') {
+        saveRecording();
     }
 })
 